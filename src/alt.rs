@@ -0,0 +1,197 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use anyhow::{Result, anyhow};
+use log::debug;
+use crate::transaction;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Expands the address lookup tables of v0 transactions into pubkeys.
+///
+/// Tables only ever grow, so a cached table is reused until a transaction
+/// references an index past its end, at which point it is fetched again.
+pub struct AltResolver {
+    rpc_client: RpcClient,
+    tables: Mutex<HashMap<Pubkey, Vec<Pubkey>>>,
+}
+
+impl AltResolver {
+    /// Tables are read at the same commitment as the transactions using
+    /// them, so one extended a few slots earlier is already visible.
+    pub fn new(rpc_endpoint: String, commitment: CommitmentConfig) -> Self {
+        AltResolver {
+            rpc_client: RpcClient::new_with_commitment(rpc_endpoint, transaction::read_commitment(commitment)),
+            tables: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Addresses loaded by `lookups`, writable ones first, in the order the
+    /// runtime appends them to the static account keys.
+    pub async fn resolve(&self, lookups: &[MessageAddressTableLookup]) -> Result<LoadedAddresses> {
+        let stale: Vec<Pubkey> = {
+            let tables = self.tables.lock().unwrap();
+            lookups.iter()
+                .filter(|lookup| {
+                    let needed = lookup.writable_indexes.iter()
+                        .chain(lookup.readonly_indexes.iter())
+                        .max()
+                        .map(|index| *index as usize + 1)
+                        .unwrap_or(0);
+                    tables.get(&lookup.account_key)
+                        .map(|addresses| addresses.len() < needed)
+                        .unwrap_or(true)
+                })
+                .map(|lookup| lookup.account_key)
+                .collect()
+        };
+
+        if !stale.is_empty() {
+            debug!("Fetching {} address lookup tables", stale.len());
+            let accounts = self.rpc_client.get_multiple_accounts(&stale).await?;
+            let mut tables = self.tables.lock().unwrap();
+            for (key, account) in stale.iter().zip(accounts) {
+                let account = account.ok_or_else(|| anyhow!("Address lookup table {} not found", key))?;
+                let table = AddressLookupTable::deserialize(&account.data)
+                    .map_err(|e| anyhow!("Invalid address lookup table {}: {}", key, e))?;
+                tables.insert(*key, table.addresses.to_vec());
+            }
+        }
+
+        let tables = self.tables.lock().unwrap();
+        let mut loaded = LoadedAddresses::default();
+        for lookup in lookups {
+            pick(&tables, lookup, &lookup.writable_indexes, &mut loaded.writable)?;
+        }
+        for lookup in lookups {
+            pick(&tables, lookup, &lookup.readonly_indexes, &mut loaded.readonly)?;
+        }
+        Ok(loaded)
+    }
+}
+
+fn pick(
+    tables: &HashMap<Pubkey, Vec<Pubkey>>,
+    lookup: &MessageAddressTableLookup,
+    indexes: &[u8],
+    out: &mut Vec<Pubkey>,
+) -> Result<()> {
+    let addresses = tables.get(&lookup.account_key)
+        .ok_or_else(|| anyhow!("Address lookup table {} not loaded", lookup.account_key))?;
+    for index in indexes {
+        let address = addresses.get(*index as usize)
+            .ok_or_else(|| anyhow!("Index {} out of range for lookup table {}", index, lookup.account_key))?;
+        out.push(*address);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{MessageHeader, VersionedMessage, v0};
+    use solana_sdk::signature::Signature;
+    use solana_sdk::transaction::VersionedTransaction;
+    use solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, TransactionStatusMeta, UiTransactionEncoding,
+        VersionedTransactionWithStatusMeta,
+    };
+    use crate::fixtures;
+    use crate::transaction::ParsedTransaction;
+
+    /// A resolver holding `tables`, whose RPC endpoint refuses connections
+    /// so any fetch fails.
+    fn resolver(tables: Vec<(Pubkey, Vec<Pubkey>)>) -> AltResolver {
+        AltResolver {
+            rpc_client: RpcClient::new("http://127.0.0.1:9".to_string()),
+            tables: Mutex::new(tables.into_iter().collect()),
+        }
+    }
+
+    fn table(len: usize) -> (Pubkey, Vec<Pubkey>) {
+        (Pubkey::new_unique(), (0..len).map(|_| Pubkey::new_unique()).collect())
+    }
+
+    fn lookup(table: &(Pubkey, Vec<Pubkey>), writable_indexes: Vec<u8>, readonly_indexes: Vec<u8>) -> MessageAddressTableLookup {
+        MessageAddressTableLookup { account_key: table.0, writable_indexes, readonly_indexes }
+    }
+
+    /// A v0 transaction calling `program` with the signer and the first
+    /// three loaded addresses, as `getTransaction` returns it with Base64
+    /// encoding. `loaded` is what the status meta lists.
+    fn v0_transaction(signer: Pubkey, program: Pubkey, lookups: Vec<MessageAddressTableLookup>, loaded: LoadedAddresses) -> EncodedConfirmedTransactionWithStatusMeta {
+        let message = v0::Message {
+            header: MessageHeader { num_required_signatures: 1, num_readonly_signed_accounts: 0, num_readonly_unsigned_accounts: 1 },
+            account_keys: vec![signer, program],
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(1, vec![9], vec![0, 2, 3, 4])],
+            address_table_lookups: lookups,
+        };
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::V0(message),
+        };
+        let meta = TransactionStatusMeta {
+            fee: 5_000,
+            pre_balances: vec![0; 5],
+            post_balances: vec![0; 5],
+            log_messages: Some(Vec::new()),
+            loaded_addresses: loaded,
+            ..TransactionStatusMeta::default()
+        };
+        let encoded = VersionedTransactionWithStatusMeta { transaction, meta }
+            .encode(UiTransactionEncoding::Base64, Some(0), false)
+            .unwrap();
+        EncodedConfirmedTransactionWithStatusMeta { slot: 250_000_000, transaction: encoded, block_time: None }
+    }
+
+    #[tokio::test]
+    async fn lists_writable_addresses_of_every_table_first() {
+        let (a, b) = (table(4), table(4));
+        let resolver = resolver(vec![a.clone(), b.clone()]);
+        let loaded = resolver.resolve(&[lookup(&a, vec![2], vec![0]), lookup(&b, vec![1], vec![3])]).await.unwrap();
+        assert_eq!(loaded.writable, vec![a.1[2], b.1[1]]);
+        assert_eq!(loaded.readonly, vec![a.1[0], b.1[3]]);
+    }
+
+    #[tokio::test]
+    async fn refetches_a_table_shorter_than_an_index() {
+        let a = table(2);
+        let resolver = resolver(vec![a.clone()]);
+        assert!(resolver.resolve(&[lookup(&a, vec![1], vec![])]).await.is_ok());
+        // Index 2 needs a table that has grown since it was cached, so it is
+        // fetched rather than reported out of range. The fetch fails here.
+        let error = resolver.resolve(&[lookup(&a, vec![2], vec![])]).await.unwrap_err();
+        assert!(!error.to_string().contains("out of range"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn expands_lookups_after_the_static_keys() {
+        let (signer, program) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (a, b) = (table(3), table(2));
+        let lookups = vec![lookup(&a, vec![2], vec![]), lookup(&b, vec![0], vec![1])];
+        let encoded = v0_transaction(signer, program, lookups, LoadedAddresses::default());
+
+        let transaction = ParsedTransaction::from_encoded(fixtures::SIGNATURE, encoded, &resolver(vec![a.clone(), b.clone()])).await.unwrap();
+        assert_eq!(transaction.account_keys, vec![signer, program, a.1[2], b.1[0], b.1[1]]);
+        let instruction = &transaction.instructions[0];
+        assert_eq!(instruction.program_id, program);
+        assert_eq!(instruction.accounts, vec![signer, a.1[2], b.1[0], b.1[1]]);
+    }
+
+    #[tokio::test]
+    async fn prefers_the_addresses_in_the_status_meta() {
+        let (signer, program) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let a = table(3);
+        let loaded = LoadedAddresses { writable: vec![a.1[0], a.1[1]], readonly: vec![a.1[2]] };
+        let encoded = v0_transaction(signer, program, vec![lookup(&a, vec![0, 1], vec![2])], loaded);
+
+        // The resolver knows no tables and cannot fetch any.
+        let transaction = ParsedTransaction::from_encoded(fixtures::SIGNATURE, encoded, &resolver(Vec::new())).await.unwrap();
+        assert_eq!(transaction.instructions[0].accounts, vec![signer, a.1[0], a.1[1], a.1[2]]);
+    }
+}
@@ -0,0 +1,71 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// Exponential backoff with random jitter for reconnect loops.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt: `initial * 2^attempt`, capped at `max`,
+    /// plus up to half of that again so reconnecting clients spread out.
+    pub fn next_delay(&mut self) -> Duration {
+        let exponent = self.attempt.min(16);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let base = self.initial.saturating_mul(1 << exponent).min(self.max);
+        let jitter_ms = rand::thread_rng().gen_range(0..=base.as_millis() as u64 / 2);
+        base + Duration::from_millis(jitter_ms)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `delay` lies within `base` plus up to half of it again.
+    fn jittered(delay: Duration, base: Duration) -> bool {
+        delay >= base && delay <= base + base / 2
+    }
+
+    #[test]
+    fn doubles_up_to_the_cap_with_jitter() {
+        let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+        for base_ms in [500, 1_000, 2_000, 4_000, 8_000, 16_000, 30_000, 30_000, 30_000] {
+            let delay = backoff.next_delay();
+            assert!(jittered(delay, Duration::from_millis(base_ms)), "{:?} for base {}ms", delay, base_ms);
+        }
+    }
+
+    #[test]
+    fn jitter_spreads_delays() {
+        let delays: std::collections::HashSet<Duration> = (0..50)
+            .map(|_| Backoff::new(Duration::from_secs(1), Duration::from_secs(30)).next_delay())
+            .collect();
+        assert!(delays.len() > 1);
+    }
+
+    #[test]
+    fn reset_starts_over_and_many_attempts_stay_capped() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_secs(90));
+        }
+        backoff.reset();
+        assert!(jittered(backoff.next_delay(), Duration::from_secs(1)));
+    }
+}
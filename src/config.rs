@@ -1,11 +1,93 @@
 use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
 use anyhow::{Result, anyhow};
 use log::info;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+
+/// How trades from one target wallet are copied.
+#[derive(Debug, Clone)]
+pub struct CopySettings {
+    pub order_size: f64,
+    pub buyin_percentage: f64,
+    /// Gain over the cost of a copied position at which it is sold, e.g.
+    /// 0.1 sells once it is worth 110% of what we paid.
+    pub take_profit: f64,
+    /// Loss at which a copied position is sold, e.g. 0.2 sells at 80%.
+    pub stop_loss: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TargetWallet {
+    pub wallet: Pubkey,
+    pub settings: CopySettings,
+    pub enabled: bool,
+}
+
+/// One entry of the TARGETS_FILE JSON array. Omitted settings fall back to
+/// the global values from the environment.
+#[derive(Debug, Deserialize)]
+struct TargetEntry {
+    wallet: String,
+    order_size: Option<f64>,
+    buyin_percentage: Option<f64>,
+    take_profit: Option<f64>,
+    stop_loss: Option<f64>,
+    enabled: Option<bool>,
+}
+
+/// Where target activity is read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestionKind {
+    WebSocket,
+    Grpc,
+    /// A capture written with RECORD_FILE, played back in paper mode.
+    Replay,
+}
+
+/// The settings read-only commands need: where to read the chain and at
+/// which commitment. Unlike `Config`, no wallet or targets are required.
+pub struct RpcSettings {
+    pub rpc_endpoint: String,
+    pub detection_commitment: CommitmentConfig,
+}
+
+impl RpcSettings {
+    pub fn new() -> Result<Self> {
+        dotenv::dotenv().ok();
+        Ok(RpcSettings {
+            rpc_endpoint: rpc_endpoint(),
+            detection_commitment: commitment_setting("DETECTION_COMMITMENT")?,
+        })
+    }
+}
 
 pub struct Config {
     pub rpc_endpoint: String,
-    pub ws_endpoint: String,
-    pub target_wallet: String,
+    pub ws_endpoints: Vec<String>,
+    /// Where pool reserves are subscribed to; one connection is enough.
+    pub reserves_ws_endpoint: String,
+    pub ingestion_source: IngestionKind,
+    pub grpc_endpoint: Option<String>,
+    pub grpc_x_token: Option<String>,
+    pub replay_file: Option<String>,
+    /// Multiplier on the recorded pace of a replay; 0 replays without waiting.
+    pub replay_speed: f64,
+    /// Capture file for raw listener traffic.
+    pub record_file: Option<String>,
+    pub ws_ping_interval: Duration,
+    pub ws_liveness_timeout: Duration,
+    /// How often positions on pump.fun curves are checked for migration.
+    pub migration_poll_interval: Duration,
+    /// How often open positions are valued against their take profit and
+    /// stop loss.
+    pub exit_poll_interval: Duration,
+    /// Where decoded Raydium pool state is kept between runs.
+    pub pool_cache_file: String,
+    pub targets: Vec<TargetWallet>,
     pub private_key: String,
     #[allow(dead_code)]
     pub take_profit: f64,
@@ -15,12 +97,23 @@ pub struct Config {
     pub order_size: f64,
     #[allow(dead_code)]
     pub buyin_percentage: f64,
+    /// Tolerated price movement on our copies, in basis points.
+    pub slippage_bps: u64,
+    /// Copies that would move the pool price more than this are not sent.
+    pub max_price_impact_bps: u64,
+    /// Build copies without signing or sending them.
+    pub paper_trading: bool,
     #[allow(dead_code)]
     pub jito_fee: f64,
     #[allow(dead_code)]
     pub bloxroute_fee: f64,
-    #[allow(dead_code)]
-    pub commitment_level: String,
+    /// Commitment for subscriptions and reading target transactions.
+    pub detection_commitment: CommitmentConfig,
+    /// Commitment for blockhashes, simulation and confirming our own swaps.
+    pub execution_commitment: CommitmentConfig,
+    /// When set, a target trade is only copied once its transaction reaches
+    /// this commitment without an error.
+    pub copy_commitment: Option<CommitmentConfig>,
     #[allow(dead_code)]
     pub raydium_program_ids: Vec<String>,
 }
@@ -29,14 +122,85 @@ impl Config {
     pub fn new() -> Result<Self> {
         dotenv::dotenv().ok();
         
-        let rpc_endpoint = env::var("RPC_ENDPOINT")
-            .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string());
+        let rpc_endpoint = rpc_endpoint();
             
-        let ws_endpoint = env::var("WS_ENDPOINT")
-            .unwrap_or_else(|_| "wss://api.mainnet-beta.solana.com".to_string());
+        // WS_ENDPOINTS lists several providers to race; WS_ENDPOINT is a single one.
+        let ws_endpoints: Vec<String> = env::var("WS_ENDPOINTS")
+            .or_else(|_| env::var("WS_ENDPOINT"))
+            .unwrap_or_else(|_| "wss://api.mainnet-beta.solana.com".to_string())
+            .split(',')
+            .map(|endpoint| endpoint.trim().to_string())
+            .filter(|endpoint| !endpoint.is_empty())
+            .collect();
+        if ws_endpoints.is_empty() {
+            return Err(anyhow!("No WebSocket endpoints configured"));
+        }
+
+        let reserves_ws_endpoint = env::var("RESERVES_WS_ENDPOINT")
+            .unwrap_or_else(|_| ws_endpoints[0].clone());
+            
+        let ingestion_source = match env::var("INGESTION_SOURCE")
+            .unwrap_or_else(|_| "websocket".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "websocket" | "ws" => IngestionKind::WebSocket,
+            "grpc" => IngestionKind::Grpc,
+            "replay" => IngestionKind::Replay,
+            other => return Err(anyhow!("Invalid INGESTION_SOURCE: {}", other)),
+        };
+
+        let grpc_endpoint = env::var("GRPC_ENDPOINT").ok();
+        if ingestion_source == IngestionKind::Grpc && grpc_endpoint.is_none() {
+            return Err(anyhow!("GRPC_ENDPOINT not set"));
+        }
+
+        let grpc_x_token = env::var("GRPC_X_TOKEN").ok();
+
+        let replay_file = env::var("REPLAY_FILE").ok();
+        if ingestion_source == IngestionKind::Replay && replay_file.is_none() {
+            return Err(anyhow!("REPLAY_FILE not set"));
+        }
+
+        let replay_speed = env::var("REPLAY_SPEED")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse::<f64>()?;
+        if replay_speed < 0.0 {
+            return Err(anyhow!("REPLAY_SPEED must not be negative"));
+        }
+
+        let record_file = env::var("RECORD_FILE").ok();
+
+        let ws_ping_interval = Duration::from_secs(env::var("WS_PING_INTERVAL_SECS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse::<u64>()?);
+        if ws_ping_interval.is_zero() {
+            return Err(anyhow!("WS_PING_INTERVAL_SECS must be at least 1"));
+        }
+
+        let ws_liveness_timeout = Duration::from_secs(env::var("WS_LIVENESS_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()?);
+        if ws_liveness_timeout.is_zero() {
+            return Err(anyhow!("WS_LIVENESS_TIMEOUT_SECS must be at least 1"));
+        }
+
+        let migration_poll_interval = Duration::from_secs(env::var("MIGRATION_POLL_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()?);
+        if migration_poll_interval.is_zero() {
+            return Err(anyhow!("MIGRATION_POLL_SECS must be at least 1"));
+        }
+
+        let exit_poll_interval = Duration::from_secs(env::var("EXIT_POLL_SECS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u64>()?);
+        if exit_poll_interval.is_zero() {
+            return Err(anyhow!("EXIT_POLL_SECS must be at least 1"));
+        }
             
-        let target_wallet = env::var("TARGET_WALLET")
-            .map_err(|_| anyhow!("TARGET_WALLET not set"))?;
+        let pool_cache_file = env::var("POOL_CACHE_FILE")
+            .unwrap_or_else(|_| "pool_cache.json".to_string());
             
         let private_key = env::var("PRIVATE_KEY")
             .map_err(|_| anyhow!("PRIVATE_KEY not set"))?;
@@ -57,6 +221,20 @@ impl Config {
             .unwrap_or_else(|_| "0.05".to_string())
             .parse::<f64>()?;
             
+        let slippage_bps = env::var("SLIPPAGE_BPS")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse::<u64>()?;
+
+        let max_price_impact_bps = env::var("MAX_PRICE_IMPACT_BPS")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<u64>()?;
+
+        // A replay never trades for real.
+        let paper_trading = ingestion_source == IngestionKind::Replay
+            || env::var("PAPER_TRADING")
+                .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false);
+            
         let jito_fee = env::var("JITO_FEE")
             .unwrap_or_else(|_| "0.0001".to_string())
             .parse::<f64>()?;
@@ -65,8 +243,12 @@ impl Config {
             .unwrap_or_else(|_| "0.001".to_string())
             .parse::<f64>()?;
             
-        let commitment_level = env::var("COMMITMENT_LEVEL")
-            .unwrap_or_else(|_| "finalized".to_string());
+        let detection_commitment = commitment_setting("DETECTION_COMMITMENT")?;
+        let execution_commitment = commitment_setting("EXECUTION_COMMITMENT")?;
+
+        let copy_commitment = env::var("COPY_COMMITMENT").ok()
+            .map(|level| parse_commitment(&level))
+            .transpose()?;
         
         let raydium_program_ids = vec![
             env::var("RAYDIUM_AMM_ID")
@@ -76,20 +258,126 @@ impl Config {
         ];
         
         info!("Monitoring Raydium program IDs: {:?}", raydium_program_ids);
+
+        let defaults = CopySettings {
+            order_size,
+            buyin_percentage,
+            take_profit,
+            stop_loss,
+        };
+        let targets = load_targets(&defaults)?;
         
         Ok(Config {
             rpc_endpoint,
-            ws_endpoint,
-            target_wallet,
+            ws_endpoints,
+            reserves_ws_endpoint,
+            ingestion_source,
+            grpc_endpoint,
+            grpc_x_token,
+            replay_file,
+            replay_speed,
+            record_file,
+            ws_ping_interval,
+            ws_liveness_timeout,
+            migration_poll_interval,
+            exit_poll_interval,
+            pool_cache_file,
+            targets,
             private_key,
             take_profit,
             stop_loss,
             order_size,
             buyin_percentage,
+            slippage_bps,
+            max_price_impact_bps,
+            paper_trading,
             jito_fee,
             bloxroute_fee,
-            commitment_level,
+            detection_commitment,
+            execution_commitment,
+            copy_commitment,
             raydium_program_ids,
         })
     }
 }
+
+impl Config {
+    pub fn enabled_targets(&self) -> impl Iterator<Item = &TargetWallet> {
+        self.targets.iter().filter(|target| target.enabled)
+    }
+}
+
+fn rpc_endpoint() -> String {
+    env::var("RPC_ENDPOINT")
+        .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string())
+}
+
+/// The commitment set in `name`, falling back to COMMITMENT_LEVEL.
+fn commitment_setting(name: &str) -> Result<CommitmentConfig> {
+    let level = env::var(name)
+        .or_else(|_| env::var("COMMITMENT_LEVEL"))
+        .unwrap_or_else(|_| "finalized".to_string());
+    parse_commitment(&level)
+}
+
+pub fn parse_commitment(level: &str) -> Result<CommitmentConfig> {
+    let commitment = match level.trim().to_lowercase().as_str() {
+        "processed" => CommitmentLevel::Processed,
+        "confirmed" => CommitmentLevel::Confirmed,
+        "finalized" => CommitmentLevel::Finalized,
+        other => return Err(anyhow!("Invalid commitment level: {} (expected processed, confirmed or finalized)", other)),
+    };
+    Ok(CommitmentConfig { commitment })
+}
+
+/// Reads targets from TARGETS_FILE if set, otherwise from the comma separated
+/// TARGET_WALLET list using the global copy settings.
+fn load_targets(defaults: &CopySettings) -> Result<Vec<TargetWallet>> {
+    let targets = match env::var("TARGETS_FILE") {
+        Ok(path) => {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read TARGETS_FILE {}: {}", path, e))?;
+            let entries: Vec<TargetEntry> = serde_json::from_str(&contents)
+                .map_err(|e| anyhow!("Invalid TARGETS_FILE {}: {}", path, e))?;
+
+            entries.into_iter()
+                .map(|entry| {
+                    Ok(TargetWallet {
+                        wallet: Pubkey::from_str(&entry.wallet)
+                            .map_err(|_| anyhow!("Invalid target wallet {}", entry.wallet))?,
+                        settings: CopySettings {
+                            order_size: entry.order_size.unwrap_or(defaults.order_size),
+                            buyin_percentage: entry.buyin_percentage.unwrap_or(defaults.buyin_percentage),
+                            take_profit: entry.take_profit.unwrap_or(defaults.take_profit),
+                            stop_loss: entry.stop_loss.unwrap_or(defaults.stop_loss),
+                        },
+                        enabled: entry.enabled.unwrap_or(true),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+        Err(_) => {
+            let wallets = env::var("TARGET_WALLET")
+                .map_err(|_| anyhow!("TARGET_WALLET or TARGETS_FILE not set"))?;
+
+            wallets.split(',')
+                .map(str::trim)
+                .filter(|wallet| !wallet.is_empty())
+                .map(|wallet| {
+                    Ok(TargetWallet {
+                        wallet: Pubkey::from_str(wallet)
+                            .map_err(|_| anyhow!("Invalid target wallet {}", wallet))?,
+                        settings: defaults.clone(),
+                        enabled: true,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+    };
+
+    if !targets.iter().any(|target| target.enabled) {
+        return Err(anyhow!("No enabled target wallets configured"));
+    }
+
+    Ok(targets)
+}
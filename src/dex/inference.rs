@@ -0,0 +1,225 @@
+//! Venue-agnostic trade inference from balance changes.
+//!
+//! Used when no decoder recognises a transaction: the target's net change
+//! per mint, with SOL counted from its lamports plus any wrapped SOL, says
+//! what it gave up and what it received regardless of the program involved.
+
+use solana_sdk::pubkey::Pubkey;
+use crate::transaction::ParsedTransaction;
+use crate::types::Dex;
+use super::{SwapEvent, TOKEN_2022_PROGRAM_ID, is_infrastructure_program};
+use std::collections::HashMap;
+
+/// Rent-exempt minimum of a 165 byte token account, paid when one is opened
+/// and refunded when it is closed.
+const TOKEN_ACCOUNT_RENT: i128 = 2_039_280;
+
+/// SOL movements smaller than this are fees and rent noise, not a trade leg.
+const MIN_SOL_CHANGE: i128 = 100_000;
+
+/// System program `CreateAccount` and SPL token `CloseAccount` tags.
+const CREATE_ACCOUNT: [u8; 4] = [0, 0, 0, 0];
+const CLOSE_ACCOUNT: u8 = 9;
+
+/// Net change of each mint across the target's accounts. Native SOL is
+/// reported under the wrapped SOL mint.
+pub fn net_changes(transaction: &ParsedTransaction, target: Pubkey) -> HashMap<Pubkey, i128> {
+    let mut changes: HashMap<Pubkey, i128> = HashMap::new();
+    let mut opened = 0i128;
+    let mut closed = 0i128;
+
+    for balance in &transaction.pre_token_balances {
+        if balance.owner == Some(target) {
+            *changes.entry(balance.mint).or_default() -= balance.amount as i128;
+            let still_open = transaction.post_token_balances.iter()
+                .any(|post| post.account_index == balance.account_index);
+            let account = transaction.account_keys.get(balance.account_index);
+            if !still_open && account.is_some_and(|account| refunded_to(transaction, account, &target)) {
+                closed += 1;
+            }
+        }
+    }
+    for balance in &transaction.post_token_balances {
+        if balance.owner == Some(target) {
+            *changes.entry(balance.mint).or_default() += balance.amount as i128;
+            let existed = transaction.pre_token_balances.iter()
+                .any(|pre| pre.account_index == balance.account_index);
+            let account = transaction.account_keys.get(balance.account_index);
+            if !existed && account.is_some_and(|account| funded_by(transaction, account, &target)) {
+                opened += 1;
+            }
+        }
+    }
+
+    // Lamports, with the fee and the rent of token accounts the target paid
+    // for or was refunded added back, so only the SOL that went into or came
+    // out of the trade remains.
+    let mut sol_change = transaction.lamport_change(&target).unwrap_or(0);
+    if transaction.account_keys.first() == Some(&target) {
+        sol_change += transaction.fee as i128;
+    }
+    sol_change += (opened - closed) * TOKEN_ACCOUNT_RENT;
+    *changes.entry(spl_token::native_mint::id()).or_default() += sol_change;
+
+    changes.retain(|mint, change| {
+        if *mint == spl_token::native_mint::id() {
+            change.abs() >= MIN_SOL_CHANGE
+        } else {
+            *change != 0
+        }
+    });
+    changes
+}
+
+/// Infers the trade the target made: the mint that fell most is the input,
+/// the one that rose most the output. Transactions that only moved tokens
+/// one way, such as transfers, are not trades.
+pub fn infer_swap(transaction: &ParsedTransaction, target: Pubkey) -> Option<SwapEvent> {
+    let changes = net_changes(transaction, target);
+    let (input_mint, input_change) = changes.iter()
+        .filter(|(_, change)| **change < 0)
+        .min_by_key(|(_, change)| **change)?;
+    let (output_mint, output_change) = changes.iter()
+        .filter(|(_, change)| **change > 0)
+        .max_by_key(|(_, change)| **change)?;
+
+    Some(SwapEvent {
+        dex: Dex::Unclassified,
+        program_id: first_venue_program(transaction),
+        pool_id: Pubkey::default(),
+        owner: target,
+        input_mint: *input_mint,
+        output_mint: *output_mint,
+        input_amount: input_change.unsigned_abs() as u64,
+        output_amount: output_change.unsigned_abs() as u64,
+        // Balance changes cover the whole transaction.
+        outer_index: 0,
+        invocation: 0,
+        inferred: true,
+    })
+}
+
+/// Whether `payer` paid the rent of `account`, opened in this transaction
+/// either as an associated token account or by a plain `CreateAccount`.
+fn funded_by(transaction: &ParsedTransaction, account: &Pubkey, payer: &Pubkey) -> bool {
+    transaction.invocations().iter().any(|invocation| {
+        let instruction = invocation.instruction;
+        let creates = instruction.program_id == spl_associated_token_account::id()
+            || (instruction.program_id == solana_sdk::system_program::id()
+                && instruction.data.get(..4) == Some(&CREATE_ACCOUNT[..]));
+        creates
+            && instruction.accounts.first() == Some(payer)
+            && instruction.accounts.get(1) == Some(account)
+    })
+}
+
+/// Whether closing `account` in this transaction returned its rent to
+/// `recipient`.
+fn refunded_to(transaction: &ParsedTransaction, account: &Pubkey, recipient: &Pubkey) -> bool {
+    transaction.invocations().iter().any(|invocation| {
+        let instruction = invocation.instruction;
+        (instruction.program_id == spl_token::id() || instruction.program_id == TOKEN_2022_PROGRAM_ID)
+            && instruction.data.first() == Some(&CLOSE_ACCOUNT)
+            && instruction.accounts.first() == Some(account)
+            && instruction.accounts.get(1) == Some(recipient)
+    })
+}
+
+fn first_venue_program(transaction: &ParsedTransaction) -> Pubkey {
+    transaction.instructions.iter()
+        .map(|instruction| instruction.program_id)
+        .find(|program_id| !is_infrastructure_program(program_id))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::transaction::ParsedInstruction;
+    use spl_associated_token_account::get_associated_token_address;
+
+    const FEE: u64 = 5_000;
+    const RENT: u64 = TOKEN_ACCOUNT_RENT as u64;
+
+    struct Trade {
+        target: Pubkey,
+        mint: Pubkey,
+        token_account: Pubkey,
+        venue: ParsedInstruction,
+    }
+
+    fn trade() -> Trade {
+        let (target, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token_account = get_associated_token_address(&target, &mint);
+        let venue = fixtures::instruction(Pubkey::new_unique(), vec![target, token_account, Pubkey::new_unique()], vec![1]);
+        Trade { target, mint, token_account, venue }
+    }
+
+    fn create_token_account(payer: Pubkey, trade: &Trade) -> ParsedInstruction {
+        fixtures::instruction(spl_associated_token_account::id(), vec![
+            payer, trade.token_account, trade.target, trade.mint, solana_sdk::system_program::id(), spl_token::id(),
+        ], vec![1])
+    }
+
+    fn close_token_account(trade: &Trade) -> ParsedInstruction {
+        fixtures::instruction(spl_token::id(), vec![trade.token_account, trade.target, trade.target], vec![CLOSE_ACCOUNT])
+    }
+
+    fn inferred(transaction: &ParsedTransaction, target: Pubkey) -> (Pubkey, u64, Pubkey, u64) {
+        let swap = infer_swap(transaction, target).unwrap();
+        (swap.input_mint, swap.input_amount, swap.output_mint, swap.output_amount)
+    }
+
+    #[test]
+    fn buy_into_an_existing_token_account() {
+        let trade = trade();
+        let mut transaction = fixtures::transaction(trade.target, vec![trade.venue.clone()]);
+        fixtures::set_lamports(&mut transaction, &trade.target, 5_000_000_000, 5_000_000_000 - 1_000_000_000 - FEE);
+        fixtures::set_token_balance(&mut transaction, &trade.token_account, &trade.mint, &trade.target, Some(0), Some(2_588_000));
+
+        assert_eq!(inferred(&transaction, trade.target), (spl_token::native_mint::id(), 1_000_000_000, trade.mint, 2_588_000));
+    }
+
+    #[test]
+    fn buy_that_opens_the_token_account() {
+        let trade = trade();
+        let mut transaction = fixtures::transaction(trade.target, vec![create_token_account(trade.target, &trade), trade.venue.clone()]);
+        fixtures::set_lamports(&mut transaction, &trade.target, 5_000_000_000, 5_000_000_000 - 1_000_000_000 - RENT - FEE);
+        fixtures::set_token_balance(&mut transaction, &trade.token_account, &trade.mint, &trade.target, None, Some(2_588_000));
+
+        assert_eq!(inferred(&transaction, trade.target), (spl_token::native_mint::id(), 1_000_000_000, trade.mint, 2_588_000));
+    }
+
+    #[test]
+    fn rent_another_wallet_paid_is_not_the_targets() {
+        // A relayer signs, pays the fee and opens the target's token account.
+        let trade = trade();
+        let relayer = Pubkey::new_unique();
+        let mut transaction = fixtures::transaction(relayer, vec![create_token_account(relayer, &trade), trade.venue.clone()]);
+        fixtures::set_lamports(&mut transaction, &trade.target, 5_000_000_000, 4_000_000_000);
+        fixtures::set_token_balance(&mut transaction, &trade.token_account, &trade.mint, &trade.target, None, Some(2_588_000));
+
+        assert_eq!(inferred(&transaction, trade.target), (spl_token::native_mint::id(), 1_000_000_000, trade.mint, 2_588_000));
+    }
+
+    #[test]
+    fn sell_that_keeps_the_token_account() {
+        let trade = trade();
+        let mut transaction = fixtures::transaction(trade.target, vec![trade.venue.clone()]);
+        fixtures::set_lamports(&mut transaction, &trade.target, 5_000_000_000, 5_000_000_000 + 1_010_000_000 - FEE);
+        fixtures::set_token_balance(&mut transaction, &trade.token_account, &trade.mint, &trade.target, Some(2_588_000), Some(0));
+
+        assert_eq!(inferred(&transaction, trade.target), (trade.mint, 2_588_000, spl_token::native_mint::id(), 1_010_000_000));
+    }
+
+    #[test]
+    fn sell_that_closes_the_token_account() {
+        let trade = trade();
+        let mut transaction = fixtures::transaction(trade.target, vec![trade.venue.clone(), close_token_account(&trade)]);
+        fixtures::set_lamports(&mut transaction, &trade.target, 5_000_000_000, 5_000_000_000 + 1_010_000_000 + RENT - FEE);
+        fixtures::set_token_balance(&mut transaction, &trade.token_account, &trade.mint, &trade.target, Some(2_588_000), None);
+
+        assert_eq!(inferred(&transaction, trade.target), (trade.mint, 2_588_000, spl_token::native_mint::id(), 1_010_000_000));
+    }
+}
@@ -0,0 +1,301 @@
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use log::debug;
+use crate::transaction::{Invocation, ParsedInstruction, ParsedTransaction};
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, swap_balance_change};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+
+const ROUTE: [u8; 8] = [229, 23, 203, 151, 122, 227, 173, 42];
+const ROUTE_WITH_TOKEN_LEDGER: [u8; 8] = [150, 86, 71, 116, 167, 93, 14, 104];
+const SHARED_ACCOUNTS_ROUTE: [u8; 8] = [193, 32, 155, 51, 65, 214, 156, 129];
+const SHARED_ACCOUNTS_ROUTE_WITH_TOKEN_LEDGER: [u8; 8] = [230, 121, 143, 80, 119, 159, 106, 170];
+const EXACT_OUT_ROUTE: [u8; 8] = [208, 51, 239, 151, 123, 43, 237, 92];
+const SHARED_ACCOUNTS_EXACT_OUT_ROUTE: [u8; 8] = [176, 209, 105, 168, 154, 125, 69, 62];
+
+/// Anchor's self-CPI event tag followed by the `SwapEvent` discriminator.
+const EVENT_IX_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+const SWAP_EVENT: [u8; 8] = [64, 198, 205, 232, 38, 8, 113, 226];
+
+/// Jupiter v6 routes. Each route is collapsed into one swap from the user's
+/// source mint to their destination mint, whatever hops it took on the way.
+pub struct JupiterDecoder;
+
+/// Account positions that differ between the route instructions.
+struct RouteAccounts {
+    authority: usize,
+    source: usize,
+    destination: usize,
+    source_mint: Option<usize>,
+    destination_mint: usize,
+}
+
+/// Amounts at the end of the instruction data, after the variable length
+/// route plan.
+enum RouteAmounts {
+    /// `in_amount, quoted_out_amount, slippage_bps, platform_fee_bps`
+    ExactIn,
+    /// `quoted_out_amount, slippage_bps, platform_fee_bps`; the input comes
+    /// from a token ledger account.
+    TokenLedger,
+    /// `out_amount, quoted_in_amount, slippage_bps, platform_fee_bps`
+    ExactOut,
+}
+
+/// One hop of a route, as reported by the program's `SwapEvent`.
+#[derive(Debug)]
+struct Leg {
+    amm: Pubkey,
+    input_mint: Pubkey,
+    input_amount: u64,
+    output_mint: Pubkey,
+    output_amount: u64,
+}
+
+impl DexDecoder for JupiterDecoder {
+    fn name(&self) -> &'static str {
+        "jupiter-v6"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn is_router(&self) -> bool {
+        true
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        transaction.invocations().iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter_map(|invocation| decode_route(transaction, invocation))
+            .collect()
+    }
+}
+
+fn route_layout(data: &[u8]) -> Option<(RouteAccounts, RouteAmounts)> {
+    let discriminator: [u8; 8] = data.get(..8)?.try_into().ok()?;
+    let user_route = RouteAccounts {
+        authority: 1,
+        source: 2,
+        destination: 3,
+        source_mint: None,
+        destination_mint: 5,
+    };
+    let shared_route = RouteAccounts {
+        authority: 2,
+        source: 3,
+        destination: 6,
+        source_mint: Some(7),
+        destination_mint: 8,
+    };
+    match discriminator {
+        ROUTE => Some((user_route, RouteAmounts::ExactIn)),
+        ROUTE_WITH_TOKEN_LEDGER => Some((user_route, RouteAmounts::TokenLedger)),
+        SHARED_ACCOUNTS_ROUTE => Some((shared_route, RouteAmounts::ExactIn)),
+        SHARED_ACCOUNTS_ROUTE_WITH_TOKEN_LEDGER => Some((shared_route, RouteAmounts::TokenLedger)),
+        SHARED_ACCOUNTS_EXACT_OUT_ROUTE => Some((shared_route, RouteAmounts::ExactOut)),
+        EXACT_OUT_ROUTE => Some((RouteAccounts {
+            authority: 1,
+            source: 2,
+            destination: 3,
+            source_mint: Some(5),
+            destination_mint: 6,
+        }, RouteAmounts::ExactOut)),
+        _ => None,
+    }
+}
+
+/// The (input, output) amounts the route instruction asked for.
+fn requested_amounts(data: &[u8], amounts: &RouteAmounts) -> (Option<u64>, Option<u64>) {
+    let read = |from_end: usize| -> Option<u64> {
+        let start = data.len().checked_sub(from_end)?;
+        Some(u64::from_le_bytes(data.get(start..start + 8)?.try_into().ok()?))
+    };
+    match amounts {
+        RouteAmounts::ExactIn => (read(19), read(11)),
+        RouteAmounts::TokenLedger => (None, read(11)),
+        RouteAmounts::ExactOut => (read(11), read(19)),
+    }
+}
+
+fn parse_leg(instruction: &ParsedInstruction) -> Option<Leg> {
+    let data = &instruction.data;
+    if instruction.program_id != PROGRAM_ID
+        || data.len() < 128
+        || data[..8] != EVENT_IX_TAG
+        || data[8..16] != SWAP_EVENT
+    {
+        return None;
+    }
+    let pubkey_at = |start: usize| Pubkey::try_from(&data[start..start + 32]).ok();
+    let u64_at = |start: usize| data[start..start + 8].try_into().ok().map(u64::from_le_bytes);
+    Some(Leg {
+        amm: pubkey_at(16)?,
+        input_mint: pubkey_at(48)?,
+        input_amount: u64_at(80)?,
+        output_mint: pubkey_at(88)?,
+        output_amount: u64_at(120)?,
+    })
+}
+
+fn decode_route(transaction: &ParsedTransaction, invocation: &Invocation) -> Option<SwapEvent> {
+    let instruction = invocation.instruction;
+    let (layout, amounts) = route_layout(&instruction.data)?;
+    let accounts = &instruction.accounts;
+    if accounts.len() <= layout.destination_mint.max(layout.source_mint.unwrap_or(0)) {
+        return None;
+    }
+    let owner = accounts[layout.authority];
+    let user_source = accounts[layout.source];
+    let user_destination = accounts[layout.destination];
+
+    let legs: Vec<Leg> = invocation.children.iter()
+        .filter_map(parse_leg)
+        .collect();
+    debug!("Jupiter route with {} legs: {:?}", legs.len(), legs);
+
+    let input_mint = layout.source_mint.map(|index| accounts[index])
+        .or_else(|| legs.first().map(|leg| leg.input_mint))
+        .or_else(|| transaction.token_mint(&user_source))?;
+    let output_mint = accounts[layout.destination_mint];
+
+    // Split routes spend the source mint and receive the destination mint on
+    // several legs; intermediate mints net out.
+    let legs_in: u64 = legs.iter()
+        .filter(|leg| leg.input_mint == input_mint)
+        .map(|leg| leg.input_amount)
+        .sum();
+    let legs_out: u64 = legs.iter()
+        .filter(|leg| leg.output_mint == output_mint)
+        .map(|leg| leg.output_amount)
+        .sum();
+    let (requested_in, requested_out) = requested_amounts(&instruction.data, &amounts);
+
+    // The route's own leg events, then the user's balance changes when no
+    // other swap shares the accounts, then the instruction.
+    let input_amount = (legs_in > 0).then_some(legs_in)
+        .or_else(|| swap_balance_change(transaction, invocation, &user_source)
+            .filter(|change| *change < 0)
+            .map(|change| change.unsigned_abs() as u64))
+        .or(requested_in)
+        .unwrap_or(0);
+    let output_amount = (legs_out > 0).then_some(legs_out)
+        .or_else(|| swap_balance_change(transaction, invocation, &user_destination)
+            .filter(|change| *change > 0)
+            .map(|change| change.unsigned_abs() as u64))
+        .or(requested_out)
+        .unwrap_or(0);
+
+    Some(SwapEvent {
+        dex: Dex::Jupiter,
+        program_id: PROGRAM_ID,
+        // A route may touch several pools; report the first hop.
+        pool_id: legs.first().map(|leg| leg.amm).unwrap_or_default(),
+        owner,
+        input_mint,
+        output_mint,
+        input_amount,
+        output_amount,
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    const BONK: Pubkey = pubkey!("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
+    const SOL_USDC_AMM: Pubkey = pubkey!("58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2");
+    const SOL_USDC_WHIRLPOOL: Pubkey = pubkey!("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE");
+    const EVENT_AUTHORITY: Pubkey = pubkey!("D8cy77BBepLMngZx6ZukaTff5hCt1HrWyKk3Hnd9oitf");
+
+    /// Route data: the discriminator and any leading arguments, a route plan
+    /// and the trailing amounts, slippage and platform fee.
+    fn route_data(head: &[u8], amounts: &[u64]) -> Vec<u8> {
+        let mut data = head.to_vec();
+        // A one step route plan: [len, swap variant, percent, input, output].
+        data.extend_from_slice(&[1, 0, 0, 0, 7, 100, 0, 1]);
+        for amount in amounts {
+            data.extend_from_slice(&amount.to_le_bytes());
+        }
+        data.extend_from_slice(&50u16.to_le_bytes());
+        data.push(0);
+        data
+    }
+
+    /// The `SwapEvent` Jupiter emits through a self-CPI for each hop.
+    fn leg_event(amm: Pubkey, input_mint: Pubkey, input_amount: u64, output_mint: Pubkey, output_amount: u64) -> crate::transaction::ParsedInstruction {
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend_from_slice(&SWAP_EVENT);
+        data.extend_from_slice(amm.as_ref());
+        data.extend_from_slice(input_mint.as_ref());
+        data.extend_from_slice(&input_amount.to_le_bytes());
+        data.extend_from_slice(output_mint.as_ref());
+        data.extend_from_slice(&output_amount.to_le_bytes());
+        fixtures::instruction(PROGRAM_ID, vec![EVENT_AUTHORITY], data)
+    }
+
+    #[test]
+    fn route_sums_the_legs_of_a_split_route() {
+        // SOL -> BONK, split across two SOL/USDC pools and then through USDC.
+        let (user, user_sol, user_bonk) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let route = fixtures::instruction(PROGRAM_ID, vec![
+            spl_token::id(), user, user_sol, user_bonk, PROGRAM_ID, BONK, PROGRAM_ID, EVENT_AUTHORITY, PROGRAM_ID,
+        ], route_data(&ROUTE, &[2_000_000_000, 13_000_000_000]));
+        let route = fixtures::with_inner(route, vec![
+            (2, leg_event(SOL_USDC_AMM, WSOL, 1_200_000_000, USDC, 177_800_000)),
+            (2, leg_event(SOL_USDC_WHIRLPOOL, WSOL, 800_000_000, USDC, 118_600_000)),
+            (2, leg_event(Pubkey::new_unique(), USDC, 296_400_000, BONK, 13_089_112_000)),
+        ]);
+        let mut transaction = fixtures::transaction(user, vec![route]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &WSOL, &user, Some(2_000_000_000), Some(0));
+
+        let swaps = JupiterDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!((swap.dex, swap.pool_id, swap.owner), (Dex::Jupiter, SOL_USDC_AMM, user));
+        assert_eq!((swap.input_mint, swap.input_amount), (WSOL, 2_000_000_000));
+        assert_eq!((swap.output_mint, swap.output_amount), (BONK, 13_089_112_000));
+    }
+
+    #[test]
+    fn shared_accounts_route_without_events_reads_balances() {
+        let (user, user_usdc, user_sol) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // shared_accounts_route takes a program account id before the plan.
+        let data = route_data(&[&SHARED_ACCOUNTS_ROUTE[..], &[3]].concat(), &[150_000_000, 990_000_000]);
+        let route = fixtures::instruction(PROGRAM_ID, vec![
+            spl_token::id(), Pubkey::new_unique(), user, user_usdc, Pubkey::new_unique(), Pubkey::new_unique(),
+            user_sol, USDC, WSOL, PROGRAM_ID, PROGRAM_ID, EVENT_AUTHORITY, PROGRAM_ID,
+        ], data);
+        let mut transaction = fixtures::transaction(user, vec![route]);
+        fixtures::set_token_balance(&mut transaction, &user_usdc, &USDC, &user, Some(400_000_000), Some(250_000_000));
+        fixtures::set_token_balance(&mut transaction, &user_sol, &WSOL, &user, Some(0), Some(1_004_218_331));
+
+        let swaps = JupiterDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 150_000_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 1_004_218_331));
+        assert_eq!(swaps[0].pool_id, Pubkey::default());
+    }
+
+    #[test]
+    fn exact_out_route_falls_back_to_the_requested_amounts() {
+        let (user, user_usdc, user_sol) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let route = fixtures::instruction(PROGRAM_ID, vec![
+            spl_token::id(), user, user_usdc, user_sol, PROGRAM_ID, USDC, WSOL, PROGRAM_ID,
+        ], route_data(&EXACT_OUT_ROUTE, &[1_000_000_000, 149_100_000]));
+        let transaction = fixtures::transaction(user, vec![route]);
+
+        let swaps = JupiterDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 149_100_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 1_000_000_000));
+    }
+}
@@ -0,0 +1,362 @@
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use anyhow::{Result, bail};
+use crate::quote::{Bin, Fee};
+use crate::transaction::{Invocation, ParsedTransaction};
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, received_amount, spent_amount, transferred};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo");
+
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+const SWAP2: [u8; 8] = [65, 75, 63, 76, 235, 91, 91, 136];
+const SWAP_EXACT_OUT: [u8; 8] = [250, 73, 101, 33, 38, 207, 75, 184];
+const SWAP_EXACT_OUT2: [u8; 8] = [43, 215, 247, 132, 137, 60, 243, 81];
+const LB_PAIR_ACCOUNT: [u8; 8] = [33, 11, 49, 98, 181, 101, 177, 13];
+const BIN_ARRAY_ACCOUNT: [u8; 8] = [92, 142, 92, 220, 5, 148, 70, 181];
+
+const MAX_BIN_PER_ARRAY: i32 = 70;
+const BINS_OFFSET: usize = 56;
+const BIN_SIZE: usize = 144;
+/// Denominator of the pair's fee rates.
+const FEE_PRECISION: u64 = 1_000_000_000;
+
+/// Meteora dynamic liquidity market maker (DLMM) pairs.
+pub struct MeteoraDlmmDecoder;
+
+impl DexDecoder for MeteoraDlmmDecoder {
+    fn name(&self) -> &'static str {
+        "meteora-dlmm"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        transaction.invocations().iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter_map(|invocation| decode_swap(transaction, invocation))
+            .collect()
+    }
+}
+
+fn decode_swap(transaction: &ParsedTransaction, invocation: &Invocation) -> Option<SwapEvent> {
+    // swap / swap2(amount_in, min_amount_out)
+    // swap_exact_out / swap_exact_out2(max_in_amount, out_amount)
+    let instruction = invocation.instruction;
+    let data = &instruction.data;
+    if data.len() < 24 {
+        return None;
+    }
+    match data[..8].try_into().ok()? {
+        SWAP | SWAP2 | SWAP_EXACT_OUT | SWAP_EXACT_OUT2 => {}
+        _ => return None,
+    }
+    let instruction_in = u64::from_le_bytes(data[8..16].try_into().ok()?);
+    let instruction_out = u64::from_le_bytes(data[16..24].try_into().ok()?);
+
+    // [lb_pair, bin_array_bitmap_extension, reserve_x, reserve_y, user_token_in,
+    //  user_token_out, token_x_mint, token_y_mint, oracle, host_fee_in, user, ..]
+    let accounts = &instruction.accounts;
+    if accounts.len() < 11 {
+        return None;
+    }
+    let pool_id = accounts[0];
+    let (reserve_x, reserve_y) = (accounts[2], accounts[3]);
+    let user_source = accounts[4];
+    let user_destination = accounts[5];
+    let (mint_x, mint_y) = (accounts[6], accounts[7]);
+    let owner = accounts[10];
+
+    // The reserve the user paid into took the input.
+    let x_in = match transaction.token_mint(&user_source) {
+        Some(mint) => mint == mint_x,
+        None => transferred(invocation, &user_source, &reserve_x).is_some()
+            || super::swap_balance_change(transaction, invocation, &reserve_x).unwrap_or(0) > 0,
+    };
+    let (input_mint, input_reserve, output_mint, output_reserve) = if x_in {
+        (mint_x, reserve_x, mint_y, reserve_y)
+    } else {
+        (mint_y, reserve_y, mint_x, reserve_x)
+    };
+
+    Some(SwapEvent {
+        dex: Dex::MeteoraDlmm,
+        program_id: PROGRAM_ID,
+        pool_id,
+        owner,
+        input_mint,
+        output_mint,
+        input_amount: spent_amount(transaction, invocation, &user_source, &input_reserve).unwrap_or(instruction_in),
+        output_amount: received_amount(transaction, invocation, &user_destination, &output_reserve).unwrap_or(instruction_out),
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+/// The parts of an `LbPair` account needed to build a swap.
+#[derive(Debug, Clone)]
+pub struct LbPair {
+    pub base_factor: u16,
+    pub active_id: i32,
+    pub bin_step: u16,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub oracle: Pubkey,
+}
+
+impl LbPair {
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < 584 || data[..8] != LB_PAIR_ACCOUNT {
+            bail!("Not an LbPair account");
+        }
+        Ok(LbPair {
+            base_factor: u16::from_le_bytes(data[8..10].try_into()?),
+            active_id: i32::from_le_bytes(data[76..80].try_into()?),
+            bin_step: u16::from_le_bytes(data[80..82].try_into()?),
+            token_x_mint: Pubkey::try_from(&data[88..120])?,
+            token_y_mint: Pubkey::try_from(&data[120..152])?,
+            reserve_x: Pubkey::try_from(&data[152..184])?,
+            reserve_y: Pubkey::try_from(&data[184..216])?,
+            oracle: Pubkey::try_from(&data[552..584])?,
+        })
+    }
+
+    /// The pair's base fee. The variable fee charged on top in volatile
+    /// markets is left out.
+    pub fn fee(&self) -> Fee {
+        Fee {
+            numerator: self.base_factor as u64 * self.bin_step as u64 * 10,
+            denominator: FEE_PRECISION,
+        }
+    }
+
+    /// The bins a swap fills, from the active bin onwards in the swap's
+    /// direction, as far as `bin_arrays` reach without a gap.
+    pub fn swap_bins(&self, bin_arrays: &[Vec<u8>], x_to_y: bool) -> Result<Vec<Bin>> {
+        let mut bins = std::collections::HashMap::new();
+        for data in bin_arrays {
+            if data.len() < BINS_OFFSET + BIN_SIZE * MAX_BIN_PER_ARRAY as usize || data[..8] != BIN_ARRAY_ACCOUNT {
+                bail!("Not a BinArray account");
+            }
+            let index = i64::from_le_bytes(data[8..16].try_into()?);
+            for position in 0..MAX_BIN_PER_ARRAY as usize {
+                let bin = &data[BINS_OFFSET + position * BIN_SIZE..][..BIN_SIZE];
+                let amount_x = u64::from_le_bytes(bin[0..8].try_into()?);
+                let amount_y = u64::from_le_bytes(bin[8..16].try_into()?);
+                let id = index * MAX_BIN_PER_ARRAY as i64 + position as i64;
+                // Bins that never held liquidity have no stored price yet.
+                let price_x64 = match u128::from_le_bytes(bin[16..32].try_into()?) {
+                    0 => self.bin_price_x64(id),
+                    price => price,
+                };
+                bins.insert(id, Bin {
+                    price_x64,
+                    amount_out: if x_to_y { amount_y } else { amount_x },
+                });
+            }
+        }
+
+        let step = if x_to_y { -1 } else { 1 };
+        let mut id = self.active_id as i64;
+        let mut ordered = Vec::new();
+        while let Some(bin) = bins.get(&id) {
+            ordered.push(*bin);
+            id += step;
+        }
+        Ok(ordered)
+    }
+
+    /// Price of bin `id` as y per x in Q64.64, (1 + bin_step / 10000)^id.
+    fn bin_price_x64(&self, id: i64) -> u128 {
+        ((1.0 + self.bin_step as f64 / 10_000.0).powi(id as i32) * (1u128 << 64) as f64) as u128
+    }
+
+    /// The bin array holding the active bin and the next two in the swap's
+    /// direction. Selling x for y walks bins downwards.
+    pub fn bin_array_addresses(&self, lb_pair: &Pubkey, x_to_y: bool) -> [Pubkey; 3] {
+        let index = self.active_id.div_euclid(MAX_BIN_PER_ARRAY) as i64;
+        let step = if x_to_y { -1 } else { 1 };
+        [0, 1, 2].map(|offset| bin_array_address(lb_pair, index + step * offset))
+    }
+}
+
+pub fn bin_array_address(lb_pair: &Pubkey, index: i64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"bin_array", lb_pair.as_ref(), &index.to_le_bytes()],
+        &PROGRAM_ID,
+    ).0
+}
+
+fn event_authority_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], &PROGRAM_ID).0
+}
+
+/// Exact-in swap of `amount_in` for at least `min_amount_out`. Only bin
+/// arrays that exist on chain should be passed.
+#[allow(clippy::too_many_arguments)]
+pub fn swap_instruction(
+    user: &Pubkey,
+    lb_pair_id: &Pubkey,
+    lb_pair: &LbPair,
+    bin_arrays: &[Pubkey],
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Instruction {
+    let mut data = SWAP.to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+
+    // Optional accounts are passed as the program ID.
+    let mut accounts = vec![
+        AccountMeta::new(*lb_pair_id, false),
+        AccountMeta::new_readonly(PROGRAM_ID, false),
+        AccountMeta::new(lb_pair.reserve_x, false),
+        AccountMeta::new(lb_pair.reserve_y, false),
+        AccountMeta::new(*user_source, false),
+        AccountMeta::new(*user_destination, false),
+        AccountMeta::new_readonly(lb_pair.token_x_mint, false),
+        AccountMeta::new_readonly(lb_pair.token_y_mint, false),
+        AccountMeta::new(lb_pair.oracle, false),
+        AccountMeta::new_readonly(PROGRAM_ID, false),
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(event_authority_address(), false),
+        AccountMeta::new_readonly(PROGRAM_ID, false),
+    ];
+    accounts.extend(bin_arrays.iter().map(|bin_array| AccountMeta::new(*bin_array, false)));
+
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+    /// A SOL/USDC pair with 10bp bins, a 10000 base factor and the given
+    /// active bin.
+    fn sol_usdc(active_id: i32) -> LbPair {
+        let mut data = vec![0; 904];
+        data[..8].copy_from_slice(&LB_PAIR_ACCOUNT);
+        data[8..10].copy_from_slice(&10_000u16.to_le_bytes());
+        data[76..80].copy_from_slice(&active_id.to_le_bytes());
+        data[80..82].copy_from_slice(&10u16.to_le_bytes());
+        data[88..120].copy_from_slice(WSOL.as_ref());
+        data[120..152].copy_from_slice(USDC.as_ref());
+        data[152..184].copy_from_slice(Pubkey::new_unique().as_ref());
+        data[184..216].copy_from_slice(Pubkey::new_unique().as_ref());
+        data[552..584].copy_from_slice(Pubkey::new_unique().as_ref());
+        LbPair::from_account_data(&data).unwrap()
+    }
+
+    /// A BinArray whose bins each hold `amount_x` and `amount_y`, with
+    /// `prices` stored for the first bins and the rest left unset.
+    fn bin_array(index: i64, amount_x: u64, amount_y: u64, prices: &[u128]) -> Vec<u8> {
+        let mut data = vec![0; BINS_OFFSET + BIN_SIZE * MAX_BIN_PER_ARRAY as usize];
+        data[..8].copy_from_slice(&BIN_ARRAY_ACCOUNT);
+        data[8..16].copy_from_slice(&index.to_le_bytes());
+        for position in 0..MAX_BIN_PER_ARRAY as usize {
+            let bin = &mut data[BINS_OFFSET + position * BIN_SIZE..][..BIN_SIZE];
+            bin[0..8].copy_from_slice(&amount_x.to_le_bytes());
+            bin[8..16].copy_from_slice(&amount_y.to_le_bytes());
+            if let Some(price) = prices.get(position) {
+                bin[16..32].copy_from_slice(&price.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn reads_an_lb_pair_account() {
+        let pair = sol_usdc(-4_383);
+        assert_eq!((pair.active_id, pair.bin_step, pair.base_factor), (-4_383, 10, 10_000));
+        assert_eq!((pair.token_x_mint, pair.token_y_mint), (WSOL, USDC));
+        // 10000 * 10 * 10 / 1e9 = 0.1%
+        assert_eq!((pair.fee().numerator, pair.fee().denominator), (1_000_000, FEE_PRECISION));
+        assert!(LbPair::from_account_data(&[0; 904]).is_err());
+    }
+
+    #[test]
+    fn bin_arrays_follow_the_swap_direction() {
+        let lb_pair = Pubkey::new_unique();
+        let addresses = |indexes: [i64; 3]| indexes.map(|index| bin_array_address(&lb_pair, index));
+        // Bin -4383 lies in array -63, which covers bins -4410..=-4341.
+        let pair = sol_usdc(-4_383);
+        assert_eq!(pair.bin_array_addresses(&lb_pair, true), addresses([-63, -64, -65]));
+        assert_eq!(pair.bin_array_addresses(&lb_pair, false), addresses([-63, -62, -61]));
+        assert_eq!(sol_usdc(-1).bin_array_addresses(&lb_pair, false), addresses([-1, 0, 1]));
+        assert_eq!(sol_usdc(70).bin_array_addresses(&lb_pair, true), addresses([1, 0, -1]));
+    }
+
+    #[test]
+    fn swap_bins_walk_from_the_active_bin_until_a_gap() {
+        let pair = sol_usdc(1);
+        let stored = 18_465_190_817_891_443_058;
+        let arrays = [bin_array(0, 2_000_000_000, 300_000_000, &[0, stored]), bin_array(-1, 1_000_000_000, 150_000_000, &[])];
+
+        // Selling x walks down through array 0 into array -1 and stops below it.
+        let bins = pair.swap_bins(&arrays, true).unwrap();
+        assert_eq!(bins.len(), 72);
+        assert_eq!(bins[0], Bin { price_x64: stored, amount_out: 300_000_000 });
+        assert_eq!(bins[1], Bin { price_x64: 1 << 64, amount_out: 300_000_000 });
+        assert!(bins[2].price_x64 < 1 << 64);
+        assert_eq!(bins[2].amount_out, 150_000_000);
+
+        // Buying x walks up to the end of array 0, as array 1 was not passed.
+        let bins = pair.swap_bins(&arrays, false).unwrap();
+        assert_eq!(bins.len(), 69);
+        assert!(bins.iter().all(|bin| bin.amount_out == 2_000_000_000));
+        assert!(bins.windows(2).skip(1).all(|neighbours| neighbours[0].price_x64 < neighbours[1].price_x64));
+
+        assert!(pair.swap_bins(&[vec![0; 100]], true).is_err());
+    }
+
+    #[test]
+    fn decodes_the_swap_we_build() {
+        let pair = sol_usdc(-4_383);
+        let (lb_pair, user, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let bin_arrays = pair.bin_array_addresses(&lb_pair, false);
+        let swap = swap_instruction(&user, &lb_pair, &pair, &bin_arrays, &user_usdc, &user_sol, 150_000_000, 990_000_000);
+        let swap = fixtures::with_inner(fixtures::from_instruction(swap), vec![
+            (2, fixtures::transfer_checked(user_usdc, USDC, pair.reserve_y, user, 150_000_000, 6)),
+            (2, fixtures::transfer_checked(pair.reserve_x, WSOL, user_sol, lb_pair, 1_001_250_044, 9)),
+        ]);
+        let transaction = fixtures::transaction(user, vec![swap]);
+
+        let swaps = MeteoraDlmmDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!((swap.dex, swap.pool_id, swap.owner), (Dex::MeteoraDlmm, lb_pair, user));
+        assert_eq!((swap.input_mint, swap.input_amount), (USDC, 150_000_000));
+        assert_eq!((swap.output_mint, swap.output_amount), (WSOL, 1_001_250_044));
+    }
+
+    #[test]
+    fn takes_the_direction_from_the_source_mint() {
+        let pair = sol_usdc(-4_383);
+        let (lb_pair, user, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let swap = swap_instruction(&user, &lb_pair, &pair, &[], &user_sol, &user_usdc, 1_000_000_000, 148_000_000);
+        let mut transaction = fixtures::transaction(user, vec![fixtures::from_instruction(swap)]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &WSOL, &user, Some(1_000_000_000), Some(0));
+
+        let swaps = MeteoraDlmmDecoder.decode(&transaction);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (WSOL, 1_000_000_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (USDC, 148_000_000));
+    }
+}
@@ -0,0 +1,436 @@
+pub mod inference;
+pub mod jupiter;
+pub mod meteora_dlmm;
+pub mod orca_whirlpool;
+pub mod pump_fun;
+pub mod raydium_amm_v4;
+pub mod raydium_clmm;
+pub mod raydium_cpmm;
+
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use log::debug;
+use crate::transaction::{Invocation, ParsedInstruction, ParsedTransaction};
+use crate::types::{Dex, TradeDetails, TradeType};
+use std::collections::HashMap;
+use std::ops::Range;
+
+const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+const TRANSFER: u8 = 3;
+const TRANSFER_CHECKED: u8 = 12;
+
+/// A swap decoded from a transaction, independent of the venue it ran on.
+#[derive(Debug, Clone)]
+pub struct SwapEvent {
+    pub dex: Dex,
+    pub program_id: Pubkey,
+    pub pool_id: Pubkey,
+    /// Wallet that signed for the swapped tokens.
+    pub owner: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    /// Top-level instruction the swap ran under, directly or through CPI.
+    pub outer_index: usize,
+    /// Position of the swap instruction among the transaction's invocations.
+    pub invocation: usize,
+    /// Built from balance changes rather than a decoded instruction.
+    pub inferred: bool,
+}
+
+impl SwapEvent {
+    pub fn into_trade(self, signature: &str) -> TradeDetails {
+        TradeDetails {
+            signature: signature.to_string(),
+            target: self.owner,
+            dex: self.dex,
+            pool_id: self.pool_id,
+            input_token: self.input_mint,
+            output_token: self.output_mint,
+            input_amount: self.input_amount,
+            output_amount: self.output_amount,
+            trade_type: TradeType::Swap,
+            inferred: self.inferred,
+        }
+    }
+}
+
+/// Turns the instructions of one venue into swap events.
+pub trait DexDecoder: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Programs whose instructions this decoder understands.
+    fn program_ids(&self) -> Vec<Pubkey>;
+
+    /// Every swap this venue executed in the transaction, for any owner,
+    /// including ones invoked through other programs.
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent>;
+
+    /// Routers report a whole route as one swap; the venue swaps they invoke
+    /// are its legs and are not reported separately.
+    fn is_router(&self) -> bool {
+        false
+    }
+}
+
+/// Decoders keyed by program ID. A transaction is handed to each decoder
+/// whose program it invokes.
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn DexDecoder>>,
+    by_program: HashMap<Pubkey, usize>,
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        DecoderRegistry {
+            decoders: Vec::new(),
+            by_program: HashMap::new(),
+        }
+    }
+
+    /// Registry with every venue the bot knows how to decode.
+    pub fn with_defaults() -> Self {
+        let mut registry = DecoderRegistry::new();
+        registry.register(Box::new(raydium_amm_v4::RaydiumAmmV4Decoder));
+        registry.register(Box::new(raydium_cpmm::RaydiumCpmmDecoder));
+        registry.register(Box::new(raydium_clmm::RaydiumClmmDecoder));
+        registry.register(Box::new(jupiter::JupiterDecoder));
+        registry.register(Box::new(pump_fun::PumpFunDecoder));
+        registry.register(Box::new(orca_whirlpool::OrcaWhirlpoolDecoder));
+        registry.register(Box::new(meteora_dlmm::MeteoraDlmmDecoder));
+        registry
+    }
+
+    pub fn register(&mut self, decoder: Box<dyn DexDecoder>) {
+        let index = self.decoders.len();
+        let program_ids = decoder.program_ids();
+        debug!("Registering {} decoder for {:?}", decoder.name(), program_ids);
+        for program_id in program_ids {
+            self.by_program.insert(program_id, index);
+        }
+        self.decoders.push(decoder);
+    }
+
+    /// Swaps made by `target` in execution order, whether called directly or
+    /// nested inside other programs. Falls back to a trade inferred from the
+    /// target's balance changes when no registered venue recognises the
+    /// transaction.
+    pub fn decode(&self, transaction: &ParsedTransaction, target: Pubkey) -> Vec<SwapEvent> {
+        let mut invoked = Vec::new();
+        for invocation in transaction.invocations() {
+            if let Some(&index) = self.by_program.get(&invocation.instruction.program_id) {
+                if !invoked.contains(&index) {
+                    invoked.push(index);
+                }
+            }
+        }
+
+        let decoded: Vec<(bool, SwapEvent)> = invoked.iter()
+            .flat_map(|index| {
+                let decoder = &self.decoders[*index];
+                decoder.decode(transaction).into_iter().map(|event| (decoder.is_router(), event))
+            })
+            .collect();
+
+        // Swaps the route instruction invoked are its legs. Invocations list
+        // each instruction's calls right after it, so those are the ones in
+        // the route's span; swaps beside the route under the same top-level
+        // instruction stay separate.
+        let invocations = transaction.invocations();
+        let spans: Vec<(usize, Range<usize>)> = decoded.iter()
+            .filter(|(is_router, _)| *is_router)
+            .map(|(_, route)| {
+                let calls = invocations.get(route.invocation).map_or(0, |invocation| invocation.children.len());
+                (route.invocation, route.invocation + 1..route.invocation + 1 + calls)
+            })
+            .collect();
+        let mut legs: HashMap<usize, Vec<SwapEvent>> = HashMap::new();
+        let mut swaps = Vec::new();
+        let mut routes = Vec::new();
+        for (is_router, event) in decoded {
+            if is_router {
+                routes.push(event);
+            } else if let Some((route, _)) = spans.iter().find(|(_, span)| span.contains(&event.invocation)) {
+                legs.entry(*route).or_default().push(event);
+            } else {
+                swaps.push(event);
+            }
+        }
+        for route in routes {
+            swaps.push(match legs.get(&route.invocation) {
+                Some(route_legs) => single_venue(route, route_legs),
+                None => route,
+            });
+        }
+        swaps.sort_by_key(|event| event.invocation);
+
+        let events: Vec<SwapEvent> = swaps.into_iter()
+            .filter(|event| event.owner == target)
+            .collect();
+
+        if events.is_empty() {
+            inference::infer_swap(transaction, target).into_iter().collect()
+        } else {
+            events
+        }
+    }
+}
+
+/// A route whose legs all swap the route's own mints on one pool is copied
+/// on that pool, keeping the route's net amounts; routers have no executor.
+fn single_venue(mut route: SwapEvent, legs: &[SwapEvent]) -> SwapEvent {
+    let Some(first) = legs.first() else {
+        return route;
+    };
+    let single_hop = legs.iter().all(|leg| {
+        leg.dex == first.dex
+            && leg.pool_id == first.pool_id
+            && leg.input_mint == route.input_mint
+            && leg.output_mint == route.output_mint
+    });
+    if single_hop {
+        route.dex = first.dex;
+        route.program_id = first.program_id;
+        route.pool_id = first.pool_id;
+    }
+    route
+}
+
+/// Amount the swap moved from the user's source account into the pool
+/// vault. Balance changes cover the whole transaction, so they are only
+/// used when the swap made no visible transfer and is the only one on the
+/// account.
+fn spent_amount(
+    transaction: &ParsedTransaction,
+    invocation: &Invocation,
+    user_source: &Pubkey,
+    vault: &Pubkey,
+) -> Option<u64> {
+    transferred(invocation, user_source, vault).or_else(|| {
+        swap_balance_change(transaction, invocation, user_source)
+            .filter(|change| *change < 0)
+            .or_else(|| swap_balance_change(transaction, invocation, vault).filter(|change| *change > 0))
+            .map(|change| change.unsigned_abs() as u64)
+    })
+}
+
+/// Amount the swap paid out of the pool vault into the user's destination
+/// account, with the same fallback as `spent_amount`.
+fn received_amount(
+    transaction: &ParsedTransaction,
+    invocation: &Invocation,
+    user_destination: &Pubkey,
+    vault: &Pubkey,
+) -> Option<u64> {
+    transferred(invocation, vault, user_destination).or_else(|| {
+        swap_balance_change(transaction, invocation, user_destination)
+            .filter(|change| *change > 0)
+            .or_else(|| swap_balance_change(transaction, invocation, vault).filter(|change| *change < 0))
+            .map(|change| change.unsigned_abs() as u64)
+    })
+}
+
+/// Total the swap's own token `transfer` and `transferChecked` calls moved
+/// from `source` to `destination`.
+fn transferred(invocation: &Invocation, source: &Pubkey, destination: &Pubkey) -> Option<u64> {
+    let mut total = None;
+    for call in invocation.children {
+        if call.program_id != spl_token::id() && call.program_id != TOKEN_2022_PROGRAM_ID {
+            continue;
+        }
+        let (from, to) = match (call.data.first(), call.accounts.as_slice()) {
+            (Some(&TRANSFER), [from, to, ..]) => (from, to),
+            (Some(&TRANSFER_CHECKED), [from, _mint, to, ..]) => (from, to),
+            _ => continue,
+        };
+        if from == source && to == destination {
+            let amount = u64::from_le_bytes(call.data.get(1..9)?.try_into().ok()?);
+            total = Some(total.unwrap_or(0) + amount);
+        }
+    }
+    total
+}
+
+/// Balance change of a token account over the transaction, when this swap
+/// is the only one that touched it.
+fn swap_balance_change(transaction: &ParsedTransaction, invocation: &Invocation, account: &Pubkey) -> Option<i128> {
+    if sole_swap_on(transaction, invocation, account) {
+        transaction.token_balance_change(account)
+    } else {
+        None
+    }
+}
+
+/// Whether no instruction but this swap uses `account`, leaving out the
+/// calls the swap made and the programs that called it.
+fn sole_swap_on(transaction: &ParsedTransaction, invocation: &Invocation, account: &Pubkey) -> bool {
+    !transaction.invocations().iter().any(|other| {
+        !std::ptr::eq(other.instruction, invocation.instruction)
+            && !is_infrastructure_program(&other.instruction.program_id)
+            && other.instruction.accounts.contains(account)
+            && !includes(invocation.children, other.instruction)
+            && !includes(other.children, invocation.instruction)
+    })
+}
+
+fn includes(instructions: &[ParsedInstruction], instruction: &ParsedInstruction) -> bool {
+    instructions.iter().any(|candidate| std::ptr::eq(candidate, instruction))
+}
+
+/// Infrastructure programs that never identify the venue of a swap.
+fn is_infrastructure_program(program_id: &Pubkey) -> bool {
+    *program_id == solana_sdk::compute_budget::id()
+        || *program_id == solana_sdk::system_program::id()
+        || *program_id == spl_token::id()
+        || *program_id == TOKEN_2022_PROGRAM_ID
+        || *program_id == spl_associated_token_account::id()
+}
+
+/// Anchor instruction discriminator and the little-endian u64 arguments that
+/// follow it.
+fn anchor_args<const N: usize>(data: &[u8], discriminator: &[u8; 8]) -> Option<[u64; N]> {
+    if data.len() < 8 + N * 8 || data[..8] != discriminator[..] {
+        return None;
+    }
+    let mut args = [0u64; N];
+    for (index, arg) in args.iter_mut().enumerate() {
+        let start = 8 + index * 8;
+        *arg = u64::from_le_bytes(data[start..start + 8].try_into().ok()?);
+    }
+    Some(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// AMM v4 `swap_base_in` accounts for a pool without the target orders
+    /// account.
+    fn amm_v4_swap(pool: Pubkey, coin_vault: Pubkey, pc_vault: Pubkey, source: Pubkey, destination: Pubkey, owner: Pubkey, amount_in: u64) -> ParsedInstruction {
+        let mut accounts: Vec<Pubkey> = (0..18).map(|_| Pubkey::new_unique()).collect();
+        accounts[0] = spl_token::id();
+        accounts[1] = pool;
+        accounts[5] = coin_vault;
+        accounts[6] = pc_vault;
+        accounts[15] = source;
+        accounts[16] = destination;
+        accounts[17] = owner;
+        let mut data = vec![9];
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&1u64.to_le_bytes());
+        fixtures::instruction(raydium_amm_v4::PROGRAM_ID, accounts, data)
+    }
+
+    #[test]
+    fn two_swaps_through_one_account_keep_their_own_amounts() {
+        // A bot buys in pool A and sells back in pool B through the same
+        // token accounts. The token account nets to zero and the SOL account
+        // to the profit, so neither balance change is either swap's.
+        let (target, bot) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (user_sol, user_token, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (pool_a, coin_a, pc_a) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (pool_b, coin_b, pc_b) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let native_mint = spl_token::native_mint::id();
+
+        let buy = amm_v4_swap(pool_a, coin_a, pc_a, user_sol, user_token, target, 1_000_000_000);
+        let sell = amm_v4_swap(pool_b, coin_b, pc_b, user_token, user_sol, target, 2_588_000);
+        let authority = Pubkey::new_unique();
+        let outer = fixtures::with_inner(
+            fixtures::instruction(bot, vec![target, user_sol, user_token, pool_a, pool_b], vec![1]),
+            vec![
+                (2, buy),
+                (3, fixtures::transfer(user_sol, pc_a, target, 1_000_000_000)),
+                (3, fixtures::transfer(coin_a, user_token, authority, 2_588_000)),
+                (2, sell),
+                (3, fixtures::transfer(user_token, coin_b, target, 2_588_000)),
+                (3, fixtures::transfer(pc_b, user_sol, authority, 1_010_000_000)),
+            ],
+        );
+        let mut transaction = fixtures::transaction(target, vec![outer]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &native_mint, &target, Some(1_000_000_000), Some(1_010_000_000));
+        fixtures::set_token_balance(&mut transaction, &user_token, &mint, &target, Some(0), Some(0));
+        fixtures::set_token_balance(&mut transaction, &coin_a, &mint, &authority, Some(90_000_000_000), Some(89_997_412_000));
+        fixtures::set_token_balance(&mut transaction, &pc_a, &native_mint, &authority, Some(35_000_000_000), Some(36_000_000_000));
+        fixtures::set_token_balance(&mut transaction, &coin_b, &mint, &authority, Some(80_000_000_000), Some(80_002_588_000));
+        fixtures::set_token_balance(&mut transaction, &pc_b, &native_mint, &authority, Some(32_000_000_000), Some(30_990_000_000));
+
+        let swaps: Vec<_> = DecoderRegistry::with_defaults().decode(&transaction, target).into_iter()
+            .map(|swap| (swap.pool_id, swap.input_mint, swap.input_amount, swap.output_mint, swap.output_amount))
+            .collect();
+        assert_eq!(swaps, vec![
+            (pool_a, native_mint, 1_000_000_000, mint, 2_588_000),
+            (pool_b, mint, 2_588_000, native_mint, 1_010_000_000),
+        ]);
+    }
+
+    #[test]
+    fn only_swaps_a_route_invoked_are_its_legs() {
+        // A bot instruction runs a Jupiter route over pool A and, beside it,
+        // a direct swap on pool B. Both sit under the same top-level
+        // instruction, but only the first is a leg of the route.
+        let (target, bot) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (user_sol, user_token, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (pool_a, coin_a, pc_a) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (pool_b, coin_b, pc_b) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let native_mint = spl_token::native_mint::id();
+        let authority = Pubkey::new_unique();
+
+        // route(route_plan, in_amount, quoted_out_amount, slippage_bps, platform_fee_bps)
+        let mut route_data = vec![229, 23, 203, 151, 122, 227, 173, 42, 1, 0, 0, 0, 7, 100, 0, 1];
+        route_data.extend_from_slice(&500_000_000u64.to_le_bytes());
+        route_data.extend_from_slice(&1_294_000u64.to_le_bytes());
+        route_data.extend_from_slice(&[50, 0, 0]);
+        let route = fixtures::instruction(jupiter::PROGRAM_ID, vec![
+            spl_token::id(), target, user_sol, user_token, jupiter::PROGRAM_ID, mint,
+        ], route_data);
+
+        let outer = fixtures::with_inner(
+            fixtures::instruction(bot, vec![target, user_sol, user_token], vec![1]),
+            vec![
+                (2, route),
+                (3, amm_v4_swap(pool_a, coin_a, pc_a, user_sol, user_token, target, 500_000_000)),
+                (4, fixtures::transfer(user_sol, pc_a, target, 500_000_000)),
+                (4, fixtures::transfer(coin_a, user_token, authority, 1_294_000)),
+                (2, amm_v4_swap(pool_b, coin_b, pc_b, user_sol, user_token, target, 1_000_000_000)),
+                (3, fixtures::transfer(user_sol, pc_b, target, 1_000_000_000)),
+                (3, fixtures::transfer(coin_b, user_token, authority, 2_588_000)),
+            ],
+        );
+        let mut transaction = fixtures::transaction(target, vec![outer]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &native_mint, &target, Some(1_500_000_000), Some(0));
+        fixtures::set_token_balance(&mut transaction, &user_token, &mint, &target, Some(0), Some(3_882_000));
+        for (vault, vault_mint) in [(coin_a, mint), (pc_a, native_mint), (coin_b, mint), (pc_b, native_mint)] {
+            fixtures::set_token_balance(&mut transaction, &vault, &vault_mint, &authority, Some(1), Some(1));
+        }
+
+        let swaps: Vec<_> = DecoderRegistry::with_defaults().decode(&transaction, target).into_iter()
+            .map(|swap| (swap.dex, swap.pool_id, swap.input_amount, swap.output_amount))
+            .collect();
+        assert_eq!(swaps, vec![
+            (Dex::RaydiumAmmV4, pool_a, 500_000_000, 1_294_000),
+            (Dex::RaydiumAmmV4, pool_b, 1_000_000_000, 2_588_000),
+        ]);
+    }
+
+    #[test]
+    fn balance_changes_price_a_swap_only_when_it_is_alone_on_the_account() {
+        // Without transfers or logs, a lone swap still reads the vaults.
+        let target = Pubkey::new_unique();
+        let (user_sol, user_token, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (pool, coin_vault, pc_vault) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let native_mint = spl_token::native_mint::id();
+        let swap = amm_v4_swap(pool, coin_vault, pc_vault, user_sol, user_token, target, 1_000_000_000);
+        let mut transaction = fixtures::transaction(target, vec![swap]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &native_mint, &target, Some(1_000_000_000), Some(0));
+        fixtures::set_token_balance(&mut transaction, &user_token, &mint, &target, Some(0), Some(2_588_000));
+        fixtures::set_token_balance(&mut transaction, &coin_vault, &mint, &target, Some(90_000_000_000), Some(89_997_412_000));
+        fixtures::set_token_balance(&mut transaction, &pc_vault, &native_mint, &target, Some(35_000_000_000), Some(36_000_000_000));
+
+        let swaps = DecoderRegistry::with_defaults().decode(&transaction, target);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (native_mint, 1_000_000_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (mint, 2_588_000));
+    }
+}
@@ -0,0 +1,306 @@
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use anyhow::{Result, bail};
+use crate::quote::Fee;
+use crate::transaction::{Invocation, ParsedTransaction};
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, received_amount, spent_amount};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+const SWAP_V2: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+const WHIRLPOOL_ACCOUNT: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+
+const TICK_ARRAY_SIZE: i32 = 88;
+const MIN_SQRT_PRICE: u128 = 4_295_048_016;
+const MAX_SQRT_PRICE: u128 = 79_226_673_515_401_279_992_447_579_055;
+
+/// Orca Whirlpools (concentrated liquidity).
+pub struct OrcaWhirlpoolDecoder;
+
+impl DexDecoder for OrcaWhirlpoolDecoder {
+    fn name(&self) -> &'static str {
+        "orca-whirlpool"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        transaction.invocations().iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter_map(|invocation| decode_swap(transaction, invocation))
+            .collect()
+    }
+}
+
+fn decode_swap(transaction: &ParsedTransaction, invocation: &Invocation) -> Option<SwapEvent> {
+    // swap / swap_v2(amount, other_amount_threshold, sqrt_price_limit: u128,
+    //                amount_specified_is_input: bool, a_to_b: bool)
+    let instruction = invocation.instruction;
+    let data = &instruction.data;
+    if data.len() < 42 {
+        return None;
+    }
+    let is_v2 = match data[..8].try_into().ok()? {
+        SWAP => false,
+        SWAP_V2 => true,
+        _ => return None,
+    };
+    let amount = u64::from_le_bytes(data[8..16].try_into().ok()?);
+    let other_amount_threshold = u64::from_le_bytes(data[16..24].try_into().ok()?);
+    let amount_specified_is_input = data[40] != 0;
+    let a_to_b = data[41] != 0;
+    let (instruction_in, instruction_out) = if amount_specified_is_input {
+        (amount, other_amount_threshold)
+    } else {
+        (other_amount_threshold, amount)
+    };
+
+    // swap: [token_program, token_authority, whirlpool, token_owner_account_a,
+    //        token_vault_a, token_owner_account_b, token_vault_b, tick arrays x3, oracle]
+    // swap_v2: [token_program_a, token_program_b, memo_program, token_authority,
+    //           whirlpool, token_mint_a, token_mint_b, token_owner_account_a,
+    //           token_vault_a, token_owner_account_b, token_vault_b, tick arrays x3, oracle]
+    let accounts = &instruction.accounts;
+    let (authority, pool, owner_a) = if is_v2 { (3, 4, 7) } else { (1, 2, 3) };
+    if accounts.len() < owner_a + 4 {
+        return None;
+    }
+    let owner = accounts[authority];
+    let pool_id = accounts[pool];
+    let (user_a, vault_a) = (accounts[owner_a], accounts[owner_a + 1]);
+    let (user_b, vault_b) = (accounts[owner_a + 2], accounts[owner_a + 3]);
+
+    let (mint_a, mint_b) = if is_v2 {
+        (accounts[5], accounts[6])
+    } else {
+        (
+            transaction.token_mint(&vault_a).or_else(|| transaction.token_mint(&user_a))?,
+            transaction.token_mint(&vault_b).or_else(|| transaction.token_mint(&user_b))?,
+        )
+    };
+
+    let (user_source, input_vault, input_mint, user_destination, output_vault, output_mint) = if a_to_b {
+        (user_a, vault_a, mint_a, user_b, vault_b, mint_b)
+    } else {
+        (user_b, vault_b, mint_b, user_a, vault_a, mint_a)
+    };
+
+    Some(SwapEvent {
+        dex: Dex::OrcaWhirlpool,
+        program_id: PROGRAM_ID,
+        pool_id,
+        owner,
+        input_mint,
+        output_mint,
+        input_amount: spent_amount(transaction, invocation, &user_source, &input_vault).unwrap_or(instruction_in),
+        output_amount: received_amount(transaction, invocation, &user_destination, &output_vault).unwrap_or(instruction_out),
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+/// The parts of a `Whirlpool` account needed to build a swap.
+#[derive(Debug, Clone)]
+pub struct Whirlpool {
+    pub tick_spacing: u16,
+    /// Swap fee in hundredths of a basis point.
+    pub fee_rate: u16,
+    /// Liquidity in the current tick range.
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+}
+
+impl Whirlpool {
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < 245 || data[..8] != WHIRLPOOL_ACCOUNT {
+            bail!("Not a whirlpool account");
+        }
+        Ok(Whirlpool {
+            tick_spacing: u16::from_le_bytes(data[41..43].try_into()?),
+            fee_rate: u16::from_le_bytes(data[45..47].try_into()?),
+            liquidity: u128::from_le_bytes(data[49..65].try_into()?),
+            sqrt_price: u128::from_le_bytes(data[65..81].try_into()?),
+            tick_current_index: i32::from_le_bytes(data[81..85].try_into()?),
+            token_mint_a: Pubkey::try_from(&data[101..133])?,
+            token_vault_a: Pubkey::try_from(&data[133..165])?,
+            token_mint_b: Pubkey::try_from(&data[181..213])?,
+            token_vault_b: Pubkey::try_from(&data[213..245])?,
+        })
+    }
+
+    pub fn fee(&self) -> Fee {
+        Fee { numerator: self.fee_rate as u64, denominator: 1_000_000 }
+    }
+
+    /// The three tick arrays a swap may cross, starting with the one holding
+    /// the current tick and moving in the direction of the swap.
+    pub fn tick_array_addresses(&self, whirlpool: &Pubkey, a_to_b: bool) -> [Pubkey; 3] {
+        let ticks_per_array = self.tick_spacing as i32 * TICK_ARRAY_SIZE;
+        let start = self.tick_current_index.div_euclid(ticks_per_array) * ticks_per_array;
+        let step = if a_to_b { -ticks_per_array } else { ticks_per_array };
+        [0, 1, 2].map(|offset| tick_array_address(whirlpool, start + step * offset))
+    }
+}
+
+pub fn tick_array_address(whirlpool: &Pubkey, start_tick_index: i32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"tick_array", whirlpool.as_ref(), start_tick_index.to_string().as_bytes()],
+        &PROGRAM_ID,
+    ).0
+}
+
+pub fn oracle_address(whirlpool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", whirlpool.as_ref()], &PROGRAM_ID).0
+}
+
+/// Exact-in swap of `amount_in` from `user_source` for at least
+/// `min_amount_out` into `user_destination`.
+#[allow(clippy::too_many_arguments)]
+pub fn swap_instruction(
+    user: &Pubkey,
+    whirlpool_id: &Pubkey,
+    whirlpool: &Whirlpool,
+    tick_arrays: [Pubkey; 3],
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    a_to_b: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Instruction {
+    let (owner_a, owner_b) = if a_to_b {
+        (user_source, user_destination)
+    } else {
+        (user_destination, user_source)
+    };
+    let sqrt_price_limit = if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE };
+
+    let mut data = SWAP.to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+    data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+    data.push(1); // amount_specified_is_input
+    data.push(a_to_b as u8);
+
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new(*whirlpool_id, false),
+            AccountMeta::new(*owner_a, false),
+            AccountMeta::new(whirlpool.token_vault_a, false),
+            AccountMeta::new(*owner_b, false),
+            AccountMeta::new(whirlpool.token_vault_b, false),
+            AccountMeta::new(tick_arrays[0], false),
+            AccountMeta::new(tick_arrays[1], false),
+            AccountMeta::new(tick_arrays[2], false),
+            AccountMeta::new_readonly(oracle_address(whirlpool_id), false),
+        ],
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    const SOL_USDC: Pubkey = pubkey!("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE");
+
+    /// A SOL/USDC whirlpool account with 64 tick spacing and a 0.04% fee.
+    fn sol_usdc(tick_current_index: i32) -> Whirlpool {
+        let mut data = vec![0; 653];
+        data[..8].copy_from_slice(&WHIRLPOOL_ACCOUNT);
+        data[41..43].copy_from_slice(&64u16.to_le_bytes());
+        data[45..47].copy_from_slice(&400u16.to_le_bytes());
+        data[49..65].copy_from_slice(&2_185_174_375_621_u128.to_le_bytes());
+        data[65..81].copy_from_slice(&7_104_571_424_318_203_238_u128.to_le_bytes());
+        data[81..85].copy_from_slice(&tick_current_index.to_le_bytes());
+        data[101..133].copy_from_slice(WSOL.as_ref());
+        data[133..165].copy_from_slice(Pubkey::new_unique().as_ref());
+        data[181..213].copy_from_slice(USDC.as_ref());
+        data[213..245].copy_from_slice(Pubkey::new_unique().as_ref());
+        Whirlpool::from_account_data(&data).unwrap()
+    }
+
+    #[test]
+    fn reads_a_whirlpool_account() {
+        let whirlpool = sol_usdc(-18_995);
+        assert_eq!((whirlpool.tick_spacing, whirlpool.tick_current_index), (64, -18_995));
+        assert_eq!((whirlpool.token_mint_a, whirlpool.token_mint_b), (WSOL, USDC));
+        assert_eq!(whirlpool.liquidity, 2_185_174_375_621);
+        assert_eq!(whirlpool.sqrt_price, 7_104_571_424_318_203_238);
+        assert_eq!((whirlpool.fee().numerator, whirlpool.fee().denominator), (400, 1_000_000));
+        assert!(Whirlpool::from_account_data(&[0; 653]).is_err());
+    }
+
+    #[test]
+    fn tick_arrays_follow_the_swap_direction() {
+        // 64 * 88 = 5632 ticks per array; -18995 lies in the one from -22528.
+        let whirlpool = sol_usdc(-18_995);
+        let addresses = |starts: [i32; 3]| starts.map(|start| tick_array_address(&SOL_USDC, start));
+        assert_eq!(whirlpool.tick_array_addresses(&SOL_USDC, true), addresses([-22_528, -28_160, -33_792]));
+        assert_eq!(whirlpool.tick_array_addresses(&SOL_USDC, false), addresses([-22_528, -16_896, -11_264]));
+        // A tick on an array boundary starts that array.
+        assert_eq!(sol_usdc(5_632).tick_array_addresses(&SOL_USDC, false)[0], tick_array_address(&SOL_USDC, 5_632));
+    }
+
+    #[test]
+    fn decodes_the_swap_we_build() {
+        let whirlpool = sol_usdc(-18_995);
+        let (user, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let tick_arrays = whirlpool.tick_array_addresses(&SOL_USDC, false);
+        let swap = swap_instruction(&user, &SOL_USDC, &whirlpool, tick_arrays, &user_usdc, &user_sol, false, 150_000_000, 995_000_000);
+        let swap = fixtures::with_inner(fixtures::from_instruction(swap), vec![
+            (2, fixtures::transfer(whirlpool.token_vault_a, user_sol, SOL_USDC, 1_003_144_112)),
+            (2, fixtures::transfer(user_usdc, whirlpool.token_vault_b, user, 150_000_000)),
+        ]);
+        let mut transaction = fixtures::transaction(user, vec![swap]);
+        fixtures::set_token_balance(&mut transaction, &whirlpool.token_vault_a, &WSOL, &SOL_USDC, Some(60_000_000_000), Some(58_996_855_888));
+        fixtures::set_token_balance(&mut transaction, &whirlpool.token_vault_b, &USDC, &SOL_USDC, Some(9_000_000_000), Some(9_150_000_000));
+
+        let swaps = OrcaWhirlpoolDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!((swap.dex, swap.pool_id, swap.owner), (Dex::OrcaWhirlpool, SOL_USDC, user));
+        assert_eq!((swap.input_mint, swap.input_amount), (USDC, 150_000_000));
+        assert_eq!((swap.output_mint, swap.output_amount), (WSOL, 1_003_144_112));
+    }
+
+    #[test]
+    fn decodes_an_exact_out_swap_v2_from_balances() {
+        let (user, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (vault_a, vault_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = SWAP_V2.to_vec();
+        data.extend_from_slice(&150_000_000u64.to_le_bytes());
+        data.extend_from_slice(&1_010_000_000u64.to_le_bytes());
+        data.extend_from_slice(&MAX_SQRT_PRICE.to_le_bytes());
+        data.extend_from_slice(&[0, 1, 0]); // exact out, a_to_b, no remaining accounts info
+        let mut accounts = vec![
+            spl_token::id(), spl_token::id(), Pubkey::new_unique(), user, SOL_USDC, WSOL, USDC,
+            user_sol, vault_a, user_usdc, vault_b,
+        ];
+        accounts.extend((0..4).map(|_| Pubkey::new_unique()));
+        let mut transaction = fixtures::transaction(user, vec![fixtures::instruction(PROGRAM_ID, accounts, data)]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &WSOL, &user, Some(2_000_000_000), Some(996_412_020));
+        fixtures::set_token_balance(&mut transaction, &user_usdc, &USDC, &user, Some(0), Some(150_000_000));
+
+        let swaps = OrcaWhirlpoolDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (WSOL, 1_003_587_980));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (USDC, 150_000_000));
+    }
+}
@@ -0,0 +1,352 @@
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use anyhow::{Result, anyhow, bail};
+use log::debug;
+use crate::transaction::{Invocation, ParsedInstruction, ParsedTransaction};
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, swap_balance_change};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P");
+
+const BUY: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+const SELL: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+const BONDING_CURVE_ACCOUNT: [u8; 8] = [23, 183, 248, 55, 96, 216, 172, 96];
+
+/// Anchor's self-CPI event tag followed by the `TradeEvent` discriminator.
+const EVENT_IX_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+const TRADE_EVENT: [u8; 8] = [189, 219, 127, 211, 78, 230, 97, 238];
+
+/// Protocol plus creator fee charged on the SOL side of every trade.
+pub const FEE_BPS: u64 = 100;
+
+/// Offset of `fee_recipient` in the `Global` account:
+/// discriminator, initialized, authority.
+const GLOBAL_FEE_RECIPIENT_OFFSET: usize = 8 + 1 + 32;
+
+/// Buys and sells against pump.fun bonding curves.
+pub struct PumpFunDecoder;
+
+impl DexDecoder for PumpFunDecoder {
+    fn name(&self) -> &'static str {
+        "pump-fun"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        transaction.invocations().iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter_map(|invocation| decode_trade(transaction, invocation))
+            .collect()
+    }
+}
+
+/// Fields of the `TradeEvent` the program emits for every buy and sell.
+#[derive(Debug)]
+struct TradeEvent {
+    mint: Pubkey,
+    sol_amount: u64,
+    token_amount: u64,
+    is_buy: bool,
+}
+
+fn parse_trade_event(instruction: &ParsedInstruction) -> Option<TradeEvent> {
+    let data = &instruction.data;
+    if instruction.program_id != PROGRAM_ID
+        || data.len() < 65
+        || data[..8] != EVENT_IX_TAG
+        || data[8..16] != TRADE_EVENT
+    {
+        return None;
+    }
+    Some(TradeEvent {
+        mint: Pubkey::try_from(&data[16..48]).ok()?,
+        sol_amount: u64::from_le_bytes(data[48..56].try_into().ok()?),
+        token_amount: u64::from_le_bytes(data[56..64].try_into().ok()?),
+        is_buy: data[64] != 0,
+    })
+}
+
+fn decode_trade(transaction: &ParsedTransaction, invocation: &Invocation) -> Option<SwapEvent> {
+    // buy(amount, max_sol_cost) / sell(amount, min_sol_output)
+    let instruction = invocation.instruction;
+    let data = &instruction.data;
+    if data.len() < 24 {
+        return None;
+    }
+    let is_buy = match data[..8].try_into().ok()? {
+        BUY => true,
+        SELL => false,
+        _ => return None,
+    };
+    let token_limit = u64::from_le_bytes(data[8..16].try_into().ok()?);
+    let sol_limit = u64::from_le_bytes(data[16..24].try_into().ok()?);
+
+    // [global, fee_recipient, mint, bonding_curve, associated_bonding_curve,
+    //  associated_user, user, ..]
+    let accounts = &instruction.accounts;
+    if accounts.len() < 7 {
+        return None;
+    }
+    let mint = accounts[2];
+    let bonding_curve = accounts[3];
+    let associated_user = accounts[5];
+    let owner = accounts[6];
+
+    let event = invocation.children.iter()
+        .filter_map(parse_trade_event)
+        .find(|event| event.mint == mint && event.is_buy == is_buy);
+    debug!("pump.fun trade event: {:?}", event);
+
+    let token_amount = event.as_ref()
+        .map(|event| event.token_amount)
+        .or_else(|| swap_balance_change(transaction, invocation, &associated_user).map(|change| change.unsigned_abs() as u64))
+        .unwrap_or(token_limit);
+    // Without the event only the SOL limit is known.
+    let sol_amount = event.as_ref()
+        .map(|event| event.sol_amount)
+        .unwrap_or(sol_limit);
+
+    let native_mint = spl_token::native_mint::id();
+    let (input_mint, input_amount, output_mint, output_amount) = if is_buy {
+        (native_mint, sol_amount, mint, token_amount)
+    } else {
+        (mint, token_amount, native_mint, sol_amount)
+    };
+
+    Some(SwapEvent {
+        dex: Dex::PumpFun,
+        program_id: PROGRAM_ID,
+        pool_id: bonding_curve,
+        owner,
+        input_mint,
+        output_mint,
+        input_amount,
+        output_amount,
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+/// The `BondingCurve` account of a pump.fun mint.
+#[derive(Debug, Clone)]
+pub struct BondingCurve {
+    pub virtual_token_reserves: u64,
+    pub virtual_sol_reserves: u64,
+    pub real_token_reserves: u64,
+    #[allow(dead_code)]
+    pub real_sol_reserves: u64,
+    #[allow(dead_code)]
+    pub token_total_supply: u64,
+    /// Set once the curve has sold out and liquidity is migrating.
+    pub complete: bool,
+    pub creator: Pubkey,
+}
+
+impl BondingCurve {
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < 81 || data[..8] != BONDING_CURVE_ACCOUNT {
+            bail!("Not a bonding curve account");
+        }
+        let u64_at = |start: usize| u64::from_le_bytes(data[start..start + 8].try_into().unwrap());
+        Ok(BondingCurve {
+            virtual_token_reserves: u64_at(8),
+            virtual_sol_reserves: u64_at(16),
+            real_token_reserves: u64_at(24),
+            real_sol_reserves: u64_at(32),
+            token_total_supply: u64_at(40),
+            complete: data[48] != 0,
+            creator: Pubkey::try_from(&data[49..81])?,
+        })
+    }
+
+    /// Tokens received for spending `sol_in` lamports, fee included.
+    pub fn quote_buy(&self, sol_in: u64) -> u64 {
+        let sol_after_fee = sol_in as u128 * 10_000 / (10_000 + FEE_BPS) as u128;
+        let tokens = self.virtual_token_reserves as u128 * sol_after_fee
+            / (self.virtual_sol_reserves as u128 + sol_after_fee);
+        (tokens as u64).min(self.real_token_reserves)
+    }
+
+    /// Lamports received for selling `tokens_in`, after the fee.
+    pub fn quote_sell(&self, tokens_in: u64) -> u64 {
+        let sol_out = self.virtual_sol_reserves as u128 * tokens_in as u128
+            / (self.virtual_token_reserves as u128 + tokens_in as u128);
+        (sol_out * (10_000 - FEE_BPS) as u128 / 10_000) as u64
+    }
+}
+
+pub fn fee_recipient_from_global(data: &[u8]) -> Result<Pubkey> {
+    let bytes = data.get(GLOBAL_FEE_RECIPIENT_OFFSET..GLOBAL_FEE_RECIPIENT_OFFSET + 32)
+        .ok_or_else(|| anyhow!("Global account too short"))?;
+    Ok(Pubkey::try_from(bytes)?)
+}
+
+pub fn global_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"global"], &PROGRAM_ID).0
+}
+
+pub fn bonding_curve_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"bonding-curve", mint.as_ref()], &PROGRAM_ID).0
+}
+
+fn creator_vault_address(creator: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"creator-vault", creator.as_ref()], &PROGRAM_ID).0
+}
+
+fn event_authority_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], &PROGRAM_ID).0
+}
+
+/// Buys exactly `amount` tokens, spending at most `max_sol_cost` lamports.
+pub fn buy_instruction(
+    user: &Pubkey,
+    mint: &Pubkey,
+    fee_recipient: &Pubkey,
+    curve: &BondingCurve,
+    amount: u64,
+    max_sol_cost: u64,
+) -> Instruction {
+    let bonding_curve = bonding_curve_address(mint);
+    let mut data = BUY.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&max_sol_cost.to_le_bytes());
+
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(global_address(), false),
+            AccountMeta::new(*fee_recipient, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(bonding_curve, false),
+            AccountMeta::new(spl_associated_token_account::get_associated_token_address(&bonding_curve, mint), false),
+            AccountMeta::new(spl_associated_token_account::get_associated_token_address(user, mint), false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(creator_vault_address(&curve.creator), false),
+            AccountMeta::new_readonly(event_authority_address(), false),
+            AccountMeta::new_readonly(PROGRAM_ID, false),
+        ],
+        data,
+    }
+}
+
+/// Sells `amount` tokens for at least `min_sol_output` lamports.
+pub fn sell_instruction(
+    user: &Pubkey,
+    mint: &Pubkey,
+    fee_recipient: &Pubkey,
+    curve: &BondingCurve,
+    amount: u64,
+    min_sol_output: u64,
+) -> Instruction {
+    let bonding_curve = bonding_curve_address(mint);
+    let mut data = SELL.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&min_sol_output.to_le_bytes());
+
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(global_address(), false),
+            AccountMeta::new(*fee_recipient, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(bonding_curve, false),
+            AccountMeta::new(spl_associated_token_account::get_associated_token_address(&bonding_curve, mint), false),
+            AccountMeta::new(spl_associated_token_account::get_associated_token_address(user, mint), false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+            AccountMeta::new(creator_vault_address(&curve.creator), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(event_authority_address(), false),
+            AccountMeta::new_readonly(PROGRAM_ID, false),
+        ],
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use spl_associated_token_account::get_associated_token_address;
+
+    /// A fresh curve: 30 virtual SOL against 1.073B virtual tokens.
+    fn curve(creator: Pubkey) -> BondingCurve {
+        let mut data = BONDING_CURVE_ACCOUNT.to_vec();
+        for value in [1_073_000_000_000_000u64, 30_000_000_000, 793_100_000_000_000, 0, 1_000_000_000_000_000] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.push(0);
+        data.extend_from_slice(creator.as_ref());
+        BondingCurve::from_account_data(&data).unwrap()
+    }
+
+    /// The `TradeEvent` the program emits through a self-CPI.
+    fn trade_event(mint: Pubkey, sol_amount: u64, token_amount: u64, is_buy: bool, user: Pubkey) -> ParsedInstruction {
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend_from_slice(&TRADE_EVENT);
+        data.extend_from_slice(mint.as_ref());
+        data.extend_from_slice(&sol_amount.to_le_bytes());
+        data.extend_from_slice(&token_amount.to_le_bytes());
+        data.push(is_buy as u8);
+        data.extend_from_slice(user.as_ref());
+        data.extend_from_slice(&1_717_000_000i64.to_le_bytes());
+        fixtures::instruction(PROGRAM_ID, vec![event_authority_address()], data)
+    }
+
+    #[test]
+    fn derives_the_program_accounts() {
+        assert_eq!(global_address(), pubkey!("4wTV1YmiEkRvAtNtsSGPtUrqRYQMe5SKy2uB4Jjaxnjf"));
+        assert_eq!(event_authority_address(), pubkey!("Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1"));
+    }
+
+    #[test]
+    fn reads_and_quotes_a_bonding_curve() {
+        let creator = Pubkey::new_unique();
+        let curve = curve(creator);
+        assert_eq!(curve.creator, creator);
+        assert!(!curve.complete);
+        assert_eq!(curve.quote_buy(1_000_000_000), 34_281_150_129_545);
+        assert_eq!(curve.quote_sell(34_612_903_225_806), 928_124_999);
+        assert!(BondingCurve::from_account_data(&[0; 81]).is_err());
+    }
+
+    #[test]
+    fn decodes_a_buy_from_its_trade_event() {
+        let (user, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let fee_recipient = Pubkey::new_unique();
+        let buy = buy_instruction(&user, &mint, &fee_recipient, &curve(Pubkey::new_unique()), 34_281_150_129_545, 1_010_000_000);
+        let buy = fixtures::with_inner(fixtures::from_instruction(buy), vec![
+            (2, trade_event(mint, 1_000_000_000, 34_281_150_129_545, true, user)),
+        ]);
+        let transaction = fixtures::transaction(user, vec![buy]);
+
+        let swaps = PumpFunDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!((swap.dex, swap.pool_id, swap.owner), (Dex::PumpFun, bonding_curve_address(&mint), user));
+        assert_eq!((swap.input_mint, swap.input_amount), (spl_token::native_mint::id(), 1_000_000_000));
+        assert_eq!((swap.output_mint, swap.output_amount), (mint, 34_281_150_129_545));
+    }
+
+    #[test]
+    fn decodes_a_sell_without_its_event() {
+        let (user, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let sell = sell_instruction(&user, &mint, &Pubkey::new_unique(), &curve(Pubkey::new_unique()), 34_612_903_225_806, 900_000_000);
+        let mut transaction = fixtures::transaction(user, vec![fixtures::from_instruction(sell)]);
+        let associated_user = get_associated_token_address(&user, &mint);
+        fixtures::set_token_balance(&mut transaction, &associated_user, &mint, &user, Some(40_000_000_000_000), Some(5_387_096_774_194));
+
+        let swaps = PumpFunDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (mint, 34_612_903_225_806));
+        // Without the event only the minimum output is known.
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (spl_token::native_mint::id(), 900_000_000));
+    }
+}
@@ -0,0 +1,479 @@
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use anyhow::{Result, anyhow, bail};
+use log::debug;
+use crate::transaction::{Invocation, ParsedTransaction};
+use crate::ray_log::{self, RayLog, SwapDirection};
+use crate::quote::Fee;
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, received_amount, spent_amount, swap_balance_change, transferred};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+const SWAP_BASE_IN: u8 = 9;
+const SWAP_BASE_OUT: u8 = 11;
+
+/// Size of the `AmmInfo` account and the offsets of its vaults and mints.
+pub const AMM_INFO_SIZE: u64 = 752;
+pub const COIN_VAULT_OFFSET: usize = 336;
+pub const PC_VAULT_OFFSET: usize = 368;
+pub const COIN_MINT_OFFSET: usize = 400;
+pub const PC_MINT_OFFSET: usize = 432;
+
+/// OpenBook/Serum market accounts start with a 5 byte "serum" padding.
+const MARKET_STATE_SIZE: usize = 388;
+
+pub struct RaydiumAmmV4Decoder;
+
+impl DexDecoder for RaydiumAmmV4Decoder {
+    fn name(&self) -> &'static str {
+        "raydium-amm-v4"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        // The AMM prints one ray_log per swap in execution order, whether it
+        // was called directly or through another program.
+        let swap_logs = ray_log::swap_logs(&transaction.log_messages);
+        let invocations = transaction.invocations();
+        let swaps = invocations.iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter(|invocation| is_swap(&invocation.instruction.data));
+
+        swaps.enumerate()
+            .filter_map(|(index, invocation)| {
+                let swap_log = swap_logs.get(index);
+                if let Some(log) = swap_log {
+                    debug!("Decoded ray_log: {:?}", log);
+                }
+                decode_swap(transaction, invocation, swap_log)
+            })
+            .collect()
+    }
+}
+
+fn decode_swap(
+    transaction: &ParsedTransaction,
+    invocation: &Invocation,
+    swap_log: Option<&RayLog>,
+) -> Option<SwapEvent> {
+    let instruction = invocation.instruction;
+    let data = &instruction.data;
+
+    // Swap accounts end with [.., pool coin vault, pool pc vault, <11 market accounts>,
+    // user source, user destination, user owner]. The target orders account is
+    // optional, so offsets are taken from the end.
+    let accounts = &instruction.accounts;
+    if accounts.len() < 17 {
+        return None;
+    }
+    let len = accounts.len();
+    let owner = accounts[len - 1];
+    let pool_id = accounts[1];
+    let coin_vault = accounts[len - 13];
+    let pc_vault = accounts[len - 12];
+    let user_source = accounts[len - 3];
+    let user_destination = accounts[len - 2];
+
+    // swap_base_in(amount_in, minimum_amount_out)
+    // swap_base_out(max_amount_in, amount_out)
+    let instruction_in = u64::from_le_bytes(data[1..9].try_into().ok()?);
+    let instruction_out = u64::from_le_bytes(data[9..17].try_into().ok()?);
+
+    // The log says which way this swap went. Without it, the vault the user
+    // paid into, or else the vault that grew, took the input.
+    let coin_in = match swap_log.and_then(RayLog::direction) {
+        Some(direction) => direction == SwapDirection::CoinToPc,
+        None if transferred(invocation, &user_source, &coin_vault).is_some() => true,
+        None if transferred(invocation, &user_source, &pc_vault).is_some() => false,
+        None => {
+            let coin_change = swap_balance_change(transaction, invocation, &coin_vault).unwrap_or(0);
+            let pc_change = swap_balance_change(transaction, invocation, &pc_vault).unwrap_or(0);
+            coin_change > 0 || pc_change < 0
+        }
+    };
+    let (in_vault, out_vault) = if coin_in {
+        (coin_vault, pc_vault)
+    } else {
+        (pc_vault, coin_vault)
+    };
+
+    let input_mint = transaction.token_mint(&user_source)
+        .or_else(|| transaction.token_mint(&in_vault))?;
+    let output_mint = transaction.token_mint(&user_destination)
+        .or_else(|| transaction.token_mint(&out_vault))?;
+
+    // The amounts this swap logged, then what it transferred, then the
+    // instruction's limits.
+    let logged = swap_log.and_then(RayLog::swap_amounts);
+    let input_amount = logged.map(|(amount_in, _)| amount_in)
+        .or_else(|| spent_amount(transaction, invocation, &user_source, &in_vault))
+        .unwrap_or(instruction_in);
+    let output_amount = logged.map(|(_, amount_out)| amount_out)
+        .or_else(|| received_amount(transaction, invocation, &user_destination, &out_vault))
+        .unwrap_or(instruction_out);
+
+    Some(SwapEvent {
+        dex: Dex::RaydiumAmmV4,
+        program_id: PROGRAM_ID,
+        pool_id,
+        owner,
+        input_mint,
+        output_mint,
+        input_amount,
+        output_amount,
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+fn is_swap(data: &[u8]) -> bool {
+    data.len() >= 17 && (data[0] == SWAP_BASE_IN || data[0] == SWAP_BASE_OUT)
+}
+
+/// The parts of an `AmmInfo` account needed to build a swap.
+#[derive(Debug, Clone)]
+pub struct AmmInfo {
+    pub status: u64,
+    pub coin_decimals: u8,
+    pub pc_decimals: u8,
+    pub swap_fee: Fee,
+    /// Fees owed to the protocol that still sit in the vaults; swaps price
+    /// against the vault balances less these.
+    pub need_take_pnl_coin: u64,
+    pub need_take_pnl_pc: u64,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub open_orders: Pubkey,
+    pub market_id: Pubkey,
+    pub market_program_id: Pubkey,
+    pub target_orders: Pubkey,
+}
+
+impl AmmInfo {
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() != AMM_INFO_SIZE as usize {
+            bail!("Not an AmmInfo account");
+        }
+        let pubkey_at = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]);
+        let u64_at = |offset: usize| data[offset..offset + 8].try_into().map(u64::from_le_bytes);
+        Ok(AmmInfo {
+            status: u64_at(0)?,
+            coin_decimals: u64_at(32)? as u8,
+            pc_decimals: u64_at(40)? as u8,
+            swap_fee: Fee {
+                numerator: u64_at(176)?,
+                denominator: u64_at(184)?,
+            },
+            need_take_pnl_coin: u64_at(192)?,
+            need_take_pnl_pc: u64_at(200)?,
+            coin_vault: pubkey_at(COIN_VAULT_OFFSET)?,
+            pc_vault: pubkey_at(PC_VAULT_OFFSET)?,
+            coin_mint: pubkey_at(COIN_MINT_OFFSET)?,
+            pc_mint: pubkey_at(PC_MINT_OFFSET)?,
+            open_orders: pubkey_at(496)?,
+            market_id: pubkey_at(528)?,
+            market_program_id: pubkey_at(560)?,
+            target_orders: pubkey_at(592)?,
+        })
+    }
+
+    /// Initialized, swap-only and waiting-for-open pools accept swaps.
+    pub fn swap_enabled(&self) -> bool {
+        matches!(self.status, 1 | 6 | 7)
+    }
+}
+
+/// The OpenBook market accounts an AMM v4 swap passes through.
+#[derive(Debug, Clone)]
+pub struct MarketAccounts {
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_queue: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub vault_signer: Pubkey,
+}
+
+impl MarketAccounts {
+    pub fn from_account_data(market_id: &Pubkey, market_program_id: &Pubkey, data: &[u8]) -> Result<Self> {
+        if data.len() < MARKET_STATE_SIZE || &data[..5] != b"serum" {
+            bail!("Not an OpenBook market account");
+        }
+        let pubkey_at = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]);
+        let vault_signer_nonce = u64::from_le_bytes(data[45..53].try_into()?);
+        let vault_signer = Pubkey::create_program_address(
+            &[market_id.as_ref(), &vault_signer_nonce.to_le_bytes()],
+            market_program_id,
+        ).map_err(|e| anyhow!("Invalid vault signer for market {}: {}", market_id, e))?;
+        Ok(MarketAccounts {
+            coin_vault: pubkey_at(117)?,
+            pc_vault: pubkey_at(165)?,
+            event_queue: pubkey_at(253)?,
+            bids: pubkey_at(285)?,
+            asks: pubkey_at(317)?,
+            vault_signer,
+        })
+    }
+}
+
+pub fn authority_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"amm authority"], &PROGRAM_ID).0
+}
+
+/// Exact-in swap of `amount_in` for at least `min_amount_out`. The
+/// direction follows from which pool mint `user_source` holds.
+#[allow(clippy::too_many_arguments)]
+pub fn swap_base_in_instruction(
+    user: &Pubkey,
+    amm_id: &Pubkey,
+    amm: &AmmInfo,
+    market: &MarketAccounts,
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Instruction {
+    swap_instruction(user, amm_id, amm, market, user_source, user_destination, SWAP_BASE_IN, amount_in, min_amount_out)
+}
+
+/// Exact-out swap of at most `max_amount_in` for `amount_out`.
+#[allow(dead_code)]
+#[allow(clippy::too_many_arguments)]
+pub fn swap_base_out_instruction(
+    user: &Pubkey,
+    amm_id: &Pubkey,
+    amm: &AmmInfo,
+    market: &MarketAccounts,
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    max_amount_in: u64,
+    amount_out: u64,
+) -> Instruction {
+    swap_instruction(user, amm_id, amm, market, user_source, user_destination, SWAP_BASE_OUT, max_amount_in, amount_out)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn swap_instruction(
+    user: &Pubkey,
+    amm_id: &Pubkey,
+    amm: &AmmInfo,
+    market: &MarketAccounts,
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    tag: u8,
+    first: u64,
+    second: u64,
+) -> Instruction {
+    let mut data = vec![tag];
+    data.extend_from_slice(&first.to_le_bytes());
+    data.extend_from_slice(&second.to_le_bytes());
+
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*amm_id, false),
+            AccountMeta::new_readonly(authority_address(), false),
+            AccountMeta::new(amm.open_orders, false),
+            AccountMeta::new(amm.target_orders, false),
+            AccountMeta::new(amm.coin_vault, false),
+            AccountMeta::new(amm.pc_vault, false),
+            AccountMeta::new_readonly(amm.market_program_id, false),
+            AccountMeta::new(amm.market_id, false),
+            AccountMeta::new(market.bids, false),
+            AccountMeta::new(market.asks, false),
+            AccountMeta::new(market.event_queue, false),
+            AccountMeta::new(market.coin_vault, false),
+            AccountMeta::new(market.pc_vault, false),
+            AccountMeta::new_readonly(market.vault_signer, false),
+            AccountMeta::new(*user_source, false),
+            AccountMeta::new(*user_destination, false),
+            AccountMeta::new_readonly(*user, true),
+        ],
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::transaction::{ParsedInstruction, ParsedTransaction};
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    const SOL_USDC: Pubkey = pubkey!("58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2");
+    /// 1 SOL in for 149.623132 USDC out of the SOL/USDC pool.
+    const SWAP_BASE_IN_LOG: &str = "AwDKmjsAAAAAtDzUCAAAAAACAAAAAAAAAADKmjsAAAAAAOUsyyNtAAAAIJvzXhAAAFwR6wgAAAAA";
+
+    struct Swap {
+        owner: Pubkey,
+        coin_vault: Pubkey,
+        pc_vault: Pubkey,
+        user_sol: Pubkey,
+        user_usdc: Pubkey,
+    }
+
+    fn swap() -> Swap {
+        Swap {
+            owner: Pubkey::new_unique(),
+            coin_vault: Pubkey::new_unique(),
+            pc_vault: Pubkey::new_unique(),
+            user_sol: Pubkey::new_unique(),
+            user_usdc: Pubkey::new_unique(),
+        }
+    }
+
+    /// A swap on the SOL/USDC pool, with the target orders account only
+    /// when `target_orders` is set.
+    fn amm_swap(swap: &Swap, source: Pubkey, destination: Pubkey, data: Vec<u8>, target_orders: bool) -> ParsedInstruction {
+        let mut accounts = vec![spl_token::id(), SOL_USDC, authority_address(), Pubkey::new_unique()];
+        if target_orders {
+            accounts.push(Pubkey::new_unique());
+        }
+        accounts.extend([swap.coin_vault, swap.pc_vault]);
+        accounts.extend((0..8).map(|_| Pubkey::new_unique()));
+        accounts.extend([source, destination, swap.owner]);
+        fixtures::instruction(PROGRAM_ID, accounts, data)
+    }
+
+    fn swap_data(tag: u8, first: u64, second: u64) -> Vec<u8> {
+        let mut data = vec![tag];
+        data.extend_from_slice(&first.to_le_bytes());
+        data.extend_from_slice(&second.to_le_bytes());
+        data
+    }
+
+    /// Records the vault mints, with balances that do not change.
+    fn vault_mints(transaction: &mut ParsedTransaction, swap: &Swap) {
+        let authority = authority_address();
+        fixtures::set_token_balance(transaction, &swap.coin_vault, &WSOL, &authority, Some(120_000_500_000_000), Some(120_000_500_000_000));
+        fixtures::set_token_balance(transaction, &swap.pc_vault, &USDC, &authority, Some(18_000_000_000_000), Some(18_000_000_000_000));
+    }
+
+    #[test]
+    fn decodes_swap_base_in_from_its_ray_log() {
+        let swap = swap();
+        let instruction = amm_swap(&swap, swap.user_sol, swap.user_usdc, swap_data(SWAP_BASE_IN, 1_000_000_000, 148_126_900), true);
+        let mut transaction = fixtures::transaction(swap.owner, vec![instruction]);
+        transaction.log_messages = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            format!("Program log: ray_log: {}", SWAP_BASE_IN_LOG),
+            format!("Program {} success", PROGRAM_ID),
+        ];
+        vault_mints(&mut transaction, &swap);
+
+        let swaps = RaydiumAmmV4Decoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let decoded = &swaps[0];
+        assert_eq!((decoded.dex, decoded.pool_id, decoded.owner), (Dex::RaydiumAmmV4, SOL_USDC, swap.owner));
+        assert_eq!((decoded.input_mint, decoded.input_amount), (WSOL, 1_000_000_000));
+        assert_eq!((decoded.output_mint, decoded.output_amount), (USDC, 149_623_132));
+    }
+
+    #[test]
+    fn takes_the_direction_from_transfers_without_a_log() {
+        let swap = swap();
+        let instruction = fixtures::with_inner(
+            amm_swap(&swap, swap.user_usdc, swap.user_sol, swap_data(SWAP_BASE_IN, 150_000_000, 990_000_000), false),
+            vec![
+                (2, fixtures::transfer(swap.user_usdc, swap.pc_vault, swap.owner, 150_000_000)),
+                (2, fixtures::transfer(swap.coin_vault, swap.user_sol, authority_address(), 998_493_117)),
+            ],
+        );
+        let mut transaction = fixtures::transaction(swap.owner, vec![instruction]);
+        vault_mints(&mut transaction, &swap);
+
+        let swaps = RaydiumAmmV4Decoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 150_000_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 998_493_117));
+    }
+
+    #[test]
+    fn takes_the_direction_from_vault_balances() {
+        let swap = swap();
+        let instruction = amm_swap(&swap, swap.user_sol, swap.user_usdc, swap_data(SWAP_BASE_IN, 1_000_000_000, 1), false);
+        let mut transaction = fixtures::transaction(swap.owner, vec![instruction]);
+        let authority = authority_address();
+        fixtures::set_token_balance(&mut transaction, &swap.coin_vault, &WSOL, &authority, Some(120_000_500_000_000), Some(120_001_500_000_000));
+        fixtures::set_token_balance(&mut transaction, &swap.pc_vault, &USDC, &authority, Some(18_000_000_000_000), Some(17_999_850_376_868));
+
+        let swaps = RaydiumAmmV4Decoder.decode(&transaction);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (WSOL, 1_000_000_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (USDC, 149_623_132));
+    }
+
+    #[test]
+    fn swap_base_out_falls_back_to_the_amount_out() {
+        let swap = swap();
+        let instruction = fixtures::with_inner(
+            amm_swap(&swap, swap.user_usdc, swap.user_sol, swap_data(SWAP_BASE_OUT, 151_000_000, 1_000_000_000), true),
+            vec![(2, fixtures::transfer(swap.user_usdc, swap.pc_vault, swap.owner, 150_376_868))],
+        );
+        let mut transaction = fixtures::transaction(swap.owner, vec![instruction]);
+        vault_mints(&mut transaction, &swap);
+
+        let swaps = RaydiumAmmV4Decoder.decode(&transaction);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 150_376_868));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 1_000_000_000));
+    }
+
+    #[test]
+    fn derives_the_pool_authority() {
+        assert_eq!(authority_address(), pubkey!("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"));
+    }
+
+    #[test]
+    fn reads_the_pool_and_its_market() {
+        let (coin_vault, pc_vault, market_id) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let amm = AmmInfo::from_account_data(&fixtures::amm_info_data((coin_vault, pc_vault), (WSOL, USDC), &market_id)).unwrap();
+        assert!(amm.swap_enabled());
+        assert_eq!((amm.coin_decimals, amm.pc_decimals), (9, 6));
+        assert_eq!((amm.coin_vault, amm.pc_vault, amm.coin_mint, amm.pc_mint), (coin_vault, pc_vault, WSOL, USDC));
+        assert_eq!((amm.market_id, amm.market_program_id), (market_id, fixtures::OPENBOOK_PROGRAM_ID));
+        assert!(AmmInfo::from_account_data(&[0; 751]).is_err());
+
+        let data = fixtures::market_data(&market_id);
+        let market = MarketAccounts::from_account_data(&market_id, &amm.market_program_id, &data).unwrap();
+        let nonce = u64::from_le_bytes(data[45..53].try_into().unwrap());
+        let vault_signer = Pubkey::create_program_address(&[market_id.as_ref(), &nonce.to_le_bytes()], &fixtures::OPENBOOK_PROGRAM_ID).unwrap();
+        assert_eq!(market.vault_signer, vault_signer);
+        assert_eq!(market.bids, Pubkey::try_from(&data[285..317]).unwrap());
+        assert!(MarketAccounts::from_account_data(&market_id, &amm.market_program_id, &data[..387]).is_err());
+    }
+
+    #[test]
+    fn builds_a_swap_base_in_the_decoder_reads_back() {
+        let (coin_vault, pc_vault, market_id) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let amm = AmmInfo::from_account_data(&fixtures::amm_info_data((coin_vault, pc_vault), (WSOL, USDC), &market_id)).unwrap();
+        let market = MarketAccounts::from_account_data(&market_id, &amm.market_program_id, &fixtures::market_data(&market_id)).unwrap();
+        let (user, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        let instruction = swap_base_in_instruction(&user, &SOL_USDC, &amm, &market, &user_usdc, &user_sol, 150_000_000, 990_000_000);
+        assert_eq!(instruction.accounts.len(), 18);
+        assert_eq!(instruction.data, swap_data(SWAP_BASE_IN, 150_000_000, 990_000_000));
+        let signers: Vec<_> = instruction.accounts.iter().filter(|meta| meta.is_signer).map(|meta| meta.pubkey).collect();
+        assert_eq!(signers, vec![user]);
+        assert_eq!(instruction.accounts[14].pubkey, market.vault_signer);
+
+        let instruction = fixtures::with_inner(fixtures::from_instruction(instruction), vec![
+            (2, fixtures::transfer(user_usdc, pc_vault, user, 150_000_000)),
+            (2, fixtures::transfer(coin_vault, user_sol, authority_address(), 998_493_117)),
+        ]);
+        let mut transaction = fixtures::transaction(user, vec![instruction]);
+        vault_mints(&mut transaction, &Swap { owner: user, coin_vault, pc_vault, user_sol, user_usdc });
+
+        let swaps = RaydiumAmmV4Decoder.decode(&transaction);
+        assert_eq!((swaps[0].pool_id, swaps[0].owner), (SOL_USDC, user));
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 150_000_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 998_493_117));
+    }
+}
@@ -0,0 +1,186 @@
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use crate::transaction::{Invocation, ParsedTransaction};
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, anchor_args, received_amount, spent_amount};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+const SWAP_V2: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+
+/// Raydium concentrated liquidity (CLMM) pools.
+pub struct RaydiumClmmDecoder;
+
+impl DexDecoder for RaydiumClmmDecoder {
+    fn name(&self) -> &'static str {
+        "raydium-clmm"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        transaction.invocations().iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter_map(|invocation| decode_swap(transaction, invocation))
+            .collect()
+    }
+}
+
+fn decode_swap(transaction: &ParsedTransaction, invocation: &Invocation) -> Option<SwapEvent> {
+    // swap / swap_v2(amount, other_amount_threshold, sqrt_price_limit_x64: u128, is_base_input: bool)
+    let instruction = invocation.instruction;
+    let data = &instruction.data;
+    let (args, is_v2) = if let Some(args) = anchor_args::<2>(data, &SWAP) {
+        (args, false)
+    } else if let Some(args) = anchor_args::<2>(data, &SWAP_V2) {
+        (args, true)
+    } else {
+        return None;
+    };
+    let [amount, other_amount_threshold] = args;
+    let is_base_input = data.get(40).map(|flag| *flag != 0).unwrap_or(true);
+    let (instruction_in, instruction_out) = if is_base_input {
+        (amount, other_amount_threshold)
+    } else {
+        (other_amount_threshold, amount)
+    };
+
+    // Both versions start with [payer, amm_config, pool_state, input_token_account,
+    // output_token_account, input_vault, output_vault, observation_state, token_program].
+    // swap_v2 continues with [token_program_2022, memo_program, input_vault_mint,
+    // output_vault_mint]; tick arrays follow in either case.
+    let accounts = &instruction.accounts;
+    let required = if is_v2 { 13 } else { 9 };
+    if accounts.len() < required {
+        return None;
+    }
+    let owner = accounts[0];
+    let pool_id = accounts[2];
+    let user_source = accounts[3];
+    let user_destination = accounts[4];
+    let input_vault = accounts[5];
+    let output_vault = accounts[6];
+
+    let (input_mint, output_mint) = if is_v2 {
+        (accounts[11], accounts[12])
+    } else {
+        (
+            transaction.token_mint(&input_vault).or_else(|| transaction.token_mint(&user_source))?,
+            transaction.token_mint(&output_vault).or_else(|| transaction.token_mint(&user_destination))?,
+        )
+    };
+
+    Some(SwapEvent {
+        dex: Dex::RaydiumClmm,
+        program_id: PROGRAM_ID,
+        pool_id,
+        owner,
+        input_mint,
+        output_mint,
+        input_amount: spent_amount(transaction, invocation, &user_source, &input_vault).unwrap_or(instruction_in),
+        output_amount: received_amount(transaction, invocation, &user_destination, &output_vault).unwrap_or(instruction_out),
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    const MEMO_PROGRAM: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+    /// A SOL/USDC pool and its vaults.
+    struct Pool {
+        id: Pubkey,
+        sol_vault: Pubkey,
+        usdc_vault: Pubkey,
+    }
+
+    fn pool() -> Pool {
+        Pool { id: Pubkey::new_unique(), sol_vault: Pubkey::new_unique(), usdc_vault: Pubkey::new_unique() }
+    }
+
+    /// swap / swap_v2 data: amount, other_amount_threshold,
+    /// sqrt_price_limit_x64 and is_base_input.
+    fn swap_data(discriminator: [u8; 8], amount: u64, threshold: u64, is_base_input: bool) -> Vec<u8> {
+        let mut data = fixtures::anchor_data(discriminator, &[amount, threshold]);
+        data.extend_from_slice(&0u128.to_le_bytes());
+        data.push(is_base_input as u8);
+        data
+    }
+
+    fn swap_accounts(owner: Pubkey, pool: &Pool, user_source: Pubkey, user_destination: Pubkey, input_vault: Pubkey, output_vault: Pubkey) -> Vec<Pubkey> {
+        vec![
+            owner, Pubkey::new_unique(), pool.id, user_source, user_destination,
+            input_vault, output_vault, Pubkey::new_unique(), spl_token::id(),
+        ]
+    }
+
+    #[test]
+    fn decodes_swap_with_mints_from_the_vaults() {
+        let pool = pool();
+        let (owner, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut accounts = swap_accounts(owner, &pool, user_sol, user_usdc, pool.sol_vault, pool.usdc_vault);
+        accounts.push(Pubkey::new_unique());
+        let authority = Pubkey::new_unique();
+        let instruction = fixtures::with_inner(
+            fixtures::instruction(PROGRAM_ID, accounts, swap_data(SWAP, 1_000_000_000, 147_000_000, true)),
+            vec![
+                (2, fixtures::transfer(user_sol, pool.sol_vault, owner, 1_000_000_000)),
+                (2, fixtures::transfer(pool.usdc_vault, user_usdc, authority, 148_305_112)),
+            ],
+        );
+        let mut transaction = fixtures::transaction(owner, vec![instruction]);
+        fixtures::set_token_balance(&mut transaction, &pool.sol_vault, &WSOL, &authority, Some(40_000_000_000), Some(41_000_000_000));
+        fixtures::set_token_balance(&mut transaction, &pool.usdc_vault, &USDC, &authority, Some(6_000_000_000_000), Some(5_999_851_694_888));
+
+        let swaps = RaydiumClmmDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!((swap.dex, swap.pool_id, swap.owner), (Dex::RaydiumClmm, pool.id, owner));
+        assert_eq!((swap.input_mint, swap.input_amount), (WSOL, 1_000_000_000));
+        assert_eq!((swap.output_mint, swap.output_amount), (USDC, 148_305_112));
+    }
+
+    #[test]
+    fn decodes_exact_out_swap_v2() {
+        let pool = pool();
+        let (owner, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut accounts = swap_accounts(owner, &pool, user_usdc, user_sol, pool.usdc_vault, pool.sol_vault);
+        accounts.extend([Pubkey::new_unique(), MEMO_PROGRAM, USDC, WSOL, Pubkey::new_unique()]);
+        let authority = Pubkey::new_unique();
+        let instruction = fixtures::with_inner(
+            fixtures::instruction(PROGRAM_ID, accounts, swap_data(SWAP_V2, 1_000_000_000, 150_000_000, false)),
+            vec![
+                (2, fixtures::transfer_checked(user_usdc, USDC, pool.usdc_vault, owner, 148_720_001, 6)),
+                (2, fixtures::transfer_checked(pool.sol_vault, WSOL, user_sol, authority, 1_000_000_000, 9)),
+            ],
+        );
+        let transaction = fixtures::transaction(owner, vec![instruction]);
+
+        let swaps = RaydiumClmmDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 148_720_001));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 1_000_000_000));
+    }
+
+    #[test]
+    fn falls_back_to_the_instruction_amounts() {
+        let pool = pool();
+        let (owner, user_sol, user_usdc) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut accounts = swap_accounts(owner, &pool, user_usdc, user_sol, pool.usdc_vault, pool.sol_vault);
+        accounts.extend([Pubkey::new_unique(), MEMO_PROGRAM, USDC, WSOL]);
+        let instruction = fixtures::instruction(PROGRAM_ID, accounts, swap_data(SWAP_V2, 1_000_000_000, 150_000_000, false));
+        let transaction = fixtures::transaction(owner, vec![instruction]);
+
+        let swaps = RaydiumClmmDecoder.decode(&transaction);
+        assert_eq!((swaps[0].input_amount, swaps[0].output_amount), (150_000_000, 1_000_000_000));
+    }
+}
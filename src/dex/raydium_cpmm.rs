@@ -0,0 +1,150 @@
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use crate::transaction::{Invocation, ParsedTransaction};
+use crate::types::Dex;
+use super::{DexDecoder, SwapEvent, anchor_args, received_amount, spent_amount};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+const SWAP_BASE_INPUT: [u8; 8] = [143, 190, 90, 218, 196, 30, 51, 222];
+const SWAP_BASE_OUTPUT: [u8; 8] = [55, 217, 98, 86, 163, 74, 180, 173];
+
+/// Raydium constant-product (CPMM) pools.
+pub struct RaydiumCpmmDecoder;
+
+impl DexDecoder for RaydiumCpmmDecoder {
+    fn name(&self) -> &'static str {
+        "raydium-cpmm"
+    }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![PROGRAM_ID]
+    }
+
+    fn decode(&self, transaction: &ParsedTransaction) -> Vec<SwapEvent> {
+        transaction.invocations().iter()
+            .filter(|invocation| invocation.instruction.program_id == PROGRAM_ID)
+            .filter_map(|invocation| decode_swap(transaction, invocation))
+            .collect()
+    }
+}
+
+fn decode_swap(transaction: &ParsedTransaction, invocation: &Invocation) -> Option<SwapEvent> {
+    // swap_base_input(amount_in, minimum_amount_out)
+    // swap_base_output(max_amount_in, amount_out)
+    let instruction = invocation.instruction;
+    let (instruction_in, instruction_out) = if let Some([amount_in, minimum_out]) = anchor_args(&instruction.data, &SWAP_BASE_INPUT) {
+        (amount_in, minimum_out)
+    } else if let Some([max_in, amount_out]) = anchor_args(&instruction.data, &SWAP_BASE_OUTPUT) {
+        (max_in, amount_out)
+    } else {
+        return None;
+    };
+
+    // Both swaps take [payer, authority, amm_config, pool_state, input_token_account,
+    // output_token_account, input_vault, output_vault, input_token_program,
+    // output_token_program, input_token_mint, output_token_mint, observation_state].
+    let accounts = &instruction.accounts;
+    if accounts.len() < 13 {
+        return None;
+    }
+    let owner = accounts[0];
+    let pool_id = accounts[3];
+    let user_source = accounts[4];
+    let user_destination = accounts[5];
+    let input_vault = accounts[6];
+    let output_vault = accounts[7];
+
+    Some(SwapEvent {
+        dex: Dex::RaydiumCpmm,
+        program_id: PROGRAM_ID,
+        pool_id,
+        owner,
+        input_mint: accounts[10],
+        output_mint: accounts[11],
+        input_amount: spent_amount(transaction, invocation, &user_source, &input_vault).unwrap_or(instruction_in),
+        output_amount: received_amount(transaction, invocation, &user_destination, &output_vault).unwrap_or(instruction_out),
+        outer_index: invocation.outer_index,
+        invocation: invocation.index,
+        inferred: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    const AUTHORITY: Pubkey = pubkey!("GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL");
+
+    struct Accounts {
+        owner: Pubkey,
+        pool: Pubkey,
+        user_source: Pubkey,
+        user_destination: Pubkey,
+        input_vault: Pubkey,
+        output_vault: Pubkey,
+    }
+
+    fn accounts() -> Accounts {
+        Accounts {
+            owner: Pubkey::new_unique(),
+            pool: Pubkey::new_unique(),
+            user_source: Pubkey::new_unique(),
+            user_destination: Pubkey::new_unique(),
+            input_vault: Pubkey::new_unique(),
+            output_vault: Pubkey::new_unique(),
+        }
+    }
+
+    fn swap(accounts: &Accounts, input_mint: Pubkey, output_mint: Pubkey, data: Vec<u8>) -> crate::transaction::ParsedInstruction {
+        fixtures::instruction(PROGRAM_ID, vec![
+            accounts.owner, AUTHORITY, Pubkey::new_unique(), accounts.pool,
+            accounts.user_source, accounts.user_destination, accounts.input_vault, accounts.output_vault,
+            spl_token::id(), spl_token::id(), input_mint, output_mint, Pubkey::new_unique(),
+        ], data)
+    }
+
+    #[test]
+    fn decodes_swap_base_input_from_its_transfers() {
+        let accounts = accounts();
+        let instruction = fixtures::with_inner(
+            swap(&accounts, WSOL, USDC, fixtures::anchor_data(SWAP_BASE_INPUT, &[1_000_000_000, 140_000_000])),
+            vec![
+                (2, fixtures::transfer_checked(accounts.user_source, WSOL, accounts.input_vault, accounts.owner, 1_000_000_000, 9)),
+                (2, fixtures::transfer_checked(accounts.output_vault, USDC, accounts.user_destination, AUTHORITY, 148_211_404, 6)),
+            ],
+        );
+        let transaction = fixtures::transaction(accounts.owner, vec![instruction]);
+
+        let swaps = RaydiumCpmmDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        let swap = &swaps[0];
+        assert_eq!((swap.dex, swap.pool_id, swap.owner), (Dex::RaydiumCpmm, accounts.pool, accounts.owner));
+        assert_eq!((swap.input_mint, swap.input_amount), (WSOL, 1_000_000_000));
+        assert_eq!((swap.output_mint, swap.output_amount), (USDC, 148_211_404));
+    }
+
+    #[test]
+    fn decodes_swap_base_output_from_balances() {
+        let accounts = accounts();
+        let instruction = swap(&accounts, USDC, WSOL, fixtures::anchor_data(SWAP_BASE_OUTPUT, &[150_000_000, 1_000_000_000]));
+        let mut transaction = fixtures::transaction(accounts.owner, vec![instruction]);
+        fixtures::set_token_balance(&mut transaction, &accounts.user_source, &USDC, &accounts.owner, Some(500_000_000), Some(351_400_000));
+        fixtures::set_token_balance(&mut transaction, &accounts.user_destination, &WSOL, &accounts.owner, Some(0), Some(1_000_000_000));
+
+        let swaps = RaydiumCpmmDecoder.decode(&transaction);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!((swaps[0].input_mint, swaps[0].input_amount), (USDC, 148_600_000));
+        assert_eq!((swaps[0].output_mint, swaps[0].output_amount), (WSOL, 1_000_000_000));
+    }
+
+    #[test]
+    fn ignores_other_instructions() {
+        let accounts = accounts();
+        let deposit = swap(&accounts, WSOL, USDC, fixtures::anchor_data([242, 35, 198, 137, 82, 225, 242, 182], &[1, 2, 3]));
+        let transaction = fixtures::transaction(accounts.owner, vec![deposit]);
+        assert!(RaydiumCpmmDecoder.decode(&transaction).is_empty());
+    }
+}
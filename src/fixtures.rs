@@ -0,0 +1,161 @@
+//! Transactions for tests, built the way `ParsedTransaction::from_encoded`
+//! lays them out, and the pool accounts the trader reads.
+
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use crate::dex::raydium_amm_v4;
+use crate::transaction::{ParsedInstruction, ParsedTransaction, TokenBalance};
+
+pub const SIGNATURE: &str = "5h6xBEauJ3PK6SWCZ1PGjBvj8vDdWG3KpwATGy1ARAXFSDwt8GFXM7W5Ncn16wmqokgpiKRLuS83KUxyZyv2sUYv";
+
+pub fn instruction(program_id: Pubkey, accounts: Vec<Pubkey>, data: Vec<u8>) -> ParsedInstruction {
+    ParsedInstruction { program_id, accounts, data, inner_instructions: Vec::new(), stack_height: None }
+}
+
+/// A built instruction as it appears in a fetched transaction.
+pub fn from_instruction(instruction: Instruction) -> ParsedInstruction {
+    let accounts = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+    self::instruction(instruction.program_id, accounts, instruction.data)
+}
+
+/// `instruction` with the calls it made, each at its stack height.
+pub fn with_inner(mut outer: ParsedInstruction, inner: Vec<(u32, ParsedInstruction)>) -> ParsedInstruction {
+    outer.inner_instructions = inner.into_iter()
+        .map(|(height, instruction)| instruction.at_height(Some(height)))
+        .collect();
+    outer
+}
+
+/// An SPL token `transfer` of `amount`.
+pub fn transfer(source: Pubkey, destination: Pubkey, authority: Pubkey, amount: u64) -> ParsedInstruction {
+    let mut data = vec![3];
+    data.extend_from_slice(&amount.to_le_bytes());
+    instruction(spl_token::id(), vec![source, destination, authority], data)
+}
+
+/// An SPL token `transferChecked` of `amount`.
+pub fn transfer_checked(source: Pubkey, mint: Pubkey, destination: Pubkey, authority: Pubkey, amount: u64, decimals: u8) -> ParsedInstruction {
+    let mut data = vec![12];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+    instruction(spl_token::id(), vec![source, mint, destination, authority], data)
+}
+
+/// Anchor instruction data: the discriminator followed by u64 arguments.
+pub fn anchor_data(discriminator: [u8; 8], args: &[u64]) -> Vec<u8> {
+    let mut data = discriminator.to_vec();
+    for arg in args {
+        data.extend_from_slice(&arg.to_le_bytes());
+    }
+    data
+}
+
+/// A successful transaction signed by `signer`, listing every account its
+/// instructions use. Balances start empty; add them with the setters.
+pub fn transaction(signer: Pubkey, instructions: Vec<ParsedInstruction>) -> ParsedTransaction {
+    let mut transaction = ParsedTransaction {
+        signature: SIGNATURE.to_string(),
+        slot: 250_000_000,
+        failed: false,
+        account_keys: vec![signer],
+        instructions: Vec::new(),
+        log_messages: Vec::new(),
+        pre_balances: vec![0],
+        post_balances: vec![0],
+        fee: 5_000,
+        pre_token_balances: Vec::new(),
+        post_token_balances: Vec::new(),
+    };
+    for instruction in &instructions {
+        add_accounts(&mut transaction, instruction);
+    }
+    transaction.instructions = instructions;
+    transaction
+}
+
+fn add_accounts(transaction: &mut ParsedTransaction, instruction: &ParsedInstruction) {
+    key_index(transaction, &instruction.program_id);
+    for account in &instruction.accounts {
+        key_index(transaction, account);
+    }
+    for inner in &instruction.inner_instructions {
+        add_accounts(transaction, inner);
+    }
+}
+
+fn key_index(transaction: &mut ParsedTransaction, account: &Pubkey) -> usize {
+    match transaction.account_index(account) {
+        Some(index) => index,
+        None => {
+            transaction.account_keys.push(*account);
+            transaction.pre_balances.push(0);
+            transaction.post_balances.push(0);
+            transaction.account_keys.len() - 1
+        }
+    }
+}
+
+/// Token balances of `account`; `None` means it did not exist on that side.
+pub fn set_token_balance(
+    transaction: &mut ParsedTransaction,
+    account: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    pre: Option<u64>,
+    post: Option<u64>,
+) {
+    let index = key_index(transaction, account);
+    let balance = |amount| TokenBalance {
+        account_index: index,
+        mint: *mint,
+        owner: Some(*owner),
+        amount,
+        decimals: 6,
+    };
+    transaction.pre_token_balances.extend(pre.map(balance));
+    transaction.post_token_balances.extend(post.map(balance));
+}
+
+pub fn set_lamports(transaction: &mut ParsedTransaction, account: &Pubkey, pre: u64, post: u64) {
+    let index = key_index(transaction, account);
+    transaction.pre_balances[index] = pre;
+    transaction.post_balances[index] = post;
+}
+
+pub const OPENBOOK_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
+
+/// `AmmInfo` data of a swappable pool with a 0.25% fee, trading against the
+/// OpenBook market `market_id`.
+pub fn amm_info_data(vaults: (Pubkey, Pubkey), mints: (Pubkey, Pubkey), market_id: &Pubkey) -> Vec<u8> {
+    let mut data = vec![0u8; raydium_amm_v4::AMM_INFO_SIZE as usize];
+    let mut put = |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    put(0, &6u64.to_le_bytes());
+    put(32, &9u64.to_le_bytes());
+    put(40, &6u64.to_le_bytes());
+    put(176, &25u64.to_le_bytes());
+    put(184, &10_000u64.to_le_bytes());
+    put(raydium_amm_v4::COIN_VAULT_OFFSET, vaults.0.as_ref());
+    put(raydium_amm_v4::PC_VAULT_OFFSET, vaults.1.as_ref());
+    put(raydium_amm_v4::COIN_MINT_OFFSET, mints.0.as_ref());
+    put(raydium_amm_v4::PC_MINT_OFFSET, mints.1.as_ref());
+    put(496, Pubkey::new_unique().as_ref());
+    put(528, market_id.as_ref());
+    put(560, OPENBOOK_PROGRAM_ID.as_ref());
+    put(592, Pubkey::new_unique().as_ref());
+    data
+}
+
+/// OpenBook market data for `market_id`, with the first vault signer nonce
+/// that derives an address.
+pub fn market_data(market_id: &Pubkey) -> Vec<u8> {
+    let nonce = (0u64..)
+        .find(|nonce| Pubkey::create_program_address(&[market_id.as_ref(), &nonce.to_le_bytes()], &OPENBOOK_PROGRAM_ID).is_ok())
+        .unwrap();
+    let mut data = vec![0u8; 388];
+    data[..5].copy_from_slice(b"serum");
+    data[45..53].copy_from_slice(&nonce.to_le_bytes());
+    for offset in [117, 165, 253, 285, 317] {
+        data[offset..offset + 32].copy_from_slice(Pubkey::new_unique().as_ref());
+    }
+    data
+}
@@ -0,0 +1,280 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use anyhow::{Result, anyhow, bail};
+use chrono::NaiveDate;
+use log::{info, warn, debug};
+use serde::Serialize;
+use crate::config::RpcSettings;
+use crate::transaction;
+use crate::alt::AltResolver;
+use crate::dex::DecoderRegistry;
+use crate::types::TradeDetails;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+const PAGE_SIZE: usize = 1000;
+
+const USAGE: &str = "usage: solana-copy-trader backfill <wallet> \
+    [--until-slot <slot>] [--until-date <YYYY-MM-DD>] [--until-signature <signature>] \
+    [--output <file>]";
+
+/// How far back a backfill pages. Without a limit it runs to the wallet's
+/// first transaction.
+#[derive(Debug, Default)]
+pub struct BackfillLimit {
+    pub slot: Option<u64>,
+    /// Unix time of the oldest block to include.
+    pub block_time: Option<i64>,
+    pub signature: Option<Signature>,
+}
+
+impl BackfillLimit {
+    /// Whether a transaction at `slot` and `block_time` is older than the
+    /// limit. Transactions without a block time pass the date limit.
+    fn excludes(&self, slot: u64, block_time: Option<i64>) -> bool {
+        self.slot.is_some_and(|oldest| slot < oldest)
+            || self.block_time.zip(block_time).is_some_and(|(oldest, time)| time < oldest)
+    }
+}
+
+#[derive(Debug)]
+pub struct BackfillArgs {
+    pub wallet: Pubkey,
+    pub limit: BackfillLimit,
+    pub output: String,
+}
+
+impl BackfillArgs {
+    /// Parses the arguments that follow the `backfill` command.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut args = args.iter();
+        let wallet = args.next().ok_or_else(|| anyhow!(USAGE))?;
+        let wallet = Pubkey::from_str(wallet)
+            .map_err(|_| anyhow!("Invalid wallet {}", wallet))?;
+
+        let mut limit = BackfillLimit::default();
+        let mut output = None;
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| anyhow!("{} needs a value\n{}", flag, USAGE))?;
+            match flag.as_str() {
+                "--until-slot" => limit.slot = Some(value.parse()?),
+                "--until-date" => {
+                    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                        .map_err(|e| anyhow!("Invalid date {}: {}", value, e))?;
+                    limit.block_time = date.and_hms_opt(0, 0, 0).map(|time| time.and_utc().timestamp());
+                }
+                "--until-signature" => limit.signature = Some(Signature::from_str(value)?),
+                "--output" => output = Some(value.clone()),
+                other => bail!("Unknown option {}\n{}", other, USAGE),
+            }
+        }
+
+        Ok(BackfillArgs {
+            wallet,
+            limit,
+            output: output.unwrap_or_else(|| format!("backfill-{}.jsonl", wallet)),
+        })
+    }
+}
+
+/// One line of the backfill output.
+#[derive(Debug, Serialize)]
+struct TradeRecord {
+    signature: String,
+    slot: u64,
+    block_time: Option<i64>,
+    target: String,
+    dex: String,
+    trade_type: String,
+    pool: String,
+    input_mint: String,
+    input_amount: u64,
+    output_mint: String,
+    output_amount: u64,
+    inferred: bool,
+}
+
+impl TradeRecord {
+    fn new(trade: TradeDetails, slot: u64, block_time: Option<i64>) -> Self {
+        TradeRecord {
+            signature: trade.signature,
+            slot,
+            block_time,
+            target: trade.target.to_string(),
+            dex: format!("{:?}", trade.dex),
+            trade_type: format!("{:?}", trade.trade_type),
+            pool: trade.pool_id.to_string(),
+            input_mint: trade.input_token.to_string(),
+            input_amount: trade.input_amount,
+            output_mint: trade.output_token.to_string(),
+            output_amount: trade.output_amount,
+            inferred: trade.inferred,
+        }
+    }
+}
+
+/// Replays a wallet's past transactions through the live decoders and writes
+/// the trades found, newest first, as JSON lines. Nothing is executed.
+///
+/// History is decoded one page of signatures at a time, so long histories
+/// are never held in memory and trades appear in the file as they are found.
+pub struct Backfill {
+    rpc_client: RpcClient,
+    commitment: CommitmentConfig,
+    decoders: DecoderRegistry,
+    alt_resolver: AltResolver,
+}
+
+impl Backfill {
+    pub fn new(settings: &RpcSettings) -> Self {
+        Backfill {
+            rpc_client: RpcClient::new_with_commitment(
+                settings.rpc_endpoint.clone(),
+                settings.detection_commitment,
+            ),
+            commitment: settings.detection_commitment,
+            decoders: DecoderRegistry::with_defaults(),
+            alt_resolver: AltResolver::new(settings.rpc_endpoint.clone(), settings.detection_commitment),
+        }
+    }
+
+    pub async fn run(&self, args: &BackfillArgs) -> Result<()> {
+        info!("Backfilling {} into {}", args.wallet, args.output);
+        let mut writer = BufWriter::new(File::create(&args.output)
+            .map_err(|e| anyhow!("Failed to create {}: {}", args.output, e))?);
+        let mut before = None;
+        let mut transactions = 0;
+        let mut written = 0;
+
+        loop {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: args.limit.signature,
+                limit: Some(PAGE_SIZE),
+                commitment: Some(transaction::read_commitment(self.commitment)),
+            };
+            let page = self.rpc_client.get_signatures_for_address_with_config(&args.wallet, config).await?;
+            let page_len = page.len();
+            if let Some(last) = page.last() {
+                before = Some(Signature::from_str(&last.signature)?);
+            }
+
+            let mut reached_limit = false;
+            for status in page {
+                if args.limit.excludes(status.slot, status.block_time) {
+                    reached_limit = true;
+                    break;
+                }
+                transactions += 1;
+                if status.err.is_some() {
+                    debug!("Skipping failed transaction {}", status.signature);
+                    continue;
+                }
+                for record in self.decode(&status.signature, &args.wallet, status.slot, status.block_time).await {
+                    serde_json::to_writer(&mut writer, &record)?;
+                    writer.write_all(b"\n")?;
+                    written += 1;
+                }
+            }
+            writer.flush()?;
+            debug!("Decoded {} signatures for {}, {} trades so far", transactions, args.wallet, written);
+
+            if reached_limit || page_len < PAGE_SIZE {
+                break;
+            }
+        }
+
+        info!("Wrote {} trades from {} transactions to {}", written, transactions, args.output);
+        Ok(())
+    }
+
+    /// The wallet's trades in one transaction, in execution order.
+    async fn decode(&self, signature: &str, wallet: &Pubkey, slot: u64, block_time: Option<i64>) -> Vec<TradeRecord> {
+        let transaction = match transaction::fetch_transaction(
+            &self.rpc_client,
+            &self.alt_resolver,
+            signature,
+            self.commitment,
+        ).await {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                warn!("Skipping {}: {:?}", signature, e);
+                return Vec::new();
+            }
+        };
+        if transaction.failed {
+            return Vec::new();
+        }
+
+        self.decoders.decode(&transaction, *wallet)
+            .into_iter()
+            .map(|swap| TradeRecord::new(swap.into_trade(&transaction.signature), slot, block_time))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn parse(args: &[&str]) -> Result<BackfillArgs> {
+        BackfillArgs::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_every_limit() {
+        let wallet = Pubkey::new_unique().to_string();
+        let args = parse(&[
+            &wallet,
+            "--until-slot", "250000000",
+            "--until-date", "2024-06-01",
+            "--until-signature", fixtures::SIGNATURE,
+            "--output", "trades.jsonl",
+        ]).unwrap();
+        assert_eq!(args.wallet.to_string(), wallet);
+        assert_eq!(args.limit.slot, Some(250_000_000));
+        assert_eq!(args.limit.block_time, Some(1_717_200_000));
+        assert_eq!(args.limit.signature, Some(Signature::from_str(fixtures::SIGNATURE).unwrap()));
+        assert_eq!(args.output, "trades.jsonl");
+    }
+
+    #[test]
+    fn defaults_to_the_whole_history_in_a_file_named_after_the_wallet() {
+        let wallet = Pubkey::new_unique();
+        let args = parse(&[&wallet.to_string()]).unwrap();
+        assert!(args.limit.slot.is_none() && args.limit.block_time.is_none() && args.limit.signature.is_none());
+        assert_eq!(args.output, format!("backfill-{}.jsonl", wallet));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let wallet = Pubkey::new_unique().to_string();
+        for args in [
+            vec![],
+            vec!["not-a-wallet"],
+            vec![&wallet, "--until-slot"],
+            vec![&wallet, "--until-slot", "-1"],
+            vec![&wallet, "--until-date", "2024-13-01"],
+            vec![&wallet, "--until-date", "1717200000"],
+            vec![&wallet, "--until-signature", "abc"],
+            vec![&wallet, "--since-slot", "250000000"],
+        ] {
+            assert!(parse(&args).is_err(), "{:?} parsed", args);
+        }
+    }
+
+    #[test]
+    fn limits_stop_at_the_oldest_slot_and_date() {
+        let limit = BackfillLimit { slot: Some(250_000_000), block_time: Some(1_717_200_000), signature: None };
+        assert!(!limit.excludes(250_000_000, Some(1_717_200_000)));
+        assert!(limit.excludes(249_999_999, Some(1_717_200_000)));
+        assert!(limit.excludes(250_000_000, Some(1_717_199_999)));
+        assert!(!limit.excludes(250_000_000, None));
+        assert!(!BackfillLimit::default().excludes(0, Some(0)));
+    }
+}
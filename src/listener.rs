@@ -1,176 +1,672 @@
 use solana_sdk::pubkey::Pubkey;
-use serde_json::Value;
+use solana_sdk::signature::Signature;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
 use tokio::sync::mpsc;
+use futures::stream::{FuturesUnordered, StreamExt};
 use anyhow::Result;
-use crate::types::{TradeDetails, TradeType};
-use crate::config::Config;
-use tokio_tungstenite::connect_async;
-use url::Url;
-use log::{info, error, debug};
+use crate::types::TradeDetails;
+use crate::config::{Config, IngestionKind};
+use crate::transaction::{self, ParsedTransaction};
+use crate::alt::AltResolver;
+use crate::dex::DecoderRegistry;
+use crate::source::{IngestionSource, SourceEvent};
+use crate::source::race::Race;
+use crate::source::recorder::{FrameKind, Protocol, Recorder};
+use crate::source::replay::ReplaySource;
+use crate::source::grpc::GrpcSource;
+use crate::source::websocket::WebSocketSource;
+use crate::backoff::Backoff;
+use log::{info, error, debug, warn};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::str::FromStr;
-use futures::StreamExt;
-use futures::SinkExt;
-use tokio_tungstenite::tungstenite::Message;
-use base64::decode;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const EVENT_CHANNEL_SIZE: usize = 1000;
+const SEEN_SIGNATURES: usize = 10_000;
+const BACKFILL_PAGE_SIZE: usize = 1000;
+const BACKFILL_MAX_PAGES: usize = 10;
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+const COPY_CONFIRM_ATTEMPTS: usize = 75;
+const COPY_CONFIRM_INTERVAL: Duration = Duration::from_millis(400);
+const RACE_STATS_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Newest signature processed for a target; the lower bound of a backfill.
+#[derive(Debug, Clone)]
+struct ProcessedMark {
+    signature: String,
+    slot: u64,
+}
+
+/// Bounded set of recently handled signatures, so backfilled and live
+/// deliveries of the same transaction are only processed once.
+struct SeenSignatures {
+    order: VecDeque<String>,
+    set: HashSet<String>,
+}
+
+impl SeenSignatures {
+    fn new() -> Self {
+        SeenSignatures {
+            order: VecDeque::new(),
+            set: HashSet::new(),
+        }
+    }
+
+    /// Returns false if the signature was already seen.
+    fn insert(&mut self, signature: &str) -> bool {
+        if !self.set.insert(signature.to_string()) {
+            return false;
+        }
+        self.order.push_back(signature.to_string());
+        if self.order.len() > SEEN_SIGNATURES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Holds back trades whose confirmation finished early until every earlier
+/// trade of the same target has finished, so a sell never overtakes the buy
+/// before it.
+#[derive(Default)]
+struct ReleaseOrder {
+    enqueued: HashMap<Pubkey, u64>,
+    released: HashMap<Pubkey, u64>,
+    finished: HashMap<Pubkey, BTreeMap<u64, Option<TradeDetails>>>,
+}
+
+impl ReleaseOrder {
+    /// Sequence number of the target's next trade.
+    fn enqueue(&mut self, target: Pubkey) -> u64 {
+        let next = self.enqueued.entry(target).or_default();
+        *next += 1;
+        *next - 1
+    }
+
+    /// Records a finished confirmation and returns the target's trades that
+    /// are now in order to copy. Failed ones still advance the queue.
+    fn finish(&mut self, target: Pubkey, sequence: u64, trade: Option<TradeDetails>) -> Vec<TradeDetails> {
+        let finished = self.finished.entry(target).or_default();
+        finished.insert(sequence, trade);
+        let released = self.released.entry(target).or_default();
+        let mut ready = Vec::new();
+        while let Some(trade) = finished.remove(released) {
+            *released += 1;
+            ready.extend(trade);
+        }
+        ready
+    }
+}
+
+/// Connection bookkeeping for one ingestion source.
+#[derive(Debug, Default)]
+struct SourceState {
+    connections: u64,
+    live: bool,
+    /// When the current outage began, if the source is down.
+    outage_started: Option<Instant>,
+}
 
 pub struct Listener {
-    ws_endpoint: String,
-    target_wallet: Pubkey,
-    raydium_program_ids: Vec<String>,
+    sources: Vec<Box<dyn IngestionSource>>,
+    rpc_client: RpcClient,
+    commitment: CommitmentConfig,
+    decoders: DecoderRegistry,
+    alt_resolver: AltResolver,
+    target_wallets: Vec<Pubkey>,
+    last_processed: Mutex<HashMap<Pubkey, ProcessedMark>>,
+    seen: Mutex<SeenSignatures>,
+    source_states: Mutex<HashMap<String, SourceState>>,
+    ever_connected: AtomicBool,
+    copy_commitment: Option<CommitmentConfig>,
+    failed_skipped: AtomicU64,
+    recorder: Option<Arc<Recorder>>,
+    /// Gaps are only backfilled from RPC when following the chain live.
+    live: bool,
 }
 
 impl Listener {
     pub fn new(config: &Config) -> Result<Self> {
+        let target_wallets: Vec<Pubkey> = config.enabled_targets().map(|target| target.wallet).collect();
+        let recorder = config.record_file.as_deref()
+            .map(Recorder::new)
+            .transpose()?
+            .map(Arc::new);
+
+        let sources: Vec<Box<dyn IngestionSource>> = match config.ingestion_source {
+            IngestionKind::WebSocket => config.ws_endpoints.iter()
+                .map(|endpoint| Box::new(WebSocketSource::new(
+                    endpoint.clone(),
+                    target_wallets.clone(),
+                    config.ws_ping_interval,
+                    config.ws_liveness_timeout,
+                    config.detection_commitment,
+                    recorder.clone(),
+                )) as Box<dyn IngestionSource>)
+                .collect(),
+            IngestionKind::Grpc => vec![Box::new(GrpcSource::new(
+                config.grpc_endpoint.clone().unwrap_or_default(),
+                config.grpc_x_token.clone(),
+                target_wallets.clone(),
+                config.detection_commitment,
+                recorder.clone(),
+            ))],
+            IngestionKind::Replay => vec![Box::new(ReplaySource::new(
+                config.replay_file.clone().unwrap_or_default(),
+                config.replay_speed,
+            ))],
+        };
+
         Ok(Listener {
-            ws_endpoint: config.ws_endpoint.clone(),
-            target_wallet: Pubkey::from_str(&config.target_wallet)?,
-            raydium_program_ids: config.raydium_program_ids.clone(),
+            sources,
+            rpc_client: RpcClient::new_with_commitment(
+                config.rpc_endpoint.clone(),
+                config.detection_commitment,
+            ),
+            commitment: config.detection_commitment,
+            decoders: DecoderRegistry::with_defaults(),
+            alt_resolver: AltResolver::new(config.rpc_endpoint.clone(), config.detection_commitment),
+            target_wallets,
+            last_processed: Mutex::new(HashMap::new()),
+            seen: Mutex::new(SeenSignatures::new()),
+            source_states: Mutex::new(HashMap::new()),
+            ever_connected: AtomicBool::new(false),
+            copy_commitment: config.copy_commitment,
+            failed_skipped: AtomicU64::new(0),
+            recorder,
+            live: config.ingestion_source != IngestionKind::Replay,
         })
     }
-    
-    pub async fn start_listening(&self, tx: mpsc::Sender<TradeDetails>) -> Result<()> {
-        info!("Connecting to WebSocket endpoint: {}", self.ws_endpoint);
-        let url = Url::parse(&self.ws_endpoint)?;
-        
-        let (ws_stream, response) = connect_async(url).await?;
-        info!("WebSocket connected with status: {}", response.status());
-        
-        let (mut write, mut read) = ws_stream.split();
-        
-        // Subscribe to transaction notifications
-        let subscribe_msg = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "logsSubscribe",
-            "params": [
-                {
-                    "mentions": [ self.target_wallet.to_string() ]
-                },
-                {
-                    "commitment": "confirmed",
-                    "encoding": "jsonParsed"
+
+    /// Runs every source at once until shutdown. Each signature is processed
+    /// the first time any source delivers it; the gap left when all sources
+    /// were down is backfilled once one reconnects.
+    pub async fn run(&self, tx: mpsc::Sender<TradeDetails>, running: Arc<AtomicBool>) {
+        if self.live {
+            self.seed_last_processed().await;
+        }
+
+        let (events_tx, mut events_rx) = mpsc::channel(EVENT_CHANNEL_SIZE);
+        let (work_tx, mut work_rx) = mpsc::channel::<SourceEvent>(EVENT_CHANNEL_SIZE);
+
+        let supervisors = futures::future::join_all(self.sources.iter()
+            .map(|source| self.supervise(source.as_ref(), events_tx.clone(), &tx, &running)));
+        drop(events_tx);
+
+        // Dedupe before processing so arrival times are not skewed by fetches.
+        let racing = self.sources.len() > 1;
+        let race = async move {
+            let mut race = Race::new();
+            let mut report = tokio::time::interval(RACE_STATS_INTERVAL);
+            report.tick().await;
+            loop {
+                tokio::select! {
+                    event = events_rx.recv() => {
+                        let Some(event) = event else { break };
+                        if race.admit(&event) && work_tx.send(event).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = report.tick(), if racing => race.log_stats(),
                 }
-            ]
-        });
-        
-        info!("Sending subscription request: {}", subscribe_msg);
-        write.send(Message::Text(subscribe_msg.to_string())).await?;
-        
-        match read.next().await {
-            Some(Ok(Message::Text(text))) => {
-                info!("Subscription response: {}", text);
-            }
-            _ => error!("Failed to get subscription confirmation"),
-        }
-        
-        info!("Waiting for messages...");
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    info!("Received WebSocket message: {}", text);
-                    let v: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
-                    
-                    if let Some(method) = v.get("method") {
-                        info!("Message method: {}", method);
+            }
+        };
+
+        let (trades_tx, mut trades_rx) = mpsc::channel::<TradeDetails>(EVENT_CHANNEL_SIZE);
+        let process = async move {
+            while let Some(event) = work_rx.recv().await {
+                for trade in self.handle_event(event, &trades_tx).await {
+                    if trades_tx.send(trade).await.is_err() {
+                        return;
                     }
-                    
-                    if let Some(params) = v.get("params") {
-                        if let Some(result) = params.get("result") {
-                            if let Some(logs) = result.get("logs") {
-                                info!("Transaction logs: {:?}", logs);
-                                
-                                // Create a longer-lived Vec for the logs
-                                let empty_vec = Vec::new();
-                                let logs_array = logs.as_array().unwrap_or(&empty_vec);
-                                
-                                for log in logs_array {
-                                    let log_str = log.as_str().unwrap_or("");
-                                    info!("Log entry: {}", log_str);
-                                    
-                                    // Check for Raydium program invocations
-                                    if self.raydium_program_ids.iter().any(|id| log_str.contains(id)) {
-                                        info!("Found Raydium transaction!");
-                                        
-                                        if let Some(signature) = result.get("signature") {
-                                            info!("Transaction signature: {}", signature);
-                                            
-                                            if let Some(trade_details) = self.process_raydium_transaction(result)? {
-                                                tx.send(trade_details).await?;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                }
+            }
+        };
+
+        // Trades waiting on COPY_COMMITMENT are confirmed concurrently so a
+        // slow one does not hold up other targets, but each target's trades
+        // are released in the order it made them.
+        let confirm = async {
+            let mut pending = FuturesUnordered::new();
+            let mut order = ReleaseOrder::default();
+            loop {
+                let (target, sequence, confirmed) = tokio::select! {
+                    trade = trades_rx.recv() => match trade {
+                        Some(trade) => {
+                            let (target, sequence) = (trade.target, order.enqueue(trade.target));
+                            pending.push(async move { (target, sequence, self.confirm_copyable(trade).await) });
+                            continue;
+                        }
+                        None => break,
+                    },
+                    Some(finished) = pending.next(), if !pending.is_empty() => finished,
+                };
+                for trade in order.finish(target, sequence, confirmed) {
+                    if tx.send(trade).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            while let Some((target, sequence, confirmed)) = pending.next().await {
+                for trade in order.finish(target, sequence, confirmed) {
+                    if tx.send(trade).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+
+        tokio::join!(supervisors, race, process, confirm);
+    }
+
+    /// Keeps one source connected, backing off between reconnect attempts.
+    async fn supervise(
+        &self,
+        source: &dyn IngestionSource,
+        events: mpsc::Sender<SourceEvent>,
+        tx: &mpsc::Sender<TradeDetails>,
+        running: &AtomicBool,
+    ) {
+        let mut backoff = Backoff::new(RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY);
+        while running.load(Ordering::SeqCst) {
+            info!("Listening via {}", source.name());
+            let connections_before = self.connections(source.name());
+            match source.run(events.clone()).await {
+                Ok(_) => info!("Connection to {} closed", source.name()),
+                Err(e) => error!("Connection error on {}: {:?}", source.name(), e),
+            }
+            if !running.load(Ordering::SeqCst) || tx.is_closed() || !source.restartable() {
+                break;
+            }
+
+            if self.connections(source.name()) > connections_before {
+                backoff.reset();
+            }
+            let delay = backoff.next_delay();
+            warn!("Reconnecting to {} in {:?}", source.name(), delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn connections(&self, source: &str) -> u64 {
+        self.source_states.lock().unwrap()
+            .get(source)
+            .map(|state| state.connections)
+            .unwrap_or(0)
+    }
+
+    /// Trades found in `event`. Backfilled trades are sent to `trades` as
+    /// they are decoded instead, so a long gap does not hold them all back.
+    async fn handle_event(&self, event: SourceEvent, trades: &mpsc::Sender<TradeDetails>) -> Vec<TradeDetails> {
+        match event {
+            SourceEvent::Connected { source } => {
+                let (outage, others_live) = {
+                    let mut states = self.source_states.lock().unwrap();
+                    let others_live = states.iter().any(|(name, state)| *name != source && state.live);
+                    let state = states.entry(source.clone()).or_default();
+                    state.connections += 1;
+                    state.live = true;
+                    (state.outage_started.take(), others_live)
+                };
+                match outage {
+                    Some(started) => info!("{} is live again after a {:?} outage", source, started.elapsed()),
+                    None => info!("{} is live", source),
+                }
+
+                // Another live source covered the outage; otherwise fill the gap.
+                let reconnected = self.ever_connected.swap(true, Ordering::SeqCst);
+                if reconnected && !others_live && self.live {
+                    self.backfill(trades).await;
+                }
+                Vec::new()
+            }
+            SourceEvent::Disconnected { source, last_seen } => {
+                warn!("{} disconnected, last heard from {:?} ago", source, last_seen.elapsed());
+                let mut states = self.source_states.lock().unwrap();
+                let state = states.entry(source).or_default();
+                state.live = false;
+                state.outage_started.get_or_insert(last_seen);
+                Vec::new()
+            }
+            SourceEvent::Signature { source, signature, slot, target, failed } => {
+                debug!("Signature {} for {} at slot {} from {}", signature, target, slot, source);
+                if failed {
+                    if self.seen.lock().unwrap().insert(&signature) {
+                        self.skip_failed(&signature);
+                    }
+                    return Vec::new();
+                }
+                self.process_signature(&signature, target).await
+            }
+            SourceEvent::Transaction { source, transaction } => {
+                debug!("Transaction {} from {}", transaction.signature, source);
+                if !self.seen.lock().unwrap().insert(&transaction.signature) {
+                    return Vec::new();
+                }
+                self.handle_transaction(&transaction, None)
+            }
+        }
+    }
+
+    async fn process_signature(&self, signature: &str, target: Pubkey) -> Vec<TradeDetails> {
+        if !self.seen.lock().unwrap().insert(signature) {
+            debug!("Skipping already processed signature {}", signature);
+            return Vec::new();
+        }
+
+        let fetched = transaction::fetch_transaction(
+            &self.rpc_client,
+            &self.alt_resolver,
+            signature,
+            self.commitment,
+        ).await;
+
+        match fetched {
+            Ok(transaction) => {
+                if let Some(recorder) = &self.recorder {
+                    match serde_json::to_string(&transaction) {
+                        Ok(data) => recorder.record(self.rpc_client.url().as_str(), Protocol::Rpc, FrameKind::Transaction, data),
+                        Err(e) => warn!("Failed to record transaction {}: {}", signature, e),
+                    }
+                }
+                self.handle_transaction(&transaction, Some(target))
+            }
+            Err(e) => {
+                error!("Failed to process transaction {}: {:?}", signature, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Records and decodes the transaction for every followed wallet it
+    /// touches, not only the one it was delivered for, since signatures are
+    /// deduplicated across targets.
+    fn handle_transaction(&self, transaction: &ParsedTransaction, notified: Option<Pubkey>) -> Vec<TradeDetails> {
+        let targets: Vec<Pubkey> = self.target_wallets.iter()
+            .filter(|target| Some(**target) == notified || transaction.account_keys.contains(target))
+            .copied()
+            .collect();
+        for target in &targets {
+            self.record_processed(*target, transaction);
+        }
+        if transaction.failed {
+            self.skip_failed(&transaction.signature);
+            return Vec::new();
+        }
+        targets.iter()
+            .flat_map(|target| self.decode_transaction(transaction, *target))
+            .collect()
+    }
+
+    fn skip_failed(&self, signature: &str) {
+        let skipped = self.failed_skipped.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("Dropping failed transaction {} ({} failed target transactions so far)", signature, skipped);
+    }
+
+    /// Waits for a trade's transaction to reach COPY_COMMITMENT. Returns
+    /// `None` if it failed or never got there.
+    async fn confirm_copyable(&self, trade: TradeDetails) -> Option<TradeDetails> {
+        let Some(commitment) = self.copy_commitment else {
+            return Some(trade);
+        };
+        let signature = Signature::from_str(&trade.signature).ok()?;
+
+        for _ in 0..COPY_CONFIRM_ATTEMPTS {
+            match self.rpc_client.get_signature_statuses(&[signature]).await {
+                Ok(response) => {
+                    if let Some(Some(status)) = response.value.first() {
+                        if status.err.is_some() {
+                            self.skip_failed(&trade.signature);
+                            return None;
+                        }
+                        if status.satisfies_commitment(commitment) {
+                            return Some(trade);
                         }
                     }
-                },
-                Ok(Message::Close(frame)) => {
-                    error!("WebSocket closed by server: {:?}", frame);
-                    break;
-                },
-                Err(e) => {
-                    error!("WebSocket error: {:?}", e);
-                    break;
-                },
-                _ => {
-                    debug!("Received non-text message");
                 }
+                Err(e) => debug!("getSignatureStatuses for {} failed: {}", trade.signature, e),
             }
+            tokio::time::sleep(COPY_CONFIRM_INTERVAL).await;
         }
-        
-        info!("WebSocket connection closed, attempting to reconnect...");
-        Ok(())
-    }
-
-    fn process_raydium_transaction(&self, transaction: &Value) -> Result<Option<TradeDetails>> {
-        // Get logs directly from the transaction parameter
-        if let Some(logs) = transaction.get("logs").and_then(|l| l.as_array()) {
-            for log in logs {
-                if let Some(log_str) = log.as_str() {
-                    // Look for ray_log entries
-                    if log_str.contains("ray_log:") {
-                        info!("Found Raydium log entry");
-                        let encoded_data = log_str.split("ray_log: ").nth(1).unwrap_or("");
-                        let decoded = decode(encoded_data)?;
-                        
-                        // Raydium swap data structure:
-                        // 0-8:   input amount (u64)
-                        // 8-16:  minimum output amount (u64)
-                        // 16-24: fee (u64)
-                        // 24-32: price impact (u64)
-                        
-                        let input_amount = u64::from_le_bytes(decoded[0..8].try_into()?);
-                        let min_output = u64::from_le_bytes(decoded[8..16].try_into()?);
-                        let fee = u64::from_le_bytes(decoded[16..24].try_into()?);
-                        let price_impact = u64::from_le_bytes(decoded[24..32].try_into()?);
-                        
-                        info!("Decoded swap details:");
-                        info!("  Input amount: {} lamports", input_amount);
-                        info!("  Minimum output: {} lamports", min_output);
-                        info!("  Fee: {} lamports", fee);
-                        info!("  Price Impact: {}", price_impact);
-                        
-                        // Get transaction signature
-                        let signature = transaction
-                            .get("signature")
-                            .and_then(|s| s.as_str())
-                            .unwrap_or("unknown");
-                            
-                        info!("Transaction signature: {}", signature);
-                        
-                        return Ok(Some(TradeDetails {
-                            pool_id: Pubkey::new_unique(),
-                            input_token: Pubkey::new_unique(),
-                            output_token: Pubkey::new_unique(),
-                            input_amount,
-                            output_amount: min_output,
-                            trade_type: TradeType::Swap,
-                        }));
+
+        warn!("{} did not reach {:?} in time, not copying", trade.signature, commitment.commitment);
+        None
+    }
+
+    fn record_processed(&self, target: Pubkey, transaction: &ParsedTransaction) {
+        let mut last_processed = self.last_processed.lock().unwrap();
+        let newer = last_processed.get(&target)
+            .map(|mark| transaction.slot >= mark.slot)
+            .unwrap_or(true);
+        if newer {
+            last_processed.insert(target, ProcessedMark {
+                signature: transaction.signature.clone(),
+                slot: transaction.slot,
+            });
+        }
+    }
+
+    /// Starts every target's backfill window at its newest signature, so an
+    /// outage before the first trade is still covered.
+    async fn seed_last_processed(&self) {
+        for target in &self.target_wallets {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                limit: Some(1),
+                commitment: Some(transaction::read_commitment(self.commitment)),
+                ..Default::default()
+            };
+            match self.rpc_client.get_signatures_for_address_with_config(target, config).await {
+                Ok(signatures) => {
+                    if let Some(newest) = signatures.first() {
+                        self.last_processed.lock().unwrap().insert(*target, ProcessedMark {
+                            signature: newest.signature.clone(),
+                            slot: newest.slot,
+                        });
+                    }
+                }
+                Err(e) => warn!("Could not load latest signature for {}: {}", target, e),
+            }
+        }
+    }
+
+    /// Processes every target signature newer than the last one handled,
+    /// oldest first, sending each trade on as soon as it is decoded.
+    async fn backfill(&self, trades: &mpsc::Sender<TradeDetails>) {
+        let marks: Vec<(Pubkey, ProcessedMark)> = self.last_processed.lock().unwrap()
+            .iter()
+            .map(|(target, mark)| (*target, mark.clone()))
+            .collect();
+
+        for (target, mark) in marks {
+            let signatures = match self.signatures_since(&target, &mark).await {
+                Ok(signatures) => signatures,
+                Err(e) => {
+                    error!("Backfill for {} failed: {:?}", target, e);
+                    continue;
+                }
+            };
+            info!("Backfilling {} signatures for {} since slot {}", signatures.len(), target, mark.slot);
+
+            for signature in signatures.iter().rev() {
+                for trade in self.process_signature(signature, target).await {
+                    if trades.send(trade).await.is_err() {
+                        return;
                     }
                 }
             }
         }
-        
-        Ok(None)
     }
-}
\ No newline at end of file
+
+    /// Signatures for `address` newer than `mark`, newest first. A gap longer
+    /// than BACKFILL_MAX_PAGES is cut short at its oldest end, with a warning
+    /// naming the range that was not covered.
+    async fn signatures_since(&self, address: &Pubkey, mark: &ProcessedMark) -> Result<Vec<String>> {
+        let until = Signature::from_str(&mark.signature)?;
+        let mut signatures = Vec::new();
+        let mut before = None;
+        let mut oldest_slot = None;
+
+        for page_number in 1..=BACKFILL_MAX_PAGES {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: Some(until),
+                limit: Some(BACKFILL_PAGE_SIZE),
+                commitment: Some(transaction::read_commitment(self.commitment)),
+            };
+            let page = self.rpc_client.get_signatures_for_address_with_config(address, config).await?;
+            let page_len = page.len();
+            if let Some(last) = page.last() {
+                before = Some(Signature::from_str(&last.signature)?);
+                oldest_slot = Some(last.slot);
+            }
+            signatures.extend(page.into_iter().map(|status| status.signature));
+            if page_len < BACKFILL_PAGE_SIZE {
+                break;
+            }
+            if page_number == BACKFILL_MAX_PAGES {
+                warn!(
+                    "Backfill for {} stopped after {} signatures; trades between {} (slot {}) and {} (slot {}) are not copied",
+                    address,
+                    signatures.len(),
+                    mark.signature,
+                    mark.slot,
+                    signatures.last().map(String::as_str).unwrap_or_default(),
+                    oldest_slot.unwrap_or_default(),
+                );
+            }
+        }
+
+        Ok(signatures)
+    }
+
+    /// Every distinct swap the target made in the transaction.
+    fn decode_transaction(&self, transaction: &ParsedTransaction, target: Pubkey) -> Vec<TradeDetails> {
+        let swaps = self.decoders.decode(transaction, target);
+        if swaps.is_empty() {
+            debug!("No target swap found in {}", transaction.signature);
+        }
+        swaps.into_iter()
+            .map(|swap| {
+                info!("Decoded {:?} swap details:", swap.dex);
+                info!("  Program: {}", swap.program_id);
+                info!("  Pool: {}", swap.pool_id);
+                info!("  Instruction: {}", swap.outer_index);
+                info!("  Input: {} of {}", swap.input_amount, swap.input_mint);
+                info!("  Output: {} of {}", swap.output_amount, swap.output_mint);
+                swap.into_trade(&transaction.signature)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::{Dex, TradeType};
+
+    /// A listener following `targets` with no sources, whose RPC endpoint
+    /// refuses connections.
+    fn listener(targets: Vec<Pubkey>) -> Listener {
+        let endpoint = "http://127.0.0.1:9".to_string();
+        Listener {
+            sources: Vec::new(),
+            rpc_client: RpcClient::new(endpoint.clone()),
+            commitment: CommitmentConfig::confirmed(),
+            decoders: DecoderRegistry::with_defaults(),
+            alt_resolver: AltResolver::new(endpoint, CommitmentConfig::confirmed()),
+            target_wallets: targets,
+            last_processed: Mutex::new(HashMap::new()),
+            seen: Mutex::new(SeenSignatures::new()),
+            source_states: Mutex::new(HashMap::new()),
+            ever_connected: AtomicBool::new(false),
+            copy_commitment: None,
+            failed_skipped: AtomicU64::new(0),
+            recorder: None,
+            live: false,
+        }
+    }
+
+    fn trade(target: Pubkey, signature: &str) -> TradeDetails {
+        TradeDetails {
+            signature: signature.to_string(),
+            target,
+            dex: Dex::RaydiumAmmV4,
+            pool_id: Pubkey::new_unique(),
+            input_token: spl_token::native_mint::id(),
+            output_token: Pubkey::new_unique(),
+            input_amount: 1_000_000_000,
+            output_amount: 2_588_000,
+            trade_type: TradeType::Swap,
+            inferred: false,
+        }
+    }
+
+    fn signatures(trades: Vec<TradeDetails>) -> Vec<String> {
+        trades.into_iter().map(|trade| trade.signature).collect()
+    }
+
+    #[test]
+    fn releases_each_targets_trades_in_order() {
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut order = ReleaseOrder::default();
+        let buy = order.enqueue(first);
+        let sell = order.enqueue(first);
+        let other = order.enqueue(second);
+        let later = order.enqueue(first);
+
+        // The sell confirmed first but waits for the buy; the other target
+        // is not held up.
+        assert!(order.finish(first, sell, Some(trade(first, "sell"))).is_empty());
+        assert_eq!(signatures(order.finish(second, other, Some(trade(second, "other")))), vec!["other"]);
+        assert_eq!(signatures(order.finish(first, buy, Some(trade(first, "buy")))), vec!["buy", "sell"]);
+        assert_eq!(signatures(order.finish(first, later, Some(trade(first, "later")))), vec!["later"]);
+    }
+
+    #[test]
+    fn a_trade_that_never_confirms_still_advances_the_queue() {
+        let target = Pubkey::new_unique();
+        let mut order = ReleaseOrder::default();
+        let failed = order.enqueue(target);
+        let next = order.enqueue(target);
+        assert!(order.finish(target, next, Some(trade(target, "next"))).is_empty());
+        assert_eq!(signatures(order.finish(target, failed, None)), vec!["next"]);
+    }
+
+    #[tokio::test]
+    async fn drops_failed_transactions_and_counts_them_once() {
+        let target = Pubkey::new_unique();
+        let listener = listener(vec![target]);
+        let (trades, _receiver) = mpsc::channel(10);
+
+        let mut transaction = fixtures::transaction(target, Vec::new());
+        transaction.failed = true;
+        let event = SourceEvent::Transaction { source: "grpc".to_string(), transaction: transaction.clone() };
+        assert!(listener.handle_event(event, &trades).await.is_empty());
+        assert_eq!(listener.failed_skipped.load(Ordering::Relaxed), 1);
+        // It still moves the backfill mark past it.
+        assert_eq!(listener.last_processed.lock().unwrap()[&target].slot, transaction.slot);
+
+        let failed = |signature: &str| SourceEvent::Signature {
+            source: "helius".to_string(),
+            signature: signature.to_string(),
+            slot: 250_000_001,
+            target,
+            failed: true,
+        };
+        // Reported failed by the notification, so it is never fetched.
+        let signature = Signature::new_unique().to_string();
+        assert!(listener.handle_event(failed(&signature), &trades).await.is_empty());
+        assert!(listener.handle_event(failed(&signature), &trades).await.is_empty());
+        assert!(listener.handle_event(failed(&transaction.signature), &trades).await.is_empty());
+        assert_eq!(listener.failed_skipped.load(Ordering::Relaxed), 2);
+    }
+}
@@ -4,15 +4,34 @@ mod listener;
 mod trader;
 mod utils;
 mod types;
+mod transaction;
+mod ray_log;
+mod source;
+mod backoff;
+mod dex;
+mod positions;
+mod migration;
+mod alt;
+mod history;
+mod pool_cache;
+mod quote;
+mod reserves;
+#[cfg(test)]
+mod fixtures;
 
 use tokio::sync::mpsc;
 use anyhow::Result;
-use config::Config;
+use config::{Config, IngestionKind, RpcSettings};
+use history::{Backfill, BackfillArgs};
 use listener::Listener;
+use migration::MigrationWatcher;
+use pool_cache::PoolCache;
+use positions::PositionBook;
+use reserves::ReserveTracker;
+use source::recorder::redact;
 use trader::Trader;
 use types::TradeDetails;
 use log::{info, error};
-use env_logger;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -30,53 +49,107 @@ async fn main() -> Result<()> {
     
     // Handle Ctrl+C
     tokio::spawn(async move {
-        if let Ok(_) = signal::ctrl_c().await {
+        if signal::ctrl_c().await.is_ok() {
             info!("Shutting down...");
             r.store(false, Ordering::SeqCst);
         }
     });
 
+    // `backfill <wallet> ..` decodes a wallet's history to a file and exits.
+    // It only reads the chain, so it needs no wallet or targets.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("backfill") {
+        let backfill_args = BackfillArgs::parse(&args[2..])?;
+        let settings = RpcSettings::new()?;
+        return Backfill::new(&settings).run(&backfill_args).await;
+    }
+
     match Config::new() {
         Ok(config) => {
             info!("Configuration loaded successfully");
-            info!("Using RPC endpoint: {}", config.rpc_endpoint);
-            info!("Using WebSocket endpoint: {}", config.ws_endpoint);
-            info!("Monitoring wallet: {}", config.target_wallet);
 
-            let trader = Trader::new(&config)?;
+            info!("Using RPC endpoint: {}", redact(&config.rpc_endpoint));
+            let ws_endpoints: Vec<String> = config.ws_endpoints.iter().map(|endpoint| redact(endpoint)).collect();
+            info!("Using WebSocket endpoints: {:?}", ws_endpoints);
+            if config.paper_trading {
+                info!("Paper trading: copies are built but never sent");
+            }
+            for target in config.enabled_targets() {
+                info!("Monitoring wallet: {} ({:?})", target.wallet, target.settings);
+            }
+
+            let positions = Arc::new(PositionBook::new());
+            let pool_cache = Arc::new(PoolCache::load(&config.pool_cache_file));
+            let reserve_tracker = Arc::new(ReserveTracker::new(&config, pool_cache.clone(), positions.clone()));
+            let trader = Arc::new(Trader::new(&config, positions.clone(), pool_cache, reserve_tracker.clone())?);
+            let migration_watcher = MigrationWatcher::new(&config, positions);
             let listener = Listener::new(&config)?;
             let (tx, mut rx) = mpsc::channel::<TradeDetails>(100);
             
             // Start listening for wallet activity
             let running_listener = running.clone();
             let listen_handle = tokio::spawn(async move {
-                while running_listener.load(Ordering::SeqCst) {
-                    match listener.start_listening(tx.clone()).await {
-                        Ok(_) => {
-                            // Connection closed normally
-                            continue;
-                        }
-                        Err(e) => {
-                            error!("Connection error: {:?}, reconnecting...", e);
+                listener.run(tx, running_listener).await;
+            });
+
+            // Follow held pump.fun tokens onto Raydium when their curve completes
+            let running_watcher = running.clone();
+            tokio::spawn(async move {
+                migration_watcher.run(running_watcher).await;
+            });
+
+            // Keep reserves of pools we trade current for quoting, and sell
+            // positions that reach their take profit or stop loss. A replay
+            // prices copies from the capture alone so it stays reproducible.
+            if config.ingestion_source != IngestionKind::Replay {
+                let running_tracker = running.clone();
+                tokio::spawn(async move {
+                    reserve_tracker.run(running_tracker).await;
+                });
+
+                let running_exits = running.clone();
+                let exit_trader = trader.clone();
+                let exit_poll_interval = config.exit_poll_interval;
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(exit_poll_interval);
+                    while running_exits.load(Ordering::SeqCst) {
+                        interval.tick().await;
+                        let trader = exit_trader.clone();
+                        if let Err(e) = tokio::task::spawn_blocking(move || trader.check_exits()).await {
+                            error!("Exit check panicked: {:?}", e);
                         }
                     }
-                }
-            });
+                });
+            }
 
             // info!("Fetching wallet information...");
             // Wallet::get_bal(config.rpc_endpoint, &config.target_wallet);
             
             // Process any detected trades
+            let replaying = config.ingestion_source == IngestionKind::Replay;
             while let Some(trade) = rx.recv().await {
                 info!("Detected new transaction:");
-                info!("  Type: {:?}", trade.trade_type);
-                info!("  Input Amount: {} lamports", trade.input_amount);
-                info!("  Output Amount: {} lamports", trade.output_amount);
+                info!("  Signature: {}", trade.signature);
+                info!("  Target: {}", trade.target);
+                info!("  Type: {:?} on {:?}{}", trade.trade_type, trade.dex,
+                    if trade.inferred { " (inferred from balances)" } else { "" });
+                info!("  Input: {} of {}", trade.input_amount, trade.input_token);
+                info!("  Output: {} of {}", trade.output_amount, trade.output_token);
                 info!("  Pool: {}", trade.pool_id);
                 
-                match trader.execute_trade(trade) {
+                // Copies quote, simulate and confirm over blocking RPC, so each
+                // runs on the blocking pool and a slow one does not hold up
+                // the next. A replay copies in capture order so it always ends
+                // with the same positions.
+                let trader = trader.clone();
+                let copy = tokio::task::spawn_blocking(move || match trader.execute_trade(trade) {
                     Ok(_) => info!("Successfully copied trade"),
                     Err(e) => error!("Failed to copy trade: {:?}", e),
+                });
+                if replaying {
+                    if let Err(e) = copy.await {
+                        error!("Copy panicked: {:?}", e);
+                    }
                 }
             }
 
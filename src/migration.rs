@@ -0,0 +1,130 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use anyhow::Result;
+use log::{info, error, debug, warn};
+use crate::config::Config;
+use crate::dex::raydium_amm_v4;
+use crate::dex::pump_fun::BondingCurve;
+use crate::positions::PositionBook;
+use crate::transaction;
+use crate::types::Dex;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Watches positions opened on pump.fun bonding curves. Once a curve
+/// completes and its liquidity lands in a Raydium AMM v4 pool, the position
+/// is rebound to that pool so copied sells route there.
+pub struct MigrationWatcher {
+    rpc_client: RpcClient,
+    positions: Arc<PositionBook>,
+    poll_interval: Duration,
+}
+
+impl MigrationWatcher {
+    pub fn new(config: &Config, positions: Arc<PositionBook>) -> Self {
+        MigrationWatcher {
+            rpc_client: RpcClient::new_with_commitment(
+                config.rpc_endpoint.clone(),
+                transaction::read_commitment(config.detection_commitment),
+            ),
+            positions,
+            poll_interval: config.migration_poll_interval,
+        }
+    }
+
+    pub async fn run(&self, running: Arc<AtomicBool>) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        while running.load(Ordering::SeqCst) {
+            interval.tick().await;
+            if let Err(e) = self.check_positions().await {
+                error!("Migration check failed: {:?}", e);
+            }
+        }
+    }
+
+    async fn check_positions(&self) -> Result<()> {
+        let positions = self.positions.on_dex(Dex::PumpFun);
+        if positions.is_empty() {
+            return Ok(());
+        }
+
+        let curves: Vec<Pubkey> = positions.iter().map(|position| position.pool_id).collect();
+        let accounts = self.rpc_client.get_multiple_accounts(&curves).await?;
+
+        for (position, account) in positions.iter().zip(accounts) {
+            let complete = account
+                .and_then(|account| BondingCurve::from_account_data(&account.data).ok())
+                .map(|curve| curve.complete)
+                .unwrap_or(false);
+            if !complete {
+                continue;
+            }
+
+            info!("Bonding curve for {} completed, looking for its Raydium pool", position.mint);
+            match self.find_amm_pool(&position.mint).await {
+                Ok(Some(pool)) => self.positions.rebind(&position.mint, Dex::RaydiumAmmV4, pool),
+                Ok(None) => debug!("No Raydium pool for {} yet", position.mint),
+                Err(e) => warn!("Looking up the Raydium pool for {} failed: {:?}", position.mint, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// AMM v4 pool pairing `mint` with wrapped SOL, on either side. When
+    /// there are several, the one holding the most SOL wins.
+    async fn find_amm_pool(&self, mint: &Pubkey) -> Result<Option<Pubkey>> {
+        let native_mint = spl_token::native_mint::id();
+        // Each candidate with the vault holding its SOL.
+        let mut candidates: Vec<(Pubkey, Pubkey)> = Vec::new();
+        for (coin, pc) in [(mint, &native_mint), (&native_mint, mint)] {
+            let config = RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::DataSize(raydium_amm_v4::AMM_INFO_SIZE),
+                    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(raydium_amm_v4::COIN_MINT_OFFSET, coin.as_ref())),
+                    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(raydium_amm_v4::PC_MINT_OFFSET, pc.as_ref())),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice: Some(UiDataSliceConfig { offset: raydium_amm_v4::COIN_VAULT_OFFSET, length: 64 }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let pools = self.rpc_client
+                .get_program_accounts_with_config(&raydium_amm_v4::PROGRAM_ID, config)
+                .await?;
+            let sol_vault_offset = if *coin == native_mint {
+                0
+            } else {
+                raydium_amm_v4::PC_VAULT_OFFSET - raydium_amm_v4::COIN_VAULT_OFFSET
+            };
+            for (pool, account) in pools {
+                if let Some(vault) = account.data.get(sol_vault_offset..sol_vault_offset + 32) {
+                    candidates.push((pool, Pubkey::try_from(vault)?));
+                }
+            }
+        }
+        if candidates.len() <= 1 {
+            return Ok(candidates.first().map(|(pool, _)| *pool));
+        }
+
+        let vaults: Vec<Pubkey> = candidates.iter().map(|(_, vault)| *vault).collect();
+        let balances = self.rpc_client.get_multiple_accounts(&vaults).await?;
+        let deepest = candidates.iter()
+            .zip(balances)
+            .map(|((pool, _), vault)| {
+                let lamports = vault
+                    .and_then(|vault| Some(u64::from_le_bytes(vault.data.get(64..72)?.try_into().ok()?)))
+                    .unwrap_or(0);
+                (lamports, *pool)
+            })
+            .max()
+            .map(|(_, pool)| pool);
+        debug!("{} Raydium pools for {}, choosing {:?}", candidates.len(), mint, deepest);
+        Ok(deepest)
+    }
+}
@@ -0,0 +1,193 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_client::rpc_client::RpcClient;
+use anyhow::{Result, anyhow};
+use log::{info, warn, debug};
+use serde::{Deserialize, Serialize};
+use crate::dex::raydium_amm_v4::{AmmInfo, MarketAccounts};
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An AMM v4 pool and the OpenBook market it trades against.
+#[derive(Debug, Clone)]
+pub struct PoolState {
+    pub amm: AmmInfo,
+    pub market: MarketAccounts,
+}
+
+/// Raw account data as persisted, so the file survives layout changes in
+/// the decoded structs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedAccounts {
+    amm: String,
+    market: String,
+    /// Unix time the accounts were fetched.
+    fetched_at: u64,
+}
+
+impl CachedAccounts {
+    fn decode(&self, pool_id: &Pubkey) -> Result<PoolState> {
+        let amm = AmmInfo::from_account_data(&base64::decode(&self.amm)?)?;
+        let market = MarketAccounts::from_account_data(
+            &amm.market_id,
+            &amm.market_program_id,
+            &base64::decode(&self.market)?,
+        ).map_err(|e| anyhow!("Cached market of {}: {}", pool_id, e))?;
+        Ok(PoolState { amm, market })
+    }
+}
+
+/// Decoded Raydium pool state keyed by pool ID, fetched on first use and
+/// written to disk so a restart can copy without refetching every pool.
+pub struct PoolCache {
+    path: String,
+    pools: Mutex<HashMap<Pubkey, (PoolState, CachedAccounts)>>,
+}
+
+impl PoolCache {
+    /// Loads the cache file if there is one. Entries that no longer decode
+    /// are dropped and fetched again when needed.
+    pub fn load(path: &str) -> Self {
+        let mut pools = HashMap::new();
+        match fs::read_to_string(path) {
+            Ok(contents) => match serde_json::from_str::<HashMap<String, CachedAccounts>>(&contents) {
+                Ok(entries) => {
+                    for (pool_id, cached) in entries {
+                        let decoded = Pubkey::from_str(&pool_id)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|pool| Ok((pool, cached.decode(&pool)?)));
+                        match decoded {
+                            Ok((pool, state)) => {
+                                pools.insert(pool, (state, cached));
+                            }
+                            Err(e) => warn!("Dropping cached pool {}: {:?}", pool_id, e),
+                        }
+                    }
+                    info!("Loaded {} pools from {}", pools.len(), path);
+                }
+                Err(e) => warn!("Ignoring unreadable pool cache {}: {}", path, e),
+            },
+            Err(e) => debug!("No pool cache at {}: {}", path, e),
+        }
+
+        PoolCache {
+            path: path.to_string(),
+            pools: Mutex::new(pools),
+        }
+    }
+
+    /// The cached state of `pool_id`, fetching it on a miss.
+    pub fn get(&self, rpc_client: &RpcClient, pool_id: &Pubkey) -> Result<PoolState> {
+        if let Some((state, _)) = self.pools.lock().unwrap().get(pool_id) {
+            return Ok(state.clone());
+        }
+        self.refresh(rpc_client, pool_id)
+    }
+
+    /// Fetches `pool_id` and its market again and replaces the cached entry.
+    pub fn refresh(&self, rpc_client: &RpcClient, pool_id: &Pubkey) -> Result<PoolState> {
+        let amm_data = rpc_client.get_account_data(pool_id)?;
+        let amm = AmmInfo::from_account_data(&amm_data)?;
+        let market_data = rpc_client.get_account_data(&amm.market_id)?;
+        let market = MarketAccounts::from_account_data(&amm.market_id, &amm.market_program_id, &market_data)?;
+        debug!("Fetched pool {} (status {}, decimals {}/{}) with market {}",
+            pool_id, amm.status, amm.coin_decimals, amm.pc_decimals, amm.market_id);
+
+        let state = PoolState { amm, market };
+        let cached = CachedAccounts {
+            amm: base64::encode(&amm_data),
+            market: base64::encode(&market_data),
+            fetched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0),
+        };
+        self.pools.lock().unwrap().insert(*pool_id, (state.clone(), cached));
+
+        if let Err(e) = self.save() {
+            warn!("Failed to save pool cache to {}: {:?}", self.path, e);
+        }
+        Ok(state)
+    }
+
+    /// Writes the cache to a temporary file and moves it into place, so a
+    /// crash mid-write leaves the previous file intact.
+    fn save(&self) -> Result<()> {
+        let entries: HashMap<String, CachedAccounts> = self.pools.lock().unwrap()
+            .iter()
+            .map(|(pool_id, (_, cached))| (pool_id.to_string(), cached.clone()))
+            .collect();
+        let temporary = format!("{}.tmp", self.path);
+        fs::write(&temporary, serde_json::to_string(&entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn cache_path() -> String {
+        let path = std::env::temp_dir().join(format!("pool-cache-{}.json", Pubkey::new_unique()));
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Cached accounts of a pool and its market, as `refresh` stores them.
+    fn cached_accounts(coin_vault: Pubkey) -> CachedAccounts {
+        let market_id = Pubkey::new_unique();
+        let mints = (spl_token::native_mint::id(), Pubkey::new_unique());
+        CachedAccounts {
+            amm: base64::encode(fixtures::amm_info_data((coin_vault, Pubkey::new_unique()), mints, &market_id)),
+            market: base64::encode(fixtures::market_data(&market_id)),
+            fetched_at: 1_717_000_000,
+        }
+    }
+
+    #[test]
+    fn reloads_saved_pools_without_fetching() {
+        let path = cache_path();
+        let cache = PoolCache::load(&path);
+        assert!(cache.pools.lock().unwrap().is_empty());
+
+        let (pool_id, coin_vault) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let cached = cached_accounts(coin_vault);
+        let state = cached.decode(&pool_id).unwrap();
+        cache.pools.lock().unwrap().insert(pool_id, (state.clone(), cached));
+        cache.save().unwrap();
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+        // The endpoint refuses connections, so only a cached pool is found.
+        let rpc_client = RpcClient::new("http://127.0.0.1:9".to_string());
+        let reloaded = PoolCache::load(&path).get(&rpc_client, &pool_id).unwrap();
+        assert_eq!(reloaded.amm.coin_vault, coin_vault);
+        assert_eq!(reloaded.amm.market_id, state.amm.market_id);
+        assert_eq!(reloaded.market.vault_signer, state.market.vault_signer);
+        assert!(PoolCache::load(&path).get(&rpc_client, &Pubkey::new_unique()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn drops_entries_that_no_longer_decode() {
+        let path = cache_path();
+        let pool_id = Pubkey::new_unique();
+        let mut truncated = cached_accounts(Pubkey::new_unique());
+        truncated.amm = base64::encode([0u8; 700]);
+        let entries: HashMap<String, CachedAccounts> = HashMap::from([
+            (pool_id.to_string(), cached_accounts(Pubkey::new_unique())),
+            (Pubkey::new_unique().to_string(), truncated),
+            ("not a pubkey".to_string(), cached_accounts(Pubkey::new_unique())),
+        ]);
+        fs::write(&path, serde_json::to_string(&entries).unwrap()).unwrap();
+
+        let pools: Vec<Pubkey> = PoolCache::load(&path).pools.lock().unwrap().keys().copied().collect();
+        assert_eq!(pools, vec![pool_id]);
+
+        fs::write(&path, "{").unwrap();
+        assert!(PoolCache::load(&path).pools.lock().unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use solana_sdk::pubkey::Pubkey;
+use log::info;
+use crate::config::CopySettings;
+use crate::types::Dex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Tokens we hold from copied buys, and where they currently trade.
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub mint: Pubkey,
+    pub dex: Dex,
+    pub pool_id: Pubkey,
+    pub amount: u64,
+    /// Lamports spent opening the position; the basis for TP/SL.
+    pub cost_lamports: u64,
+    /// Gain and loss, as fractions of the cost, at which the position is
+    /// sold. Taken from the target whose trade we last copied into it.
+    pub take_profit: f64,
+    pub stop_loss: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    TakeProfit,
+    StopLoss,
+}
+
+impl Position {
+    /// Whether a position now worth `value_lamports` should be sold.
+    /// Positions without a cost basis, such as copies the book only learned
+    /// about from a sell, never exit on their own.
+    pub fn exit(&self, value_lamports: u64) -> Option<Exit> {
+        if self.cost_lamports == 0 {
+            return None;
+        }
+        let cost = self.cost_lamports as f64;
+        let value = value_lamports as f64;
+        if self.take_profit > 0.0 && value >= cost * (1.0 + self.take_profit) {
+            Some(Exit::TakeProfit)
+        } else if self.stop_loss > 0.0 && value <= cost * (1.0 - self.stop_loss) {
+            Some(Exit::StopLoss)
+        } else {
+            None
+        }
+    }
+}
+
+/// Open positions keyed by mint, shared between the trader and the
+/// migration watcher.
+pub struct PositionBook {
+    positions: Mutex<HashMap<Pubkey, Position>>,
+}
+
+impl PositionBook {
+    pub fn new() -> Self {
+        PositionBook {
+            positions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds a buy to the position in `mint`. A later buy on another venue
+    /// moves the position to that venue, and one copied from another target
+    /// takes that target's exits.
+    pub fn open(&self, mint: Pubkey, dex: Dex, pool_id: Pubkey, amount: u64, cost_lamports: u64, settings: Option<&CopySettings>) {
+        let mut positions = self.positions.lock().unwrap();
+        let position = positions.entry(mint).or_insert(Position {
+            mint,
+            dex,
+            pool_id,
+            amount: 0,
+            cost_lamports: 0,
+            take_profit: 0.0,
+            stop_loss: 0.0,
+        });
+        if let Some(settings) = settings {
+            position.take_profit = settings.take_profit;
+            position.stop_loss = settings.stop_loss;
+        }
+        position.dex = dex;
+        position.pool_id = pool_id;
+        position.amount += amount;
+        position.cost_lamports += cost_lamports;
+    }
+
+    /// Removes sold tokens, closing the position once nothing is left.
+    pub fn reduce(&self, mint: &Pubkey, amount: u64) {
+        let mut positions = self.positions.lock().unwrap();
+        if let Some(position) = positions.get_mut(mint) {
+            let sold = amount.min(position.amount);
+            if position.amount > 0 {
+                position.cost_lamports -= (position.cost_lamports as u128 * sold as u128 / position.amount as u128) as u64;
+            }
+            position.amount -= sold;
+            if position.amount == 0 {
+                positions.remove(mint);
+            }
+        }
+    }
+
+    /// Points a position at the pool its liquidity moved to.
+    pub fn rebind(&self, mint: &Pubkey, dex: Dex, pool_id: Pubkey) {
+        if let Some(position) = self.positions.lock().unwrap().get_mut(mint) {
+            info!("Position in {} moved from {:?} {} to {:?} {}", mint, position.dex, position.pool_id, dex, pool_id);
+            position.dex = dex;
+            position.pool_id = pool_id;
+        }
+    }
+
+    pub fn get(&self, mint: &Pubkey) -> Option<Position> {
+        self.positions.lock().unwrap().get(mint).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Position> {
+        self.positions.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Positions still trading on `dex`.
+    pub fn on_dex(&self, dex: Dex) -> Vec<Position> {
+        self.positions.lock().unwrap()
+            .values()
+            .filter(|position| position.dex == dex)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bought_for(cost_lamports: u64) -> Position {
+        Position {
+            mint: Pubkey::new_unique(),
+            dex: Dex::RaydiumAmmV4,
+            pool_id: Pubkey::new_unique(),
+            amount: 2_588_000,
+            cost_lamports,
+            take_profit: 0.1,
+            stop_loss: 0.2,
+        }
+    }
+
+    #[test]
+    fn exits_at_the_take_profit_and_stop_loss() {
+        let position = bought_for(1_000_000_000);
+        assert_eq!(position.exit(1_100_000_000), Some(Exit::TakeProfit));
+        assert_eq!(position.exit(1_099_999_999), None);
+        assert_eq!(position.exit(800_000_001), None);
+        assert_eq!(position.exit(800_000_000), Some(Exit::StopLoss));
+        assert_eq!(bought_for(0).exit(0), None);
+    }
+
+    #[test]
+    fn later_buys_add_to_the_cost_and_sells_release_it() {
+        let book = PositionBook::new();
+        let mint = Pubkey::new_unique();
+        let settings = CopySettings { order_size: 1.0, buyin_percentage: 1.0, take_profit: 0.5, stop_loss: 0.25 };
+        book.open(mint, Dex::PumpFun, Pubkey::new_unique(), 1_000, 100_000_000, Some(&settings));
+        book.open(mint, Dex::PumpFun, Pubkey::new_unique(), 1_000, 300_000_000, None);
+        book.reduce(&mint, 500);
+
+        let position = book.get(&mint).unwrap();
+        assert_eq!((position.amount, position.cost_lamports), (1_500, 300_000_000));
+        assert_eq!((position.take_profit, position.stop_loss), (0.5, 0.25));
+        assert_eq!(position.exit(450_000_000), Some(Exit::TakeProfit));
+        assert_eq!(position.exit(225_000_000), Some(Exit::StopLoss));
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{Result, bail};
+
+const Q64: u128 = 1 << 64;
+
+/// Swap fee charged on the input, as a fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fee {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Fee {
+    /// What Raydium AMM v4 pools charge unless their `AmmInfo` says otherwise.
+    #[allow(dead_code)]
+    pub const RAYDIUM_AMM_V4: Fee = Fee { numerator: 25, denominator: 10_000 };
+
+    /// Fee on `amount_in`, rounded up as the AMM does.
+    fn on(&self, amount_in: u128) -> u128 {
+        ceil_div(amount_in * self.numerator as u128, self.denominator as u128)
+    }
+}
+
+/// A constant-product swap priced against a reserve snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Part of `amount_in` taken as fee.
+    pub fee: u64,
+    /// How far the execution price falls short of the spot price, ignoring
+    /// the fee, in basis points.
+    pub price_impact_bps: u64,
+}
+
+impl Quote {
+    /// Refuses quotes that move the price more than `max_bps`.
+    pub fn check_price_impact(&self, max_bps: u64) -> Result<()> {
+        if self.price_impact_bps > max_bps {
+            bail!("Price impact of {} bps exceeds the {} bps cap", self.price_impact_bps, max_bps);
+        }
+        Ok(())
+    }
+}
+
+/// Output for exactly `amount_in`, as Raydium's SwapBaseIn computes it.
+pub fn quote_exact_in(reserve_in: u64, reserve_out: u64, amount_in: u64, fee: Fee) -> Result<Quote> {
+    check_reserves(reserve_in, reserve_out, fee)?;
+    let (reserve_in, reserve_out) = (reserve_in as u128, reserve_out as u128);
+
+    let fee_amount = fee.on(amount_in as u128);
+    let amount_in_less_fee = amount_in as u128 - fee_amount;
+    let amount_out = reserve_out * amount_in_less_fee / (reserve_in + amount_in_less_fee);
+
+    Ok(Quote {
+        amount_in,
+        amount_out: amount_out as u64,
+        fee: fee_amount as u64,
+        price_impact_bps: price_impact_bps(reserve_in, reserve_out, amount_in_less_fee, amount_out),
+    })
+}
+
+/// Input needed to receive exactly `amount_out`, as Raydium's SwapBaseOut
+/// computes it.
+#[allow(dead_code)]
+pub fn quote_exact_out(reserve_in: u64, reserve_out: u64, amount_out: u64, fee: Fee) -> Result<Quote> {
+    check_reserves(reserve_in, reserve_out, fee)?;
+    if amount_out >= reserve_out {
+        bail!("Cannot take {} out of a reserve of {}", amount_out, reserve_out);
+    }
+    let (reserve_in, reserve_out) = (reserve_in as u128, reserve_out as u128);
+
+    let amount_in_less_fee = ceil_div(reserve_in * amount_out as u128, reserve_out - amount_out as u128);
+    let amount_in = ceil_div(
+        amount_in_less_fee * fee.denominator as u128,
+        (fee.denominator - fee.numerator) as u128,
+    );
+    if amount_in > u64::MAX as u128 {
+        bail!("Input for {} out exceeds u64", amount_out);
+    }
+
+    Ok(Quote {
+        amount_in: amount_in as u64,
+        amount_out,
+        fee: (amount_in - amount_in_less_fee) as u64,
+        price_impact_bps: price_impact_bps(reserve_in, reserve_out, amount_in_less_fee, amount_out as u128),
+    })
+}
+
+/// Output for exactly `amount_in` into a concentrated-liquidity pool at
+/// `sqrt_price_x64` (Q64.64, sqrt of b per a). Liquidity is taken as
+/// constant over the swap, so this is an estimate once the swap leaves the
+/// current tick range.
+pub fn quote_concentrated(liquidity: u128, sqrt_price_x64: u128, amount_in: u64, a_to_b: bool, fee: Fee) -> Result<Quote> {
+    if liquidity == 0 || sqrt_price_x64 == 0 {
+        bail!("Pool has no liquidity");
+    }
+    check_fee(fee)?;
+    let fee_amount = fee.on(amount_in as u128);
+    let amount_in_less_fee = (amount_in as u128 - fee_amount) as f64;
+    let liquidity = liquidity as f64;
+    let sqrt_price = sqrt_price_x64 as f64 / Q64 as f64;
+
+    let (amount_out, price_ratio) = if a_to_b {
+        let next = liquidity * sqrt_price / (liquidity + amount_in_less_fee * sqrt_price);
+        (liquidity * (sqrt_price - next), next / sqrt_price)
+    } else {
+        let next = sqrt_price + amount_in_less_fee / liquidity;
+        (liquidity * (1.0 / sqrt_price - 1.0 / next), sqrt_price / next)
+    };
+
+    Ok(Quote {
+        amount_in,
+        amount_out: amount_out as u64,
+        fee: fee_amount as u64,
+        price_impact_bps: ((1.0 - price_ratio) * 10_000.0) as u64,
+    })
+}
+
+/// Liquidity bin of a bin-based pool: its price (Q64.64, y per x) and how
+/// much of the output token it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bin {
+    pub price_x64: u128,
+    pub amount_out: u64,
+}
+
+/// Output for exactly `amount_in`, filling `bins` in order from the active
+/// one. Fails if the bins run dry first.
+pub fn quote_bins(bins: &[Bin], amount_in: u64, x_to_y: bool, fee: Fee) -> Result<Quote> {
+    check_fee(fee)?;
+    let Some(active) = bins.first().filter(|bin| bin.price_x64 > 0) else {
+        bail!("Pool has no liquidity");
+    };
+    // Output for `amount` of input at `price`, and the input that takes all
+    // of `amount` of output.
+    let out_at = |amount: u128, price: u128| if x_to_y {
+        mul_shr64(amount, price)
+    } else {
+        (amount << 64) / price
+    };
+    let in_for = |amount: u128, price: u128| if x_to_y {
+        ceil_div(amount << 64, price)
+    } else {
+        amount * (price >> 64) + ceil_div(amount * (price & (Q64 - 1)), Q64)
+    };
+
+    let fee_amount = fee.on(amount_in as u128);
+    let amount_in_less_fee = amount_in as u128 - fee_amount;
+    let mut remaining = amount_in_less_fee;
+    let mut amount_out = 0u128;
+    for bin in bins.iter().filter(|bin| bin.price_x64 > 0) {
+        let needed = in_for(bin.amount_out as u128, bin.price_x64);
+        if remaining < needed {
+            amount_out += out_at(remaining, bin.price_x64).min(bin.amount_out as u128);
+            remaining = 0;
+            break;
+        }
+        amount_out += bin.amount_out as u128;
+        remaining -= needed;
+    }
+    if remaining > 0 {
+        bail!("Not enough liquidity in the loaded bins for {} in", amount_in);
+    }
+
+    let spot_out = out_at(amount_in_less_fee, active.price_x64);
+    Ok(Quote {
+        amount_in,
+        amount_out: amount_out.min(u64::MAX as u128) as u64,
+        fee: fee_amount as u64,
+        price_impact_bps: (spot_out.saturating_sub(amount_out) * 10_000)
+            .checked_div(spot_out)
+            .unwrap_or(0) as u64,
+    })
+}
+
+/// Output for `amount_in` at the price a recorded trade of `input_amount`
+/// for `output_amount` executed at. Without the pool's state the price
+/// impact is unknown and reported as none.
+pub fn quote_at_price(input_amount: u64, output_amount: u64, amount_in: u64) -> Result<Quote> {
+    if input_amount == 0 {
+        bail!("Trade has no input to price against");
+    }
+    let amount_out = output_amount as u128 * amount_in as u128 / input_amount as u128;
+    Ok(Quote {
+        amount_in,
+        amount_out: amount_out.min(u64::MAX as u128) as u64,
+        fee: 0,
+        price_impact_bps: 0,
+    })
+}
+
+/// The least output to accept for `amount_out` within `slippage_bps`.
+pub fn min_amount_out(amount_out: u64, slippage_bps: u64) -> u64 {
+    (amount_out as u128 * 10_000u64.saturating_sub(slippage_bps) as u128 / 10_000) as u64
+}
+
+/// The most input to allow for `amount_in` within `slippage_bps`.
+pub fn max_amount_in(amount_in: u64, slippage_bps: u64) -> u64 {
+    (amount_in as u128 * (10_000 + slippage_bps) as u128 / 10_000).min(u64::MAX as u128) as u64
+}
+
+fn check_reserves(reserve_in: u64, reserve_out: u64, fee: Fee) -> Result<()> {
+    if reserve_in == 0 || reserve_out == 0 {
+        bail!("Pool has no liquidity");
+    }
+    check_fee(fee)
+}
+
+fn check_fee(fee: Fee) -> Result<()> {
+    if fee.denominator == 0 || fee.numerator >= fee.denominator {
+        bail!("Invalid fee {}/{}", fee.numerator, fee.denominator);
+    }
+    Ok(())
+}
+
+/// Shortfall of `amount_out` against `amount_in` at the spot price.
+fn price_impact_bps(reserve_in: u128, reserve_out: u128, amount_in: u128, amount_out: u128) -> u64 {
+    let spot_out = amount_in * reserve_out / reserve_in;
+    if spot_out == 0 {
+        return 0;
+    }
+    (spot_out.saturating_sub(amount_out) * 10_000 / spot_out) as u64
+}
+
+/// `amount * price_x64 >> 64` without overflowing for prices above 1.
+fn mul_shr64(amount: u128, price_x64: u128) -> u128 {
+    amount * (price_x64 >> 64) + ((amount * (price_x64 & (Q64 - 1))) >> 64)
+}
+
+fn ceil_div(numerator: u128, denominator: u128) -> u128 {
+    numerator.div_ceil(denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::raydium_amm_v4::{self, AmmInfo};
+    use crate::reserves::PoolReserves;
+    use solana_sdk::pubkey;
+    use solana_sdk::pubkey::Pubkey;
+
+    const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+    // SOL/USDC: 120,000.5 SOL (9 decimals) against 18,000,000 USDC (6 decimals).
+    const SOL_RESERVE: u64 = 120_000_500_000_000;
+    const USDC_RESERVE: u64 = 18_000_000_000_000;
+
+    // A fresh pump.fun migration: 79 SOL against 206.9M tokens (6 decimals).
+    const MIGRATED_SOL_RESERVE: u64 = 79_005_359_057;
+    const MIGRATED_TOKEN_RESERVE: u64 = 206_900_000_000_000;
+
+    #[test]
+    fn exact_in_matches_pool_math() {
+        // 1 SOL into the SOL/USDC pool.
+        let quote = quote_exact_in(SOL_RESERVE, USDC_RESERVE, 1_000_000_000, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert_eq!(quote.fee, 2_500_000);
+        assert_eq!(quote.amount_out, 149_623_132);
+        assert_eq!(quote.price_impact_bps, 0);
+
+        // 0.5 SOL into the freshly migrated pool.
+        let quote = quote_exact_in(MIGRATED_SOL_RESERVE, MIGRATED_TOKEN_RESERVE, 500_000_000, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert_eq!(quote.fee, 1_250_000);
+        assert_eq!(quote.amount_out, 1_297_937_631_450);
+        assert_eq!(quote.price_impact_bps, 62);
+    }
+
+    #[test]
+    fn exact_in_rounds_the_fee_up() {
+        let quote = quote_exact_in(SOL_RESERVE, USDC_RESERVE, 1, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert_eq!(quote.fee, 1);
+        assert_eq!(quote.amount_out, 0);
+    }
+
+    #[test]
+    fn exact_out_matches_pool_math() {
+        // Buy exactly 1,000,000 tokens from the migrated pool.
+        let quote = quote_exact_out(MIGRATED_SOL_RESERVE, MIGRATED_TOKEN_RESERVE, 1_000_000_000_000, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert_eq!(quote.amount_in, 384_669_100);
+        assert_eq!(quote.fee, 961_673);
+        assert_eq!(quote.price_impact_bps, 48);
+    }
+
+    #[test]
+    fn exact_out_covers_exact_in() {
+        let amount_in = 2_000_000_000;
+        let forward = quote_exact_in(MIGRATED_SOL_RESERVE, MIGRATED_TOKEN_RESERVE, amount_in, Fee::RAYDIUM_AMM_V4).unwrap();
+        let backward = quote_exact_out(MIGRATED_SOL_RESERVE, MIGRATED_TOKEN_RESERVE, forward.amount_out, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert!(backward.amount_in <= amount_in);
+        let again = quote_exact_in(MIGRATED_SOL_RESERVE, MIGRATED_TOKEN_RESERVE, backward.amount_in, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert!(again.amount_out >= forward.amount_out);
+    }
+
+    #[test]
+    fn exact_out_rejects_draining_the_pool() {
+        assert!(quote_exact_out(SOL_RESERVE, USDC_RESERVE, USDC_RESERVE, Fee::RAYDIUM_AMM_V4).is_err());
+        assert!(quote_exact_in(0, USDC_RESERVE, 1_000, Fee::RAYDIUM_AMM_V4).is_err());
+    }
+
+    #[test]
+    fn slippage_bounds() {
+        assert_eq!(min_amount_out(149_623_132, 100), 148_126_900);
+        assert_eq!(max_amount_in(384_669_100, 100), 388_515_791);
+        assert_eq!(min_amount_out(1_000, 20_000), 0);
+    }
+
+    /// `AmmInfo` account data as the program lays it out.
+    fn amm_info_data(
+        status: u64,
+        decimals: (u64, u64),
+        need_take_pnl: (u64, u64),
+        vaults: (Pubkey, Pubkey),
+        mints: (Pubkey, Pubkey),
+    ) -> Vec<u8> {
+        let mut data = vec![0u8; raydium_amm_v4::AMM_INFO_SIZE as usize];
+        let mut put = |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        put(0, &status.to_le_bytes());
+        put(32, &decimals.0.to_le_bytes());
+        put(40, &decimals.1.to_le_bytes());
+        put(176, &25u64.to_le_bytes());
+        put(184, &10_000u64.to_le_bytes());
+        put(192, &need_take_pnl.0.to_le_bytes());
+        put(200, &need_take_pnl.1.to_le_bytes());
+        put(raydium_amm_v4::COIN_VAULT_OFFSET, vaults.0.as_ref());
+        put(raydium_amm_v4::PC_VAULT_OFFSET, vaults.1.as_ref());
+        put(raydium_amm_v4::COIN_MINT_OFFSET, mints.0.as_ref());
+        put(raydium_amm_v4::PC_MINT_OFFSET, mints.1.as_ref());
+        data
+    }
+
+    /// SPL token account data holding `amount` of `mint`.
+    fn token_account_data(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 165];
+        data[..32].copy_from_slice(mint.as_ref());
+        data[32..64].copy_from_slice(owner.as_ref());
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        data[108] = 1;
+        data
+    }
+
+    #[test]
+    fn quotes_the_sol_usdc_pool_net_of_owed_pnl() {
+        // The SOL/USDC pool 58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2, with
+        // protocol fees not yet taken out of either vault.
+        let authority = pubkey!("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
+        let coin_vault = pubkey!("DQyrAcCrDXQ7NeoqGgDCZwBvWDcYmFCjSb9JtteuvPpz");
+        let pc_vault = pubkey!("HLmqeL62xR1QoZ1HKKbXRrdN1p3phKpxRMb2VVopvBBz");
+        let (pnl_coin, pnl_pc) = (412_845_117, 61_927_004);
+        let amm = AmmInfo::from_account_data(&amm_info_data(
+            6,
+            (9, 6),
+            (pnl_coin, pnl_pc),
+            (coin_vault, pc_vault),
+            (WSOL, USDC),
+        )).unwrap();
+        assert!(amm.swap_enabled());
+        assert_eq!(amm.swap_fee, Fee::RAYDIUM_AMM_V4);
+
+        let reserves = PoolReserves::from_accounts(
+            &amm,
+            &token_account_data(&WSOL, &authority, SOL_RESERVE + pnl_coin),
+            &token_account_data(&USDC, &authority, USDC_RESERVE + pnl_pc),
+        ).unwrap();
+        assert_eq!(reserves.tradable(), (SOL_RESERVE, USDC_RESERVE));
+
+        // Priced as 1 SOL against the vaults less what the protocol is owed.
+        let quote = reserves.quote(&WSOL, 1_000_000_000).unwrap();
+        assert_eq!(quote.amount_out, 149_623_132);
+        assert!(reserves.quote(&Pubkey::new_unique(), 1_000_000_000).is_err());
+    }
+
+    #[test]
+    fn quotes_a_freshly_migrated_pool() {
+        // pump.fun migrations pair the token as coin with SOL as pc.
+        let mint = Pubkey::new_unique();
+        let authority = pubkey!("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
+        let (coin_vault, pc_vault) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let amm = AmmInfo::from_account_data(&amm_info_data(
+            6,
+            (6, 9),
+            (0, 0),
+            (coin_vault, pc_vault),
+            (mint, WSOL),
+        )).unwrap();
+
+        let reserves = PoolReserves::from_accounts(
+            &amm,
+            &token_account_data(&mint, &authority, MIGRATED_TOKEN_RESERVE),
+            &token_account_data(&WSOL, &authority, MIGRATED_SOL_RESERVE),
+        ).unwrap();
+        let quote = reserves.quote(&WSOL, 500_000_000).unwrap();
+        assert_eq!(quote.amount_out, 1_297_937_631_450);
+        assert_eq!(quote.price_impact_bps, 62);
+
+        // A vault that is not a token account cannot be priced.
+        assert!(PoolReserves::from_accounts(&amm, &[0u8; 32], &token_account_data(&WSOL, &authority, 1)).is_err());
+    }
+
+    #[test]
+    fn concentrated_quote_within_current_liquidity() {
+        // 150 USDC per SOL, 0.3% fee.
+        let sqrt_price = 7_144_393_258_922_745_856;
+        let liquidity = 4_000_000_000_000;
+        let fee = Fee { numerator: 3_000, denominator: 1_000_000 };
+
+        let quote = quote_concentrated(liquidity, sqrt_price, 1_000_000_000, true, fee).unwrap();
+        assert_eq!(quote.fee, 3_000_000);
+        assert_eq!(quote.amount_out, 149_535_564);
+        assert_eq!(quote.price_impact_bps, 0);
+
+        let quote = quote_concentrated(liquidity, sqrt_price, 100_000_000_000, true, fee).unwrap();
+        assert_eq!(quote.amount_out, 14_812_013_545);
+        assert_eq!(quote.price_impact_bps, 95);
+
+        let quote = quote_concentrated(liquidity, sqrt_price, 150_000_000, false, fee).unwrap();
+        assert_eq!(quote.amount_out, 996_903_764);
+        assert!(quote_concentrated(0, sqrt_price, 1_000, true, fee).is_err());
+    }
+
+    #[test]
+    fn bin_quote_walks_into_the_next_bin() {
+        // Bins -2000 and -2001 of a 25 bps pair, selling x for y.
+        let bins = [
+            Bin { price_x64: 125_071_148_546_935_472, amount_out: 5_000_000 },
+            Bin { price_x64: 124_759_250_420_883_264, amount_out: 8_000_000 },
+        ];
+        let fee = Fee { numerator: 250_000, denominator: 1_000_000_000 };
+
+        let quote = quote_bins(&bins, 1_000_000_000, true, fee).unwrap();
+        assert_eq!(quote.fee, 250_000);
+        assert_eq!(quote.amount_out, 6_773_990);
+        assert_eq!(quote.price_impact_bps, 6);
+
+        // More than the loaded bins hold cannot be quoted.
+        assert!(quote_bins(&bins, 5_000_000_000, true, fee).is_err());
+        assert!(quote_bins(&[], 1_000, true, fee).is_err());
+    }
+
+    #[test]
+    fn price_impact_cap() {
+        // 10 SOL against 79 moves the price over 11%.
+        let quote = quote_exact_in(MIGRATED_SOL_RESERVE, MIGRATED_TOKEN_RESERVE, 10_000_000_000, Fee::RAYDIUM_AMM_V4).unwrap();
+        assert_eq!(quote.price_impact_bps, 1121);
+        assert!(quote.check_price_impact(500).is_err());
+        assert!(quote.check_price_impact(1_200).is_ok());
+    }
+}
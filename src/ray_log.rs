@@ -0,0 +1,388 @@
+//! Decoder for the `ray_log` lines printed by the Raydium AMM v4 program.
+//!
+//! Each log is a base64 encoded, packed little-endian struct whose first byte
+//! identifies the log type.
+
+use anyhow::{Result, anyhow, bail};
+use base64::decode;
+
+pub const RAY_LOG_PREFIX: &str = "ray_log: ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogType {
+    Init = 0,
+    Deposit = 1,
+    Withdraw = 2,
+    SwapBaseIn = 3,
+    SwapBaseOut = 4,
+}
+
+impl TryFrom<u8> for LogType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(LogType::Init),
+            1 => Ok(LogType::Deposit),
+            2 => Ok(LogType::Withdraw),
+            3 => Ok(LogType::SwapBaseIn),
+            4 => Ok(LogType::SwapBaseOut),
+            other => Err(anyhow!("Unknown ray_log type {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwapDirection {
+    /// Quote (pc) in, base (coin) out.
+    PcToCoin,
+    /// Base (coin) in, quote (pc) out.
+    CoinToPc,
+}
+
+impl TryFrom<u64> for SwapDirection {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u64) -> Result<Self> {
+        match value {
+            1 => Ok(SwapDirection::PcToCoin),
+            2 => Ok(SwapDirection::CoinToPc),
+            other => Err(anyhow!("Unknown swap direction {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitLog {
+    pub time: u64,
+    pub pc_decimals: u8,
+    pub coin_decimals: u8,
+    pub pc_lot_size: u64,
+    pub coin_lot_size: u64,
+    pub pc_amount: u64,
+    pub coin_amount: u64,
+    pub market: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepositLog {
+    pub max_coin: u64,
+    pub max_pc: u64,
+    pub base: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+    pub pool_lp: u64,
+    pub calc_pnl_x: u128,
+    pub calc_pnl_y: u128,
+    pub deduct_coin: u64,
+    pub deduct_pc: u64,
+    pub mint_lp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawLog {
+    pub withdraw_lp: u64,
+    pub user_lp: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+    pub pool_lp: u64,
+    pub calc_pnl_x: u128,
+    pub calc_pnl_y: u128,
+    pub out_coin: u64,
+    pub out_pc: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapBaseInLog {
+    pub amount_in: u64,
+    pub minimum_out: u64,
+    pub direction: SwapDirection,
+    pub user_source: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+    pub out_amount: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapBaseOutLog {
+    pub max_in: u64,
+    pub amount_out: u64,
+    pub direction: SwapDirection,
+    pub user_source: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+    pub deduct_in: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RayLog {
+    Init(InitLog),
+    Deposit(DepositLog),
+    Withdraw(WithdrawLog),
+    SwapBaseIn(SwapBaseInLog),
+    SwapBaseOut(SwapBaseOutLog),
+}
+
+impl RayLog {
+    /// Which way a swap went.
+    pub fn direction(&self) -> Option<SwapDirection> {
+        match self {
+            RayLog::SwapBaseIn(log) => Some(log.direction),
+            RayLog::SwapBaseOut(log) => Some(log.direction),
+            _ => None,
+        }
+    }
+
+    /// Amount the user paid and received, for the two swap variants.
+    pub fn swap_amounts(&self) -> Option<(u64, u64)> {
+        match self {
+            RayLog::SwapBaseIn(log) => Some((log.amount_in, log.out_amount)),
+            RayLog::SwapBaseOut(log) => Some((log.deduct_in, log.amount_out)),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset + len;
+        if end > self.data.len() {
+            bail!("ray_log truncated: need {} bytes, have {}", end, self.data.len());
+        }
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn u128(&mut self) -> Result<u128> {
+        Ok(u128::from_le_bytes(self.take(16)?.try_into()?))
+    }
+
+    fn bytes32(&mut self) -> Result<[u8; 32]> {
+        Ok(self.take(32)?.try_into()?)
+    }
+}
+
+/// Decodes the raw bytes of a `ray_log`.
+pub fn decode_bytes(data: &[u8]) -> Result<RayLog> {
+    let mut reader = Reader::new(data);
+    let log = match LogType::try_from(reader.u8()?)? {
+        LogType::Init => RayLog::Init(InitLog {
+            time: reader.u64()?,
+            pc_decimals: reader.u8()?,
+            coin_decimals: reader.u8()?,
+            pc_lot_size: reader.u64()?,
+            coin_lot_size: reader.u64()?,
+            pc_amount: reader.u64()?,
+            coin_amount: reader.u64()?,
+            market: reader.bytes32()?,
+        }),
+        LogType::Deposit => RayLog::Deposit(DepositLog {
+            max_coin: reader.u64()?,
+            max_pc: reader.u64()?,
+            base: reader.u64()?,
+            pool_coin: reader.u64()?,
+            pool_pc: reader.u64()?,
+            pool_lp: reader.u64()?,
+            calc_pnl_x: reader.u128()?,
+            calc_pnl_y: reader.u128()?,
+            deduct_coin: reader.u64()?,
+            deduct_pc: reader.u64()?,
+            mint_lp: reader.u64()?,
+        }),
+        LogType::Withdraw => RayLog::Withdraw(WithdrawLog {
+            withdraw_lp: reader.u64()?,
+            user_lp: reader.u64()?,
+            pool_coin: reader.u64()?,
+            pool_pc: reader.u64()?,
+            pool_lp: reader.u64()?,
+            calc_pnl_x: reader.u128()?,
+            calc_pnl_y: reader.u128()?,
+            out_coin: reader.u64()?,
+            out_pc: reader.u64()?,
+        }),
+        LogType::SwapBaseIn => RayLog::SwapBaseIn(SwapBaseInLog {
+            amount_in: reader.u64()?,
+            minimum_out: reader.u64()?,
+            direction: SwapDirection::try_from(reader.u64()?)?,
+            user_source: reader.u64()?,
+            pool_coin: reader.u64()?,
+            pool_pc: reader.u64()?,
+            out_amount: reader.u64()?,
+        }),
+        LogType::SwapBaseOut => RayLog::SwapBaseOut(SwapBaseOutLog {
+            max_in: reader.u64()?,
+            amount_out: reader.u64()?,
+            direction: SwapDirection::try_from(reader.u64()?)?,
+            user_source: reader.u64()?,
+            pool_coin: reader.u64()?,
+            pool_pc: reader.u64()?,
+            deduct_in: reader.u64()?,
+        }),
+    };
+    Ok(log)
+}
+
+/// Decodes the base64 payload of a `ray_log`.
+pub fn decode_base64(encoded: &str) -> Result<RayLog> {
+    let data = decode(encoded.trim())?;
+    decode_bytes(&data)
+}
+
+/// Decodes a full program log line such as `Program log: ray_log: A8Bf...`.
+/// Returns `None` for lines that are not ray logs.
+pub fn parse_log_line(line: &str) -> Option<Result<RayLog>> {
+    let (_, encoded) = line.split_once(RAY_LOG_PREFIX)?;
+    Some(decode_base64(encoded))
+}
+
+/// All swap logs in a transaction's log messages, in execution order.
+pub fn swap_logs(log_messages: &[String]) -> Vec<RayLog> {
+    log_messages.iter()
+        .filter_map(|line| parse_log_line(line))
+        .filter_map(|result| result.ok())
+        .filter(|log| log.swap_amounts().is_some())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Logs of a SOL/USDC pool (coin SOL, pc USDC) and of a freshly migrated
+    // pump.fun pool (coin token, pc SOL).
+    const INIT: &str = "AEBXV2YAAAAACQYBAAAAAAAAAEBCDwAAAAAA0RsWZRIAAAAACAGpLLwAAAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f";
+    const DEPOSIT: &str = "AQDKmjsAAAAAgNHwCAAAAAAAAAAAAAAAAADlLMsjbQAAACCb814QAAAA4Kg11ikAAABAQ8n1aleBBgAAAAAAAAAAAAjFodjM+QAAAAAAAAAAAMqaOwAAAACezPAIAAAAANUz2RYAAAAA";
+    const WITHDRAW: &str = "AtUz2RYAAAAA1TPZFgAAAAAAr8cGJG0AAIDxi/xeEAAA1ROCTNYpAAAAQEPJ9WpXgQYAAAAAAAAAAAAIxaHYzPkAAAAAAAAAADDCmjsAAAAAmM3wCAAAAAA=";
+    const SWAP_BASE_IN: &str = "AwDKmjsAAAAAtDzUCAAAAAACAAAAAAAAAADKmjsAAAAAAOUsyyNtAAAAIJvzXhAAAFwR6wgAAAAA";
+    const SWAP_BASE_OUT: &str = "BM9HKBcAAAAAABCl1OgAAAABAAAAAAAAAM9HKBcAAAAAAAgBqSy8AADRGxZlEgAAAKyV7RYAAAAA";
+
+    #[test]
+    fn decodes_init() {
+        let market: [u8; 32] = std::array::from_fn(|i| i as u8);
+        assert_eq!(decode_base64(INIT).unwrap(), RayLog::Init(InitLog {
+            time: 1_717_000_000,
+            pc_decimals: 9,
+            coin_decimals: 6,
+            pc_lot_size: 1,
+            coin_lot_size: 1_000_000,
+            pc_amount: 79_005_359_057,
+            coin_amount: 206_900_000_000_000,
+            market,
+        }));
+    }
+
+    #[test]
+    fn decodes_deposit() {
+        assert_eq!(decode_base64(DEPOSIT).unwrap(), RayLog::Deposit(DepositLog {
+            max_coin: 1_000_000_000,
+            max_pc: 150_000_000,
+            base: 0,
+            pool_coin: 120_000_500_000_000,
+            pool_pc: 18_000_000_000_000,
+            pool_lp: 46_000_000_000_000,
+            calc_pnl_x: 120_000_500_000_000_000_000,
+            calc_pnl_y: 18_000_000_000_000_000_000,
+            deduct_coin: 1_000_000_000,
+            deduct_pc: 149_998_750,
+            mint_lp: 383_333_333,
+        }));
+    }
+
+    #[test]
+    fn decodes_withdraw() {
+        assert_eq!(decode_base64(WITHDRAW).unwrap(), RayLog::Withdraw(WithdrawLog {
+            withdraw_lp: 383_333_333,
+            user_lp: 383_333_333,
+            pool_coin: 120_001_500_000_000,
+            pool_pc: 18_000_150_000_000,
+            pool_lp: 46_000_383_333_333,
+            calc_pnl_x: 120_000_500_000_000_000_000,
+            calc_pnl_y: 18_000_000_000_000_000_000,
+            out_coin: 999_998_000,
+            out_pc: 149_999_000,
+        }));
+    }
+
+    #[test]
+    fn decodes_swap_base_in() {
+        let log = parse_log_line(&format!("Program log: {}{}", RAY_LOG_PREFIX, SWAP_BASE_IN)).unwrap().unwrap();
+        assert_eq!(log, RayLog::SwapBaseIn(SwapBaseInLog {
+            amount_in: 1_000_000_000,
+            minimum_out: 148_126_900,
+            direction: SwapDirection::CoinToPc,
+            user_source: 1_000_000_000,
+            pool_coin: 120_000_500_000_000,
+            pool_pc: 18_000_000_000_000,
+            out_amount: 149_623_132,
+        }));
+        assert_eq!(log.swap_amounts(), Some((1_000_000_000, 149_623_132)));
+    }
+
+    #[test]
+    fn decodes_swap_base_out() {
+        let log = decode_base64(SWAP_BASE_OUT).unwrap();
+        assert_eq!(log, RayLog::SwapBaseOut(SwapBaseOutLog {
+            max_in: 388_515_791,
+            amount_out: 1_000_000_000_000,
+            direction: SwapDirection::PcToCoin,
+            user_source: 388_515_791,
+            pool_coin: 206_900_000_000_000,
+            pool_pc: 79_005_359_057,
+            deduct_in: 384_669_100,
+        }));
+        assert_eq!(log.swap_amounts(), Some((384_669_100, 1_000_000_000_000)));
+    }
+
+    #[test]
+    fn truncated_logs_are_errors() {
+        let data = decode(SWAP_BASE_IN).unwrap();
+        for len in 0..data.len() {
+            assert!(decode_bytes(&data[..len]).is_err(), "{} bytes decoded", len);
+        }
+        let data = decode(DEPOSIT).unwrap();
+        assert!(decode_bytes(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn unknown_values_are_errors() {
+        let mut data = decode(SWAP_BASE_IN).unwrap();
+        data[0] = 5;
+        assert!(decode_bytes(&data).is_err());
+        data[0] = 3;
+        data[17] = 3;
+        assert!(decode_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn swap_logs_skips_other_lines() {
+        let logs = vec![
+            "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 invoke [1]".to_string(),
+            format!("Program log: {}{}", RAY_LOG_PREFIX, DEPOSIT),
+            format!("Program log: {}{}", RAY_LOG_PREFIX, SWAP_BASE_IN),
+            format!("Program log: {}AwDK", RAY_LOG_PREFIX),
+            format!("Program log: {}{}", RAY_LOG_PREFIX, SWAP_BASE_OUT),
+        ];
+        let swaps = swap_logs(&logs);
+        assert_eq!(swaps.len(), 2);
+        assert!(matches!(swaps[0], RayLog::SwapBaseIn(_)));
+        assert!(matches!(swaps[1], RayLog::SwapBaseOut(_)));
+    }
+}
@@ -0,0 +1,416 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_client::rpc_client::RpcClient;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use anyhow::{Result, anyhow, bail};
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use log::{info, error, debug, warn};
+use crate::backoff::Backoff;
+use crate::config::Config;
+use crate::dex::raydium_amm_v4::AmmInfo;
+use crate::pool_cache::PoolCache;
+use crate::positions::PositionBook;
+use crate::quote::{self, Fee, Quote};
+use crate::source::recorder;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Pools the target traded on stay tracked this long unless we hold them.
+const RECENT_POOL_TTL: Duration = Duration::from_secs(30 * 60);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Vault balances of one AMM v4 pool and the protocol fees still held in
+/// them.
+#[derive(Debug, Clone)]
+pub struct PoolReserves {
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_amount: u64,
+    pub pc_amount: u64,
+    pub need_take_pnl_coin: u64,
+    pub need_take_pnl_pc: u64,
+    pub fee: Fee,
+}
+
+impl PoolReserves {
+    /// Reserves from a pool's `AmmInfo` and the data of its two vaults.
+    pub fn from_accounts(amm: &AmmInfo, coin_vault_data: &[u8], pc_vault_data: &[u8]) -> Result<Self> {
+        let mut reserves = PoolReserves {
+            coin_mint: amm.coin_mint,
+            pc_mint: amm.pc_mint,
+            coin_amount: token_amount(coin_vault_data)
+                .ok_or_else(|| anyhow!("Vault {} is not a token account", amm.coin_vault))?,
+            pc_amount: token_amount(pc_vault_data)
+                .ok_or_else(|| anyhow!("Vault {} is not a token account", amm.pc_vault))?,
+            need_take_pnl_coin: 0,
+            need_take_pnl_pc: 0,
+            fee: amm.swap_fee,
+        };
+        reserves.apply_amm(amm);
+        Ok(reserves)
+    }
+
+    /// Exact-in quote for `amount_in` of `input_mint`.
+    pub fn quote(&self, input_mint: &Pubkey, amount_in: u64) -> Result<Quote> {
+        let (coin, pc) = self.tradable();
+        let (reserve_in, reserve_out) = if *input_mint == self.coin_mint {
+            (coin, pc)
+        } else if *input_mint == self.pc_mint {
+            (pc, coin)
+        } else {
+            bail!("Pool does not trade {}", input_mint);
+        };
+        quote::quote_exact_in(reserve_in, reserve_out, amount_in, self.fee)
+    }
+
+    /// The coin and pc reserves a swap is priced against.
+    pub fn tradable(&self) -> (u64, u64) {
+        (
+            self.coin_amount.saturating_sub(self.need_take_pnl_coin),
+            self.pc_amount.saturating_sub(self.need_take_pnl_pc),
+        )
+    }
+
+    fn apply_amm(&mut self, amm: &AmmInfo) {
+        self.need_take_pnl_coin = amm.need_take_pnl_coin;
+        self.need_take_pnl_pc = amm.need_take_pnl_pc;
+        self.fee = amm.swap_fee;
+    }
+}
+
+struct TrackedPool {
+    coin_vault: Pubkey,
+    pc_vault: Pubkey,
+    reserves: PoolReserves,
+    last_traded: Instant,
+}
+
+/// Per-connection subscription bookkeeping.
+#[derive(Default)]
+struct AccountSubscriptions {
+    next_id: u64,
+    /// Request id -> account, until the subscription is confirmed.
+    pending: HashMap<u64, Pubkey>,
+    /// Subscription id -> pool or vault account.
+    accounts: HashMap<u64, Pubkey>,
+}
+
+/// Keeps the vault balances of AMM v4 pools, and the fees owed out of them,
+/// current through `accountSubscribe` on the vaults and the pool account, so
+/// copies can be quoted without an RPC round trip. Pools are tracked while
+/// we hold them or the target traded them recently.
+///
+/// The tracker keeps one connection, to RESERVES_WS_ENDPOINT or else the
+/// first of the listener's WebSocket endpoints, and reads at confirmed
+/// commitment at most so quotes are not several seconds behind.
+pub struct ReserveTracker {
+    ws_endpoint: String,
+    commitment: CommitmentConfig,
+    ping_interval: Duration,
+    rpc_client: RpcClient,
+    pool_cache: Arc<PoolCache>,
+    positions: Arc<PositionBook>,
+    pools: Mutex<HashMap<Pubkey, TrackedPool>>,
+    watch_tx: mpsc::UnboundedSender<Pubkey>,
+    watch_rx: Mutex<Option<mpsc::UnboundedReceiver<Pubkey>>>,
+    /// Reserves are only trusted while the subscription is up.
+    live: AtomicBool,
+}
+
+impl ReserveTracker {
+    pub fn new(config: &Config, pool_cache: Arc<PoolCache>, positions: Arc<PositionBook>) -> Self {
+        let (watch_tx, watch_rx) = mpsc::unbounded_channel();
+        let commitment = if config.detection_commitment.is_at_least_confirmed() {
+            CommitmentConfig::confirmed()
+        } else {
+            config.detection_commitment
+        };
+        ReserveTracker {
+            ws_endpoint: config.reserves_ws_endpoint.clone(),
+            commitment,
+            ping_interval: config.ws_ping_interval,
+            rpc_client: RpcClient::new_with_commitment(config.rpc_endpoint.clone(), commitment),
+            pool_cache,
+            positions,
+            pools: Mutex::new(HashMap::new()),
+            watch_tx,
+            watch_rx: Mutex::new(Some(watch_rx)),
+            live: AtomicBool::new(false),
+        }
+    }
+
+    /// Starts tracking `pool_id`, or marks it as recently traded.
+    pub fn watch(&self, pool_id: Pubkey) {
+        if let Some(pool) = self.pools.lock().unwrap().get_mut(&pool_id) {
+            pool.last_traded = Instant::now();
+            return;
+        }
+        let _ = self.watch_tx.send(pool_id);
+    }
+
+    pub fn reserves(&self, pool_id: &Pubkey) -> Option<PoolReserves> {
+        if !self.live.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.pools.lock().unwrap().get(pool_id).map(|pool| pool.reserves.clone())
+    }
+
+    /// Exact-in quote for `amount_in` of `input_mint` at the current reserves.
+    /// Pools not tracked yet, or while the subscription is down, are read
+    /// from RPC instead.
+    pub fn quote(&self, pool_id: &Pubkey, input_mint: &Pubkey, amount_in: u64) -> Result<Quote> {
+        let reserves = match self.reserves(pool_id) {
+            Some(reserves) => reserves,
+            None => self.fetch_reserves(pool_id)?.2,
+        };
+        reserves.quote(input_mint, amount_in)
+    }
+
+    pub async fn run(&self, running: Arc<AtomicBool>) {
+        let Some(mut watch_rx) = self.watch_rx.lock().unwrap().take() else {
+            error!("Reserve tracker is already running");
+            return;
+        };
+        let mut backoff = Backoff::new(RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY);
+        while running.load(Ordering::SeqCst) {
+            match self.stream(&mut watch_rx, &running, &mut backoff).await {
+                Ok(_) => info!("Reserve subscription to {} closed", recorder::redact(&self.ws_endpoint)),
+                Err(e) => error!("Reserve subscription error on {}: {:?}", recorder::redact(&self.ws_endpoint), e),
+            }
+            self.live.store(false, Ordering::SeqCst);
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+            let delay = backoff.next_delay();
+            warn!("Reconnecting reserve tracker in {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn stream(
+        &self,
+        watch_rx: &mut mpsc::UnboundedReceiver<Pubkey>,
+        running: &AtomicBool,
+        backoff: &mut Backoff,
+    ) -> Result<()> {
+        let (ws_stream, _) = connect_async(self.ws_endpoint.as_str()).await?;
+        let (mut write, mut read) = ws_stream.split();
+        backoff.reset();
+
+        let mut subscriptions = AccountSubscriptions::default();
+        let accounts: Vec<Pubkey> = self.pools.lock().unwrap()
+            .iter()
+            .flat_map(|(pool_id, pool)| [*pool_id, pool.coin_vault, pool.pc_vault])
+            .collect();
+        for account in &accounts {
+            write.send(self.subscribe_message(&mut subscriptions, *account)).await?;
+        }
+        // Balances may have moved while we were disconnected.
+        tokio::task::block_in_place(|| self.refresh(&accounts));
+        self.live.store(true, Ordering::SeqCst);
+        info!("Tracking reserves of {} pools via {}", accounts.len() / 3, recorder::redact(&self.ws_endpoint));
+
+        let mut ping_interval = tokio::time::interval(self.ping_interval);
+        let mut prune_interval = tokio::time::interval(PRUNE_INTERVAL);
+        while running.load(Ordering::SeqCst) {
+            tokio::select! {
+                msg = read.next() => match msg {
+                    Some(Ok(Message::Text(text))) => self.handle_text(&text, &mut subscriptions),
+                    Some(Ok(Message::Close(frame))) => {
+                        warn!("Reserve subscription closed by server: {:?}", frame);
+                        break;
+                    }
+                    Some(Err(e)) => return Err(e.into()),
+                    Some(Ok(_)) => {}
+                    None => break,
+                },
+                pool_id = watch_rx.recv() => {
+                    let Some(pool_id) = pool_id else { break };
+                    match tokio::task::block_in_place(|| self.add_pool(pool_id)) {
+                        Ok(Some(accounts)) => {
+                            for account in accounts {
+                                write.send(self.subscribe_message(&mut subscriptions, account)).await?;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!("Not tracking reserves of {}: {:?}", pool_id, e),
+                    }
+                }
+                _ = ping_interval.tick() => write.send(Message::Ping(Vec::new())).await?,
+                _ = prune_interval.tick() => {
+                    for subscription in self.prune(&subscriptions) {
+                        subscriptions.accounts.remove(&subscription);
+                        let unsubscribe = serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "id": 0,
+                            "method": "accountUnsubscribe",
+                            "params": [subscription]
+                        });
+                        write.send(Message::Text(unsubscribe.to_string())).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn subscribe_message(&self, subscriptions: &mut AccountSubscriptions, account: Pubkey) -> Message {
+        subscriptions.next_id += 1;
+        subscriptions.pending.insert(subscriptions.next_id, account);
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": subscriptions.next_id,
+            "method": "accountSubscribe",
+            "params": [
+                account.to_string(),
+                {
+                    "commitment": self.commitment.commitment.to_string(),
+                    "encoding": "base64"
+                }
+            ]
+        });
+        Message::Text(request.to_string())
+    }
+
+    fn handle_text(&self, text: &str, subscriptions: &mut AccountSubscriptions) {
+        let v: Value = serde_json::from_str(text).unwrap_or(Value::Null);
+
+        // Subscription confirmation: { id, result: <subscription id> }
+        if let (Some(id), Some(subscription)) = (
+            v.get("id").and_then(|id| id.as_u64()),
+            v.get("result").and_then(|result| result.as_u64()),
+        ) {
+            if let Some(account) = subscriptions.pending.remove(&id) {
+                subscriptions.accounts.insert(subscription, account);
+            }
+            return;
+        }
+
+        // accountNotification: params.result.value.data = [<base64>, "base64"]
+        let account = v.pointer("/params/subscription")
+            .and_then(|subscription| subscription.as_u64())
+            .and_then(|subscription| subscriptions.accounts.get(&subscription));
+        let data = v.pointer("/params/result/value/data/0").and_then(|data| data.as_str());
+        if let (Some(account), Some(data)) = (account, data) {
+            match base64::decode(data) {
+                Ok(data) => self.apply_update(account, &data),
+                Err(_) => debug!("Unreadable account update for {}", account),
+            }
+        }
+    }
+
+    /// Reads the pool's current reserves. Returns the accounts to subscribe
+    /// to, or `None` if the pool is already tracked.
+    fn add_pool(&self, pool_id: Pubkey) -> Result<Option<[Pubkey; 3]>> {
+        if self.pools.lock().unwrap().contains_key(&pool_id) {
+            return Ok(None);
+        }
+        let (coin_vault, pc_vault, reserves) = self.fetch_reserves(&pool_id)?;
+        debug!("Tracking reserves of {}: {:?}", pool_id, reserves);
+
+        self.pools.lock().unwrap().insert(pool_id, TrackedPool {
+            coin_vault,
+            pc_vault,
+            reserves,
+            last_traded: Instant::now(),
+        });
+        Ok(Some([pool_id, coin_vault, pc_vault]))
+    }
+
+    /// The pool's vaults and reserves, read in one request so the balances
+    /// and the fees owed out of them match.
+    fn fetch_reserves(&self, pool_id: &Pubkey) -> Result<(Pubkey, Pubkey, PoolReserves)> {
+        let pool = self.pool_cache.get(&self.rpc_client, pool_id)?;
+        let (coin_vault, pc_vault) = (pool.amm.coin_vault, pool.amm.pc_vault);
+        let accounts = self.rpc_client.get_multiple_accounts(&[*pool_id, coin_vault, pc_vault])?;
+        let [pool, coin_vault_account, pc_vault_account] = &accounts[..] else {
+            bail!("Expected 3 accounts for pool {}", pool_id);
+        };
+        let amm = AmmInfo::from_account_data(
+            &pool.as_ref().ok_or_else(|| anyhow!("Pool {} not found", pool_id))?.data,
+        )?;
+        let data = |account: &Option<solana_sdk::account::Account>| {
+            account.as_ref().map(|account| account.data.clone()).unwrap_or_default()
+        };
+        let reserves = PoolReserves::from_accounts(&amm, &data(coin_vault_account), &data(pc_vault_account))?;
+        Ok((coin_vault, pc_vault, reserves))
+    }
+
+    fn refresh(&self, accounts: &[Pubkey]) {
+        for chunk in accounts.chunks(100) {
+            match self.rpc_client.get_multiple_accounts(chunk) {
+                Ok(fetched) => {
+                    for (address, account) in chunk.iter().zip(fetched) {
+                        if let Some(account) = account {
+                            self.apply_update(address, &account.data);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to refresh pool reserves: {:?}", e),
+            }
+        }
+    }
+
+    /// Applies new data of a tracked pool account or one of its vaults.
+    fn apply_update(&self, address: &Pubkey, data: &[u8]) {
+        let mut pools = self.pools.lock().unwrap();
+        if let Some(pool) = pools.get_mut(address) {
+            match AmmInfo::from_account_data(data) {
+                Ok(amm) => pool.reserves.apply_amm(&amm),
+                Err(e) => debug!("Unreadable pool update for {}: {}", address, e),
+            }
+            return;
+        }
+        let Some(amount) = token_amount(data) else {
+            debug!("Unreadable vault update for {}", address);
+            return;
+        };
+        for pool in pools.values_mut() {
+            if pool.coin_vault == *address {
+                pool.reserves.coin_amount = amount;
+            } else if pool.pc_vault == *address {
+                pool.reserves.pc_amount = amount;
+            }
+        }
+    }
+
+    /// Stops tracking pools that are neither held nor recently traded, and
+    /// returns their subscriptions.
+    fn prune(&self, subscriptions: &AccountSubscriptions) -> Vec<u64> {
+        let mut pools = self.pools.lock().unwrap();
+        let expired: Vec<Pubkey> = pools.iter()
+            .filter(|(_, pool)| pool.last_traded.elapsed() > RECENT_POOL_TTL)
+            .filter(|(pool_id, pool)| {
+                let held = [pool.reserves.coin_mint, pool.reserves.pc_mint].iter()
+                    .filter_map(|mint| self.positions.get(mint))
+                    .any(|position| position.pool_id == **pool_id);
+                !held
+            })
+            .map(|(pool_id, _)| *pool_id)
+            .collect();
+
+        let mut accounts = Vec::new();
+        for pool_id in expired {
+            if let Some(pool) = pools.remove(&pool_id) {
+                debug!("No longer tracking reserves of {}", pool_id);
+                accounts.extend([pool_id, pool.coin_vault, pool.pc_vault]);
+            }
+        }
+        subscriptions.accounts.iter()
+            .filter(|(_, account)| accounts.contains(account))
+            .map(|(subscription, _)| *subscription)
+            .collect()
+    }
+}
+
+/// Amount field of an SPL token account.
+fn token_amount(data: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(64..72)?.try_into().ok()?))
+}
@@ -0,0 +1,432 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use tokio::sync::mpsc;
+use anyhow::{Result, anyhow};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use prost::Message;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use log::{info, debug, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::transaction::{self, ParsedTransaction, TokenBalance};
+use super::geyser::{self, subscribe_update::UpdateOneof};
+use super::{IngestionSource, SourceEvent};
+use super::recorder::{self, FrameKind, Protocol, Recorder};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Yellowstone (Geyser) gRPC feed of transactions that include a target.
+pub struct GrpcSource {
+    endpoint: String,
+    /// The endpoint with any API key left out, for logs and stats.
+    name: String,
+    x_token: Option<String>,
+    target_wallets: Vec<Pubkey>,
+    commitment: CommitmentConfig,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl GrpcSource {
+    pub fn new(
+        endpoint: String,
+        x_token: Option<String>,
+        target_wallets: Vec<Pubkey>,
+        commitment: CommitmentConfig,
+        recorder: Option<Arc<Recorder>>,
+    ) -> Self {
+        GrpcSource {
+            name: recorder::redact(&endpoint),
+            endpoint,
+            x_token,
+            target_wallets,
+            commitment,
+            recorder,
+        }
+    }
+
+    fn subscribe_request(&self) -> geyser::SubscribeRequest {
+        let filter = geyser::SubscribeRequestFilterTransactions {
+            vote: Some(false),
+            account_include: self.target_wallets.iter().map(|wallet| wallet.to_string()).collect(),
+            ..Default::default()
+        };
+
+        geyser::SubscribeRequest {
+            transactions: HashMap::from([("targets".to_string(), filter)]),
+            commitment: Some(geyser_commitment(self.commitment) as i32),
+            ping: None,
+        }
+    }
+
+    async fn listen(&self, events: mpsc::Sender<SourceEvent>) -> Result<()> {
+        let mut last_seen = Instant::now();
+        let result = self.listen_until_closed(&events, &mut last_seen).await;
+        let _ = events.send(SourceEvent::Disconnected {
+            source: self.name().to_string(),
+            last_seen,
+        }).await;
+        result
+    }
+
+    async fn listen_until_closed(&self, events: &mpsc::Sender<SourceEvent>, last_seen: &mut Instant) -> Result<()> {
+        info!("Connecting to gRPC endpoint: {}", self.name);
+        let mut endpoint = Endpoint::from_shared(self.endpoint.clone())?
+            .connect_timeout(CONNECT_TIMEOUT);
+        if self.endpoint.starts_with("https") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+        let channel = endpoint.connect().await?;
+
+        let mut client = tonic::client::Grpc::new(channel);
+        client.ready().await.map_err(|e| anyhow!("gRPC channel not ready: {}", e))?;
+
+        // Keep the request stream open; closing it ends the subscription.
+        let (requests, requests_rx) = futures::channel::mpsc::unbounded();
+        requests.unbounded_send(self.subscribe_request())?;
+        let mut request = tonic::Request::new(requests_rx);
+        if let Some(token) = &self.x_token {
+            request.metadata_mut().insert("x-token", token.parse()?);
+        }
+
+        let codec = ProstCodec::<geyser::SubscribeRequest, geyser::SubscribeUpdate>::default();
+        let mut updates = client
+            .streaming(request, PathAndQuery::from_static(geyser::SUBSCRIBE_PATH), codec)
+            .await?
+            .into_inner();
+        info!("gRPC subscription established for {} targets", self.target_wallets.len());
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&self.endpoint, Protocol::Grpc, FrameKind::Connect, String::new());
+        }
+        events.send(SourceEvent::Connected {
+            source: self.name().to_string(),
+        }).await?;
+
+        while let Some(update) = updates.message().await? {
+            *last_seen = Instant::now();
+            if let Some(recorder) = &self.recorder {
+                recorder.record(&self.endpoint, Protocol::Grpc, FrameKind::Received, base64::encode(update.encode_to_vec()));
+            }
+            // Providers close subscriptions that leave their pings unanswered.
+            if matches!(update.update_oneof, Some(UpdateOneof::Ping(_))) {
+                requests.unbounded_send(geyser::SubscribeRequest {
+                    ping: Some(geyser::SubscribeRequestPing { id: 1 }),
+                    ..Default::default()
+                })?;
+            }
+            match update_event(self.name(), update) {
+                Ok(Some(event)) => events.send(event).await?,
+                Ok(None) => {}
+                Err(e) => warn!("Skipping gRPC update from {}: {:?}", self.name(), e),
+            }
+        }
+
+        info!("gRPC stream closed, attempting to reconnect...");
+        Ok(())
+    }
+}
+
+impl IngestionSource for GrpcSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run<'a>(&'a self, events: mpsc::Sender<SourceEvent>) -> BoxFuture<'a, Result<()>> {
+        self.listen(events).boxed()
+    }
+}
+
+/// The event a subscription update carries, if any.
+pub(super) fn update_event(source: &str, update: geyser::SubscribeUpdate) -> Result<Option<SourceEvent>> {
+    match update.update_oneof {
+        Some(UpdateOneof::Transaction(update)) => {
+            let transaction = parse_transaction(update)?;
+            debug!("Received gRPC transaction: {}", transaction.signature);
+            Ok(Some(SourceEvent::Transaction {
+                source: source.to_string(),
+                transaction,
+            }))
+        }
+        Some(UpdateOneof::Ping(_)) => {
+            debug!("Received gRPC ping");
+            Ok(None)
+        }
+        Some(UpdateOneof::Pong(pong)) => {
+            debug!("Received gRPC pong {}", pong.id);
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+fn geyser_commitment(commitment: CommitmentConfig) -> geyser::CommitmentLevel {
+    match commitment.commitment {
+        CommitmentLevel::Processed => geyser::CommitmentLevel::Processed,
+        CommitmentLevel::Finalized => geyser::CommitmentLevel::Finalized,
+        _ => geyser::CommitmentLevel::Confirmed,
+    }
+}
+
+fn parse_pubkey(bytes: &[u8]) -> Result<Pubkey> {
+    Pubkey::try_from(bytes).map_err(|_| anyhow!("Invalid pubkey length {}", bytes.len()))
+}
+
+fn parse_token_balances(balances: Vec<geyser::TokenBalance>) -> Vec<TokenBalance> {
+    balances.into_iter()
+        .filter_map(|balance| {
+            let amount = balance.ui_token_amount?;
+            Some(TokenBalance {
+                account_index: balance.account_index as usize,
+                mint: balance.mint.parse().ok()?,
+                owner: balance.owner.parse().ok(),
+                amount: amount.amount.parse().ok()?,
+                decimals: amount.decimals as u8,
+            })
+        })
+        .collect()
+}
+
+fn parse_transaction(update: geyser::SubscribeUpdateTransaction) -> Result<ParsedTransaction> {
+    let info = update.transaction.ok_or_else(|| anyhow!("gRPC update without transaction"))?;
+    let signature = bs58::encode(&info.signature).into_string();
+    let message = info.transaction
+        .and_then(|transaction| transaction.message)
+        .ok_or_else(|| anyhow!("gRPC transaction {} without message", signature))?;
+    let meta = info.meta.ok_or_else(|| anyhow!("gRPC transaction {} without meta", signature))?;
+
+    // Yellowstone expands lookup tables itself; the loaded addresses follow
+    // the static keys in the order the runtime uses.
+    let account_keys = message.account_keys.iter()
+        .chain(meta.loaded_writable_addresses.iter())
+        .chain(meta.loaded_readonly_addresses.iter())
+        .map(|key| parse_pubkey(key))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut resolved = Vec::new();
+    for ix in message.instructions {
+        let program_id_index = u8::try_from(ix.program_id_index)?;
+        resolved.push(transaction::resolve_instruction(&account_keys, program_id_index, &ix.accounts, ix.data));
+    }
+
+    for set in meta.inner_instructions {
+        let calls = set.instructions.into_iter()
+            .filter_map(|ix| {
+                let program_id_index = u8::try_from(ix.program_id_index).ok()?;
+                transaction::resolve_instruction(&account_keys, program_id_index, &ix.accounts, ix.data)
+                    .map(|call| call.at_height(ix.stack_height))
+            })
+            .collect();
+        transaction::attach_inner_instructions(&mut resolved, set.index as usize, calls);
+    }
+    let instructions = transaction::collect_resolved(&signature, resolved);
+
+    Ok(ParsedTransaction {
+        signature,
+        slot: update.slot,
+        failed: meta.err.is_some(),
+        account_keys,
+        instructions,
+        log_messages: meta.log_messages,
+        pre_balances: meta.pre_balances,
+        post_balances: meta.post_balances,
+        fee: meta.fee,
+        pre_token_balances: parse_token_balances(meta.pre_token_balances),
+        post_token_balances: parse_token_balances(meta.post_token_balances),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use tokio::net::TcpListener;
+    use tonic::body::BoxBody;
+    use tonic::codegen::{http, Body, Service, StdError};
+    use tonic::server::{NamedService, StreamingService};
+    use tonic::transport::Server;
+    use tonic::{Request, Response, Status, Streaming};
+
+    /// In-process Geyser server that records the subscribe request and
+    /// replays a fixed list of updates before closing the stream.
+    #[derive(Clone)]
+    struct MockGeyser {
+        updates: Vec<geyser::SubscribeUpdate>,
+        requests: Arc<Mutex<Vec<geyser::SubscribeRequest>>>,
+    }
+
+    impl StreamingService<geyser::SubscribeRequest> for MockGeyser {
+        type Response = geyser::SubscribeUpdate;
+        type ResponseStream = futures::stream::BoxStream<'static, Result<geyser::SubscribeUpdate, Status>>;
+        type Future = BoxFuture<'static, Result<Response<Self::ResponseStream>, Status>>;
+
+        fn call(&mut self, request: Request<Streaming<geyser::SubscribeRequest>>) -> Self::Future {
+            let updates = self.updates.clone();
+            let requests = self.requests.clone();
+            async move {
+                let mut incoming = request.into_inner();
+                if let Some(subscribe) = incoming.message().await? {
+                    requests.lock().unwrap().push(subscribe);
+                }
+                // Close only once the client has answered the ping.
+                let reply = futures::stream::once(async move {
+                    let next = tokio::time::timeout(Duration::from_secs(5), incoming.message()).await;
+                    if let Ok(Ok(Some(request))) = next {
+                        requests.lock().unwrap().push(request);
+                    }
+                })
+                .filter_map(|_| futures::future::ready(None));
+                let stream: Self::ResponseStream = futures::stream::iter(updates.into_iter().map(Ok))
+                    .chain(reply)
+                    .boxed();
+                Ok(Response::new(stream))
+            }
+            .boxed()
+        }
+    }
+
+    impl<B> Service<http::Request<B>> for MockGeyser
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<B>) -> Self::Future {
+            let service = self.clone();
+            async move {
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::default());
+                Ok(grpc.streaming(service, request).await)
+            }
+            .boxed()
+        }
+    }
+
+    impl NamedService for MockGeyser {
+        const NAME: &'static str = "geyser.Geyser";
+    }
+
+    fn transaction_update(target: &Pubkey, pool: &Pubkey, mint: &Pubkey) -> geyser::SubscribeUpdate {
+        let program = Pubkey::new_unique();
+        let meta = geyser::TransactionStatusMeta {
+            log_messages: vec!["Program log: ray_log: test".to_string()],
+            post_token_balances: vec![geyser::TokenBalance {
+                account_index: 1,
+                mint: mint.to_string(),
+                ui_token_amount: Some(geyser::UiTokenAmount {
+                    decimals: 6,
+                    amount: "1500".to_string(),
+                    ..Default::default()
+                }),
+                owner: target.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let message = geyser::Message {
+            account_keys: vec![target.to_bytes().to_vec(), pool.to_bytes().to_vec(), program.to_bytes().to_vec()],
+            instructions: vec![geyser::CompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![1, 0],
+                data: vec![9, 1, 2, 3],
+            }],
+            ..Default::default()
+        };
+
+        geyser::SubscribeUpdate {
+            filters: vec!["targets".to_string()],
+            update_oneof: Some(UpdateOneof::Transaction(geyser::SubscribeUpdateTransaction {
+                transaction: Some(geyser::SubscribeUpdateTransactionInfo {
+                    signature: vec![7; 64],
+                    transaction: Some(geyser::Transaction {
+                        signatures: vec![vec![7; 64]],
+                        message: Some(message),
+                    }),
+                    meta: Some(meta),
+                    ..Default::default()
+                }),
+                slot: 42,
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_transactions_from_mock_server() {
+        let target = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let ping = geyser::SubscribeUpdate {
+            filters: vec![],
+            update_oneof: Some(UpdateOneof::Ping(geyser::SubscribeUpdatePing {})),
+        };
+        let undecodable = geyser::SubscribeUpdate {
+            filters: vec!["targets".to_string()],
+            update_oneof: Some(UpdateOneof::Transaction(geyser::SubscribeUpdateTransaction {
+                transaction: None,
+                slot: 41,
+            })),
+        };
+        let mock = MockGeyser {
+            updates: vec![ping, undecodable, transaction_update(&target, &pool, &mint)],
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let requests = mock.requests.clone();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        });
+        tokio::spawn(Server::builder().add_service(mock).serve_with_incoming(incoming));
+
+        let source = GrpcSource::new(
+            format!("http://{}", address),
+            None,
+            vec![target],
+            CommitmentConfig::processed(),
+            None,
+        );
+        let (events_tx, mut events_rx) = mpsc::channel(10);
+        source.run(events_tx).await.unwrap();
+
+        let event = events_rx.recv().await.expect("connected event");
+        assert!(matches!(event, SourceEvent::Connected { .. }));
+
+        let event = events_rx.recv().await.expect("transaction event");
+        match event {
+            SourceEvent::Transaction { transaction, .. } => {
+                assert_eq!(transaction.signature, bs58::encode([7u8; 64]).into_string());
+                assert_eq!(transaction.slot, 42);
+                assert_eq!(transaction.account_keys[0], target);
+                assert_eq!(transaction.instructions[0].accounts, vec![pool, target]);
+                assert_eq!(transaction.instructions[0].data, vec![9, 1, 2, 3]);
+                assert_eq!(transaction.token_mint(&pool), Some(mint));
+                assert_eq!(transaction.token_balance_change(&pool), Some(1500));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        let event = events_rx.recv().await.expect("disconnected event");
+        assert!(matches!(event, SourceEvent::Disconnected { .. }));
+        assert!(events_rx.recv().await.is_none());
+
+        let requests = requests.lock().unwrap();
+        let filter = &requests[0].transactions["targets"];
+        assert_eq!(filter.account_include, vec![target.to_string()]);
+        assert_eq!(filter.vote, Some(false));
+        assert_eq!(requests[0].commitment, Some(geyser::CommitmentLevel::Processed as i32));
+        assert_eq!(requests[1].ping, Some(geyser::SubscribeRequestPing { id: 1 }));
+        assert!(requests[1].transactions.is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+pub mod geyser;
+pub mod grpc;
+pub mod race;
+pub mod recorder;
+pub mod replay;
+pub mod websocket;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc;
+use std::time::Instant;
+use crate::transaction::ParsedTransaction;
+
+/// What an ingestion source hands to the listener.
+#[derive(Debug)]
+pub enum SourceEvent {
+    /// The source is connected and its subscriptions are live.
+    Connected {
+        source: String,
+    },
+    /// The connection ended; `last_seen` is when it last showed signs of life.
+    Disconnected {
+        source: String,
+        last_seen: Instant,
+    },
+    /// A signature that mentions a target; the transaction still has to be fetched.
+    Signature {
+        source: String,
+        signature: String,
+        slot: u64,
+        target: Pubkey,
+        /// The notification reported the transaction as failed.
+        failed: bool,
+    },
+    /// A complete transaction delivered by the source itself.
+    Transaction {
+        source: String,
+        transaction: ParsedTransaction,
+    },
+}
+
+/// A feed of target wallet activity. `run` streams events until the
+/// connection ends; callers decide whether to run it again.
+pub trait IngestionSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn run<'a>(&'a self, events: mpsc::Sender<SourceEvent>) -> BoxFuture<'a, Result<()>>;
+
+    /// Whether `run` should be started again once it returns. A replay ends
+    /// for good when its capture is exhausted.
+    fn restartable(&self) -> bool {
+        true
+    }
+}
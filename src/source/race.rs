@@ -0,0 +1,159 @@
+use log::info;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+use super::SourceEvent;
+
+const TRACKED_SIGNATURES: usize = 10_000;
+
+#[derive(Debug, Default, Clone)]
+pub struct ProviderStats {
+    /// Signatures this provider delivered first.
+    pub wins: u64,
+    /// Wins where another provider delivered later, and the summed lead over it.
+    pub contested_wins: u64,
+    pub total_lead_ms: u128,
+    /// Signatures another provider delivered first, and the summed delay.
+    pub losses: u64,
+    pub total_lag_ms: u128,
+}
+
+struct Arrival {
+    winner: String,
+    at: Instant,
+    runner_up_seen: bool,
+}
+
+/// Sits in front of the listener when several providers stream the same
+/// targets: forwards a signature the first time any provider delivers it and
+/// keeps score of who was fastest.
+pub struct Race {
+    arrivals: HashMap<String, Arrival>,
+    order: VecDeque<String>,
+    stats: HashMap<String, ProviderStats>,
+}
+
+impl Race {
+    pub fn new() -> Self {
+        Race {
+            arrivals: HashMap::new(),
+            order: VecDeque::new(),
+            stats: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the event should be processed.
+    pub fn admit(&mut self, event: &SourceEvent) -> bool {
+        let (source, signature) = match event {
+            SourceEvent::Signature { source, signature, .. } => (source, signature),
+            SourceEvent::Transaction { source, transaction } => (source, &transaction.signature),
+            SourceEvent::Connected { .. } | SourceEvent::Disconnected { .. } => return true,
+        };
+        let now = Instant::now();
+
+        match self.arrivals.get_mut(signature) {
+            None => {
+                self.stats.entry(source.clone()).or_default().wins += 1;
+                self.arrivals.insert(signature.clone(), Arrival {
+                    winner: source.clone(),
+                    at: now,
+                    runner_up_seen: false,
+                });
+                self.order.push_back(signature.clone());
+                if self.order.len() > TRACKED_SIGNATURES {
+                    if let Some(oldest) = self.order.pop_front() {
+                        self.arrivals.remove(&oldest);
+                    }
+                }
+                true
+            }
+            Some(arrival) => {
+                if arrival.winner == *source {
+                    return false;
+                }
+                let lag_ms = now.duration_since(arrival.at).as_millis();
+
+                let loser = self.stats.entry(source.clone()).or_default();
+                loser.losses += 1;
+                loser.total_lag_ms += lag_ms;
+
+                if !arrival.runner_up_seen {
+                    arrival.runner_up_seen = true;
+                    let winner = self.stats.entry(arrival.winner.clone()).or_default();
+                    winner.contested_wins += 1;
+                    winner.total_lead_ms += lag_ms;
+                }
+                false
+            }
+        }
+    }
+
+    pub fn log_stats(&self) {
+        for (provider, stats) in &self.stats {
+            let average_lead = stats.total_lead_ms.checked_div(stats.contested_wins as u128).unwrap_or(0);
+            let average_lag = stats.total_lag_ms.checked_div(stats.losses as u128).unwrap_or(0);
+            info!(
+                "{}: first on {} signatures (avg lead {} ms over runner-up), late on {} (avg {} ms behind)",
+                provider, stats.wins, average_lead, stats.losses, average_lag,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use std::time::Duration;
+
+    fn signature(source: &str, signature: &str) -> SourceEvent {
+        SourceEvent::Signature {
+            source: source.to_string(),
+            signature: signature.to_string(),
+            slot: 250_000_000,
+            target: Pubkey::new_unique(),
+            failed: false,
+        }
+    }
+
+    #[test]
+    fn admits_each_signature_once() {
+        let mut race = Race::new();
+        assert!(race.admit(&signature("helius", "a")));
+        assert!(!race.admit(&signature("triton", "a")));
+        assert!(!race.admit(&signature("helius", "a")));
+        assert!(race.admit(&signature("triton", "b")));
+        assert!(race.admit(&SourceEvent::Connected { source: "helius".to_string() }));
+        assert!(race.admit(&SourceEvent::Connected { source: "helius".to_string() }));
+    }
+
+    #[test]
+    fn scores_wins_leads_and_lags_per_provider() {
+        let mut race = Race::new();
+        race.admit(&signature("helius", "a"));
+        std::thread::sleep(Duration::from_millis(20));
+        race.admit(&signature("triton", "a"));
+        race.admit(&signature("quicknode", "a"));
+        // A repeat from the winner is neither a loss nor a second contest.
+        race.admit(&signature("helius", "a"));
+        race.admit(&signature("triton", "b"));
+
+        let helius = &race.stats["helius"];
+        assert_eq!((helius.wins, helius.contested_wins, helius.losses), (1, 1, 0));
+        assert!(helius.total_lead_ms >= 20);
+        let triton = &race.stats["triton"];
+        assert_eq!((triton.wins, triton.contested_wins, triton.losses), (1, 0, 1));
+        assert!(triton.total_lag_ms >= 20);
+        let quicknode = &race.stats["quicknode"];
+        assert_eq!((quicknode.wins, quicknode.losses), (0, 1));
+    }
+
+    #[test]
+    fn forgets_the_oldest_signatures() {
+        let mut race = Race::new();
+        for index in 0..=TRACKED_SIGNATURES {
+            assert!(race.admit(&signature("helius", &index.to_string())));
+        }
+        assert!(!race.admit(&signature("triton", "1")));
+        assert!(race.admit(&signature("triton", "0")));
+    }
+}
@@ -0,0 +1,315 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use anyhow::{Result, anyhow};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use prost::Message;
+use log::{info, debug, warn};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use super::geyser;
+use super::grpc;
+use super::recorder::{Frame, FrameKind, Protocol};
+use super::websocket::{Subscriptions, WebSocketSource};
+use super::{IngestionSource, SourceEvent};
+use crate::transaction::ParsedTransaction;
+
+/// Plays a capture written by `Recorder` back through the same parsing as
+/// the live sources, so the listener sees the events it saw when recording.
+/// Signatures whose transaction was recorded are delivered with it, so the
+/// listener does not fetch them again.
+///
+/// `speed` scales the recorded gaps between frames: 1.0 replays in real
+/// time, 10.0 ten times faster and 0 without waiting at all.
+pub struct ReplaySource {
+    path: String,
+    speed: f64,
+}
+
+/// Events one WebSocket message can produce.
+const PARSED_EVENTS: usize = 16;
+
+/// What a recorded WebSocket connection subscribed to, rebuilt from the
+/// requests it sent.
+struct WebSocketSession {
+    targets: Vec<Pubkey>,
+    slot_request_id: u64,
+    parser: Option<(WebSocketSource, Subscriptions)>,
+    last_seen: Instant,
+}
+
+impl ReplaySource {
+    pub fn new(path: String, speed: f64) -> Self {
+        ReplaySource { path, speed }
+    }
+
+    async fn replay(&self, events: mpsc::Sender<SourceEvent>) -> Result<()> {
+        let transactions = self.recorded_transactions().await?;
+        let mut lines = self.frames().await?;
+        info!("Replaying {} at {}x with {} recorded transactions", self.path, self.speed, transactions.len());
+
+        let mut websockets: HashMap<String, WebSocketSession> = HashMap::new();
+        let mut grpc_sources: HashMap<String, Instant> = HashMap::new();
+        let mut previous_at = None;
+        let mut frames = 0u64;
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame: Frame = serde_json::from_str(&line)
+                .map_err(|e| anyhow!("Invalid frame {} in {}: {}", frames + 1, self.path, e))?;
+            frames += 1;
+            if frame.kind == FrameKind::Transaction {
+                continue;
+            }
+
+            if let Some(previous) = previous_at {
+                if self.speed > 0.0 && frame.at_ms > previous {
+                    let gap = Duration::from_millis(frame.at_ms - previous);
+                    tokio::time::sleep(gap.div_f64(self.speed)).await;
+                }
+            }
+            previous_at = Some(frame.at_ms);
+
+            match frame.protocol {
+                Protocol::WebSocket => self.replay_websocket(frame, &mut websockets, &transactions, &events).await?,
+                Protocol::Grpc => self.replay_grpc(frame, &mut grpc_sources, &events).await?,
+                Protocol::Rpc => debug!("Ignoring recorded {:?} RPC frame", frame.kind),
+            }
+        }
+
+        for (source, session) in websockets {
+            disconnected(&events, source, session.last_seen).await?;
+        }
+        for (source, last_seen) in grpc_sources {
+            disconnected(&events, source, last_seen).await?;
+        }
+        info!("Replay of {} finished after {} frames", self.path, frames);
+        Ok(())
+    }
+
+    async fn frames(&self) -> Result<tokio::io::Lines<BufReader<tokio::fs::File>>> {
+        let file = tokio::fs::File::open(&self.path).await
+            .map_err(|e| anyhow!("Failed to open capture {}: {}", self.path, e))?;
+        Ok(BufReader::new(file).lines())
+    }
+
+    /// Transactions the listener fetched while recording, by signature. They
+    /// are written after the notification that led to them, so the capture
+    /// is read once up front to find them.
+    async fn recorded_transactions(&self) -> Result<HashMap<String, ParsedTransaction>> {
+        let mut lines = self.frames().await?;
+        let mut transactions = HashMap::new();
+        while let Some(line) = lines.next_line().await? {
+            let Ok(frame) = serde_json::from_str::<Frame>(&line) else {
+                continue;
+            };
+            if frame.kind == FrameKind::Transaction {
+                let transaction: ParsedTransaction = serde_json::from_str(&frame.data)
+                    .map_err(|e| anyhow!("Invalid recorded transaction in {}: {}", self.path, e))?;
+                transactions.insert(transaction.signature.clone(), transaction);
+            }
+        }
+        Ok(transactions)
+    }
+
+    async fn replay_websocket(
+        &self,
+        frame: Frame,
+        sessions: &mut HashMap<String, WebSocketSession>,
+        transactions: &HashMap<String, ParsedTransaction>,
+        events: &mpsc::Sender<SourceEvent>,
+    ) -> Result<()> {
+        match frame.kind {
+            FrameKind::Connect => {
+                let session = WebSocketSession {
+                    targets: Vec::new(),
+                    slot_request_id: 0,
+                    parser: None,
+                    last_seen: Instant::now(),
+                };
+                if let Some(previous) = sessions.insert(frame.source.clone(), session) {
+                    disconnected(events, frame.source, previous.last_seen).await?;
+                }
+            }
+            FrameKind::Sent => {
+                let session = sessions.get_mut(&frame.source)
+                    .ok_or_else(|| anyhow!("Frame from {} before it connected", frame.source))?;
+                let request: Value = serde_json::from_str(&frame.data)?;
+                match request.get("method").and_then(|method| method.as_str()) {
+                    Some("logsSubscribe") => {
+                        let target = request.pointer("/params/0/mentions/0")
+                            .and_then(|target| target.as_str())
+                            .ok_or_else(|| anyhow!("logsSubscribe without a target: {}", frame.data))?;
+                        session.targets.push(Pubkey::from_str(target)?);
+                    }
+                    Some("slotSubscribe") => {
+                        session.slot_request_id = request.get("id").and_then(|id| id.as_u64()).unwrap_or(0);
+                    }
+                    _ => debug!("Ignoring recorded request: {}", frame.data),
+                }
+            }
+            FrameKind::Received => {
+                let session = sessions.get_mut(&frame.source)
+                    .ok_or_else(|| anyhow!("Frame from {} before it connected", frame.source))?;
+                // Subscriptions are all sent before the first reply arrives.
+                let (socket, subscriptions) = session.parser.get_or_insert_with(|| (
+                    WebSocketSource::new(
+                        frame.source.clone(),
+                        session.targets.clone(),
+                        Duration::ZERO,
+                        Duration::ZERO,
+                        CommitmentConfig::default(),
+                        None,
+                    ),
+                    Subscriptions::new(session.slot_request_id),
+                ));
+                let (parsed_tx, mut parsed_rx) = mpsc::channel(PARSED_EVENTS);
+                socket.handle_text(&frame.data, subscriptions, &parsed_tx, &mut session.last_seen).await?;
+                drop(parsed_tx);
+                while let Some(event) = parsed_rx.recv().await {
+                    events.send(with_recorded_transaction(event, transactions)).await?;
+                }
+            }
+            FrameKind::Transaction => {}
+        }
+        Ok(())
+    }
+
+    async fn replay_grpc(
+        &self,
+        frame: Frame,
+        sources: &mut HashMap<String, Instant>,
+        events: &mpsc::Sender<SourceEvent>,
+    ) -> Result<()> {
+        match frame.kind {
+            FrameKind::Connect => {
+                if let Some(last_seen) = sources.insert(frame.source.clone(), Instant::now()) {
+                    disconnected(events, frame.source.clone(), last_seen).await?;
+                }
+                events.send(SourceEvent::Connected { source: frame.source }).await?;
+            }
+            FrameKind::Sent | FrameKind::Transaction => {}
+            FrameKind::Received => {
+                sources.insert(frame.source.clone(), Instant::now());
+                let bytes = base64::decode(&frame.data)?;
+                let update = geyser::SubscribeUpdate::decode(bytes.as_slice())?;
+                match grpc::update_event(&frame.source, update) {
+                    Ok(Some(event)) => events.send(event).await?,
+                    Ok(None) => {}
+                    Err(e) => warn!("Skipping recorded gRPC update from {}: {:?}", frame.source, e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A notified signature becomes the transaction recorded for it, if any.
+fn with_recorded_transaction(event: SourceEvent, transactions: &HashMap<String, ParsedTransaction>) -> SourceEvent {
+    match event {
+        SourceEvent::Signature { source, signature, failed: false, .. } if transactions.contains_key(&signature) => {
+            SourceEvent::Transaction {
+                source,
+                transaction: transactions[&signature].clone(),
+            }
+        }
+        event => event,
+    }
+}
+
+async fn disconnected(events: &mpsc::Sender<SourceEvent>, source: String, last_seen: Instant) -> Result<()> {
+    events.send(SourceEvent::Disconnected { source, last_seen }).await?;
+    Ok(())
+}
+
+impl IngestionSource for ReplaySource {
+    fn name(&self) -> &str {
+        &self.path
+    }
+
+    fn run<'a>(&'a self, events: mpsc::Sender<SourceEvent>) -> BoxFuture<'a, Result<()>> {
+        self.replay(events).boxed()
+    }
+
+    fn restartable(&self) -> bool {
+        false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::recorder::Recorder;
+
+    #[tokio::test]
+    async fn replays_recorded_transactions_without_rpc() {
+        let target = Pubkey::new_unique();
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", Pubkey::new_unique()));
+        let path = path.to_str().unwrap().to_string();
+        let endpoint = "wss://rpc.example.com/?api-key=secret";
+        let transaction = ParsedTransaction {
+            signature: "5h6xBEauJ3PK6SWCZ1PGjBvj8vDdWG3KpwATGy1ARAXFSDwt8GFXM7W5Ncn16wmqokgpiKRLuS83KUxyZyv2sUYv".to_string(),
+            slot: 7,
+            failed: false,
+            account_keys: vec![target],
+            instructions: Vec::new(),
+            log_messages: vec!["Program log: test".to_string()],
+            pre_balances: vec![10],
+            post_balances: vec![5],
+            fee: 5,
+            pre_token_balances: Vec::new(),
+            post_token_balances: Vec::new(),
+        };
+
+        let recorder = Recorder::new(&path).unwrap();
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Connect, String::new());
+        let subscribe = serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "logsSubscribe",
+            "params": [{ "mentions": [target.to_string()] }, { "commitment": "confirmed" }],
+        });
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Sent, subscribe.to_string());
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Sent,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "slotSubscribe" }).to_string());
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Received,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": 11 }).to_string());
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Received,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 2, "result": 12 }).to_string());
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0", "method": "logsNotification",
+            "params": {
+                "subscription": 11,
+                "result": { "context": { "slot": 7 }, "value": { "signature": transaction.signature, "err": null, "logs": [] } },
+            },
+        });
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Received, notification.to_string());
+        recorder.record("https://rpc.example.com/?api-key=secret", Protocol::Rpc, FrameKind::Transaction,
+            serde_json::to_string(&transaction).unwrap());
+        drop(recorder);
+
+        let capture = std::fs::read_to_string(&path).unwrap();
+        assert!(!capture.contains("secret"));
+
+        let (events_tx, mut events_rx) = mpsc::channel(10);
+        ReplaySource::new(path.clone(), 0.0).run(events_tx).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(events_rx.recv().await, Some(SourceEvent::Connected { .. })));
+        match events_rx.recv().await {
+            Some(SourceEvent::Transaction { source, transaction: replayed }) => {
+                assert_eq!(source, "wss://rpc.example.com/");
+                assert_eq!(replayed.signature, transaction.signature);
+                assert_eq!(replayed.account_keys, vec![target]);
+                assert_eq!(replayed.post_balances, vec![5]);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(events_rx.recv().await, Some(SourceEvent::Disconnected { .. })));
+    }
+}
@@ -0,0 +1,261 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use anyhow::{Result, anyhow};
+use futures::future::BoxFuture;
+use futures::{FutureExt, SinkExt, StreamExt};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use url::Url;
+use log::{info, error, debug, trace, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use super::{IngestionSource, SourceEvent};
+use super::recorder::{self, FrameKind, Protocol, Recorder};
+
+/// JSON-RPC `logsSubscribe` feed over a WebSocket.
+///
+/// A `slotSubscribe` runs alongside the log subscriptions as a heartbeat:
+/// if no slot notification arrives within `liveness_timeout` the socket is
+/// treated as dead and torn down, even if TCP still looks open.
+pub struct WebSocketSource {
+    endpoint: String,
+    /// The endpoint with any API key left out, for logs and stats.
+    name: String,
+    target_wallets: Vec<Pubkey>,
+    ping_interval: Duration,
+    liveness_timeout: Duration,
+    commitment: CommitmentConfig,
+    recorder: Option<Arc<Recorder>>,
+}
+
+/// Per-connection subscription bookkeeping.
+pub(super) struct Subscriptions {
+    /// Subscription id -> target wallet, filled in as confirmations arrive.
+    targets: HashMap<u64, Pubkey>,
+    slot_request_id: u64,
+    slot_subscription: Option<u64>,
+    live: bool,
+}
+
+impl WebSocketSource {
+    pub fn new(
+        endpoint: String,
+        target_wallets: Vec<Pubkey>,
+        ping_interval: Duration,
+        liveness_timeout: Duration,
+        commitment: CommitmentConfig,
+        recorder: Option<Arc<Recorder>>,
+    ) -> Self {
+        WebSocketSource {
+            name: recorder::redact(&endpoint),
+            endpoint,
+            target_wallets,
+            ping_interval,
+            liveness_timeout,
+            commitment,
+            recorder,
+        }
+    }
+
+    fn record(&self, kind: FrameKind, data: &str) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&self.endpoint, Protocol::WebSocket, kind, data.to_string());
+        }
+    }
+
+    async fn listen(&self, events: mpsc::Sender<SourceEvent>) -> Result<()> {
+        let mut last_seen = Instant::now();
+        let result = self.listen_until_closed(&events, &mut last_seen).await;
+        let _ = events.send(SourceEvent::Disconnected {
+            source: self.name().to_string(),
+            last_seen,
+        }).await;
+        result
+    }
+
+    async fn listen_until_closed(&self, events: &mpsc::Sender<SourceEvent>, last_seen: &mut Instant) -> Result<()> {
+        info!("Connecting to WebSocket endpoint: {}", self.name);
+        let url = Url::parse(&self.endpoint)?;
+
+        let (ws_stream, response) = connect_async(url).await?;
+        info!("WebSocket connected with status: {}", response.status());
+        self.record(FrameKind::Connect, "");
+
+        let (mut write, mut read) = ws_stream.split();
+
+        // logsSubscribe accepts a single address per subscription, so each
+        // target gets its own subscription on this socket.
+        for (index, target) in self.target_wallets.iter().enumerate() {
+            let subscribe_msg = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": index + 1,
+                "method": "logsSubscribe",
+                "params": [
+                    {
+                        "mentions": [ target.to_string() ]
+                    },
+                    {
+                        "commitment": self.commitment.commitment.to_string(),
+                        "encoding": "jsonParsed"
+                    }
+                ]
+            });
+
+            info!("Sending subscription request: {}", subscribe_msg);
+            self.record(FrameKind::Sent, &subscribe_msg.to_string());
+            write.send(Message::Text(subscribe_msg.to_string())).await?;
+        }
+
+        let slot_request_id = self.target_wallets.len() as u64 + 1;
+        let slot_subscribe_msg = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": slot_request_id,
+            "method": "slotSubscribe"
+        });
+        self.record(FrameKind::Sent, &slot_subscribe_msg.to_string());
+        write.send(Message::Text(slot_subscribe_msg.to_string())).await?;
+
+        let mut subscriptions = Subscriptions::new(slot_request_id);
+        *last_seen = Instant::now();
+        let mut ping_interval = tokio::time::interval(self.ping_interval);
+
+        info!("Waiting for messages...");
+        loop {
+            let deadline = tokio::time::Instant::from_std(*last_seen + self.liveness_timeout);
+            tokio::select! {
+                msg = read.next() => {
+                    match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            self.record(FrameKind::Received, &text);
+                            self.handle_text(&text, &mut subscriptions, events, last_seen).await?;
+                        },
+                        Some(Ok(Message::Pong(_))) => {
+                            debug!("Received pong from {}", self.name);
+                        },
+                        Some(Ok(Message::Close(frame))) => {
+                            error!("WebSocket closed by server: {:?}", frame);
+                            break;
+                        },
+                        Some(Err(e)) => {
+                            error!("WebSocket error: {:?}", e);
+                            break;
+                        },
+                        Some(Ok(_)) => {
+                            debug!("Received non-text message");
+                        },
+                        None => break,
+                    }
+                }
+                _ = ping_interval.tick() => {
+                    write.send(Message::Ping(Vec::new())).await?;
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    warn!("No slot heartbeat from {} for {:?}, dropping connection", self.name, self.liveness_timeout);
+                    let _ = write.send(Message::Close(None)).await;
+                    return Err(anyhow!("Stale WebSocket connection to {}", self.name));
+                }
+            }
+        }
+
+        info!("WebSocket connection closed, attempting to reconnect...");
+        Ok(())
+    }
+
+    /// Applies one received frame to the connection's subscriptions and
+    /// forwards any target activity it reports.
+    pub(super) async fn handle_text(
+        &self,
+        text: &str,
+        subscriptions: &mut Subscriptions,
+        events: &mpsc::Sender<SourceEvent>,
+        last_seen: &mut Instant,
+    ) -> Result<()> {
+        let v: Value = serde_json::from_str(text).unwrap_or(Value::Null);
+
+        let method = v.get("method").and_then(|method| method.as_str());
+        if method == Some("slotNotification") {
+            *last_seen = Instant::now();
+            return Ok(());
+        }
+
+        trace!("Received WebSocket message: {}", text);
+        if let Some(method) = method {
+            debug!("Message method: {}", method);
+        }
+
+        // Subscription confirmation: { id, result: <subscription id> }
+        if let (Some(id), Some(subscription)) = (
+            v.get("id").and_then(|id| id.as_u64()),
+            v.get("result").and_then(|result| result.as_u64()),
+        ) {
+            if id == subscriptions.slot_request_id {
+                info!("Slot heartbeat subscribed (subscription {})", subscription);
+                subscriptions.slot_subscription = Some(subscription);
+            } else if let Some(target) = id.checked_sub(1).and_then(|index| self.target_wallets.get(index as usize)) {
+                info!("Subscribed to {} (subscription {})", target, subscription);
+                subscriptions.targets.insert(subscription, *target);
+            }
+
+            if !subscriptions.live
+                && subscriptions.slot_subscription.is_some()
+                && subscriptions.targets.len() == self.target_wallets.len()
+            {
+                subscriptions.live = true;
+                *last_seen = Instant::now();
+                events.send(SourceEvent::Connected {
+                    source: self.name().to_string(),
+                }).await?;
+            }
+            return Ok(());
+        }
+
+        let target = v.pointer("/params/subscription")
+            .and_then(|subscription| subscription.as_u64())
+            .and_then(|subscription| subscriptions.targets.get(&subscription))
+            .copied();
+
+        // logsNotification: params.result = { context: { slot }, value: { signature, err, logs } }
+        // Every transaction mentioning a target is forwarded; the decoders
+        // decide whether it was a trade.
+        if let (Some(value), Some(target)) = (v.pointer("/params/result/value"), target) {
+            if let Some(signature) = value.get("signature").and_then(|s| s.as_str()) {
+                info!("Found transaction from {}: {}", target, signature);
+                events.send(SourceEvent::Signature {
+                    source: self.name().to_string(),
+                    signature: signature.to_string(),
+                    slot: v.pointer("/params/result/context/slot")
+                        .and_then(|slot| slot.as_u64())
+                        .unwrap_or(0),
+                    target,
+                    failed: value.get("err").map(|err| !err.is_null()).unwrap_or(false),
+                }).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Subscriptions {
+    pub(super) fn new(slot_request_id: u64) -> Self {
+        Subscriptions {
+            targets: HashMap::new(),
+            slot_request_id,
+            slot_subscription: None,
+            live: false,
+        }
+    }
+}
+
+impl IngestionSource for WebSocketSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run<'a>(&'a self, events: mpsc::Sender<SourceEvent>) -> BoxFuture<'a, Result<()>> {
+        self.listen(events).boxed()
+    }
+}
@@ -1,58 +1,621 @@
 use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcSimulateTransactionConfig, RpcTransactionConfig};
+use solana_transaction_status::UiTransactionEncoding;
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::transaction::Transaction;
 use solana_sdk::instruction::Instruction;
 use solana_sdk::pubkey::Pubkey;
-use anyhow::Result;
-use crate::types::TradeDetails;
+use solana_sdk::signature::Signature;
+use solana_sdk::native_token::sol_to_lamports;
+use anyhow::{Result, anyhow, bail};
+use crate::dex::{meteora_dlmm, orca_whirlpool, pump_fun, raydium_amm_v4};
+use crate::dex::meteora_dlmm::LbPair;
+use crate::dex::orca_whirlpool::Whirlpool;
+use crate::dex::pump_fun::BondingCurve;
+use crate::transaction;
+use crate::utils;
+use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use crate::pool_cache::PoolCache;
+use crate::quote::{self, Quote};
+use crate::positions::{Position, PositionBook};
+use crate::reserves::ReserveTracker;
+use crate::types::{Dex, TradeDetails, TradeType};
 use crate::wallet::Wallet;
-use crate::config::Config;
-use log::info;
-use std::str::FromStr;
+use crate::config::{Config, CopySettings, IngestionKind, TargetWallet};
+use log::{info, debug, error, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
 pub struct Trader {
     rpc_client: RpcClient,
+    commitment: CommitmentConfig,
+    slippage_bps: u64,
+    max_price_impact_bps: u64,
+    paper_trading: bool,
+    /// Copies of replayed trades are priced from the capture alone.
+    replay: bool,
     wallet: Wallet,
+    targets: HashMap<Pubkey, TargetWallet>,
+    positions: Arc<PositionBook>,
+    pool_cache: Arc<PoolCache>,
+    reserves: Arc<ReserveTracker>,
+    /// Our token accounts known to exist, so creating them is only paid for
+    /// on the first buy of a mint.
+    token_accounts: Mutex<HashSet<Pubkey>>,
 }
 
 impl Trader {
-    pub fn new(config: &Config) -> Result<Self> {
-        let rpc_client = RpcClient::new(config.rpc_endpoint.clone());
+    pub fn new(
+        config: &Config,
+        positions: Arc<PositionBook>,
+        pool_cache: Arc<PoolCache>,
+        reserves: Arc<ReserveTracker>,
+    ) -> Result<Self> {
+        // Blockhashes, simulation and confirmation all use the execution commitment.
+        let rpc_client = RpcClient::new_with_commitment(
+            config.rpc_endpoint.clone(),
+            config.execution_commitment,
+        );
         let wallet = Wallet::new(config.private_key.clone())?;
         
+        let targets = config.targets.iter()
+            .map(|target| (target.wallet, target.clone()))
+            .collect();
+        
         Ok(Trader {
             rpc_client,
+            commitment: config.execution_commitment,
+            slippage_bps: config.slippage_bps,
+            max_price_impact_bps: config.max_price_impact_bps,
+            paper_trading: config.paper_trading,
+            replay: config.ingestion_source == IngestionKind::Replay,
             wallet,
+            targets,
+            positions,
+            pool_cache,
+            reserves,
+            token_accounts: Mutex::new(HashSet::new()),
         })
     }
     
     pub fn execute_trade(&self, trade: TradeDetails) -> Result<()> {
+        let target = self.targets.get(&trade.target)
+            .ok_or_else(|| anyhow!("Trade from unknown target {}", trade.target))?;
+        if !target.enabled {
+            info!("Ignoring trade from disabled target {}", trade.target);
+            return Ok(());
+        }
+
+        // Inferred sells of a token we hold can still be copied on the
+        // position's venue; anything else without a venue cannot.
+        let trade = self.route_to_position(trade);
+        if trade.dex == Dex::Unclassified {
+            info!("No venue for {} trade {}, not copying",
+                if trade.inferred { "inferred" } else { "unclassified" }, trade.signature);
+            return Ok(());
+        }
+        if self.replay && trade.trade_type == TradeType::Swap {
+            return self.replay_copy(&trade, &target.settings);
+        }
+        if trade.dex == Dex::RaydiumAmmV4 {
+            self.reserves.watch(trade.pool_id);
+        }
+
         match trade.trade_type {
-            crate::types::TradeType::Swap => self.swap_tokens(),
-            crate::types::TradeType::AddLiquidity => self.add_liquidity(trade),
-            crate::types::TradeType::RemoveLiquidity => self.remove_liquidity(trade),
+            TradeType::Swap => {
+                let amount_in = self.held_copy_amount(&trade, &target.settings);
+                if amount_in == 0 {
+                    info!("No {} held, not copying sell", trade.input_token);
+                    return Ok(());
+                }
+                match trade.dex {
+                    Dex::PumpFun if trade.input_token == spl_token::native_mint::id() => self.pump_fun_buy(&trade, amount_in),
+                    Dex::PumpFun => self.pump_fun_sell(&trade, amount_in),
+                    _ => self.swap(&trade, amount_in),
+                }
+            }
+            TradeType::AddLiquidity => self.add_liquidity(trade),
+            TradeType::RemoveLiquidity => self.remove_liquidity(trade),
+        }
+    }
+
+    /// Paper copy of a replayed trade. Pool state now is not pool state when
+    /// the capture was taken, so nothing is read from the chain and the
+    /// same capture always replays to the same copies.
+    fn replay_copy(&self, trade: &TradeDetails, settings: &CopySettings) -> Result<()> {
+        let (amount_in, quote, min_amount_out) = Self::replay_terms(trade, settings, self.slippage_bps)?;
+        info!("Replaying copy of {} {} -> {} on {:?} with {} in, at least {} out",
+            trade.input_amount, trade.input_token, trade.output_token, trade.dex, amount_in, min_amount_out);
+        self.record_fill(trade, amount_in, quote.amount_out);
+        Ok(())
+    }
+
+    /// Our amount in, its quote at the price the target got, and the least
+    /// output to accept.
+    fn replay_terms(trade: &TradeDetails, settings: &CopySettings, slippage_bps: u64) -> Result<(u64, Quote, u64)> {
+        let amount_in = Self::copy_amount(trade, settings);
+        let quote = quote::quote_at_price(trade.input_amount, trade.output_amount, amount_in)?;
+        Ok((amount_in, quote, quote::min_amount_out(quote.amount_out, slippage_bps)))
+    }
+    
+    /// Sells go to the venue our position is on, which differs from the
+    /// target's when the token migrated after we bought.
+    fn route_to_position(&self, mut trade: TradeDetails) -> TradeDetails {
+        if trade.output_token != spl_token::native_mint::id() {
+            return trade;
+        }
+        if let Some(position) = self.positions.get(&trade.input_token) {
+            if position.dex != trade.dex || position.pool_id != trade.pool_id {
+                info!("Routing sell of {} to {:?} {} where our position is", trade.input_token, position.dex, position.pool_id);
+                trade.dex = position.dex;
+                trade.pool_id = position.pool_id;
+            }
+        }
+        trade
+    }
+
+    /// Updates our positions after a copy lands: buys with SOL open or add to
+    /// the output mint, sells for SOL reduce the input mint.
+    fn record_fill(&self, trade: &TradeDetails, amount_in: u64, amount_out: u64) {
+        let native_mint = spl_token::native_mint::id();
+        if trade.input_token == native_mint {
+            let settings = self.targets.get(&trade.target).map(|target| &target.settings);
+            self.positions.open(trade.output_token, trade.dex, trade.pool_id, amount_out, amount_in, settings);
+        } else if trade.output_token == native_mint {
+            self.positions.reduce(&trade.input_token, amount_in);
+        }
+    }
+
+    /// Size of our copy: the target's input scaled by the buy-in percentage,
+    /// capped at the order size when spending SOL.
+    fn copy_amount(trade: &TradeDetails, settings: &CopySettings) -> u64 {
+        let scaled = (trade.input_amount as f64 * settings.buyin_percentage) as u64;
+        if trade.input_token == spl_token::native_mint::id() {
+            scaled.min(sol_to_lamports(settings.order_size))
+        } else {
+            scaled
         }
     }
     
-    fn swap_tokens(&self) -> Result<()> {
-        let instruction = Instruction {
-            program_id: Pubkey::from_str("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8")?,
-            accounts: vec![], 
-            data: vec![], 
+    /// Our copy size, with sells capped at what we hold of the token.
+    fn held_copy_amount(&self, trade: &TradeDetails, settings: &CopySettings) -> u64 {
+        let amount = Self::copy_amount(trade, settings);
+        if trade.input_token == spl_token::native_mint::id() {
+            amount
+        } else {
+            amount.min(self.held_amount(&trade.input_token))
+        }
+    }
+
+    fn swap(&self, trade: &TradeDetails, amount_in: u64) -> Result<()> {
+        let user = self.wallet.public_key;
+        let user_source = get_associated_token_address(&user, &trade.input_token);
+        let user_destination = get_associated_token_address(&user, &trade.output_token);
+        // Every venue quotes our amount against the pool's current state;
+        // a swap that cannot be quoted is not sent.
+        let (swap, quote) = match trade.dex {
+            Dex::OrcaWhirlpool => self.whirlpool_swap(trade, &user_source, &user_destination, amount_in)?,
+            Dex::MeteoraDlmm => self.dlmm_swap(trade, &user_source, &user_destination, amount_in)?,
+            Dex::RaydiumAmmV4 => self.amm_v4_swap(trade, &user_source, &user_destination, amount_in)?,
+            other => bail!("No executor for {:?} swaps", other),
         };
+        info!("Copying swap of {} {} -> {} on {:?} with {} in, {} expected out at {} bps impact",
+            trade.input_amount, trade.input_token, trade.output_token, trade.dex, amount_in, quote.amount_out, quote.price_impact_bps);
+
+        let native_mint = spl_token::native_mint::id();
+        let mut instructions = Vec::new();
+        if trade.input_token == native_mint {
+            instructions.extend(utils::wrap_sol_instructions(&user, amount_in)?);
+        }
+        instructions.extend(self.create_token_account_instruction(&trade.output_token));
+        instructions.push(swap);
+        if trade.input_token == native_mint || trade.output_token == native_mint {
+            instructions.push(utils::unwrap_sol_instruction(&user)?);
+        }
         
-        let recent_blockhash = self.rpc_client.get_latest_blockhash()?;
+        match self.send_receiving(&instructions, &trade.output_token)? {
+            Some(signature) => info!("Swap executed with signature: {}", signature),
+            None => info!("Paper trade, swap not sent"),
+        }
+        self.record_fill(trade, amount_in, quote.amount_out);
+        
+        Ok(())
+    }
+
+    fn amm_v4_swap(
+        &self,
+        trade: &TradeDetails,
+        user_source: &Pubkey,
+        user_destination: &Pubkey,
+        amount_in: u64,
+    ) -> Result<(Instruction, Quote)> {
+        let mut pool = self.pool_cache.get(&self.rpc_client, &trade.pool_id)?;
+        if !pool.amm.swap_enabled() {
+            // The cached status may be stale.
+            pool = self.pool_cache.refresh(&self.rpc_client, &trade.pool_id)?;
+            if !pool.amm.swap_enabled() {
+                bail!("AMM {} is not accepting swaps (status {})", trade.pool_id, pool.amm.status);
+            }
+        }
+        let pool_mints = [pool.amm.coin_mint, pool.amm.pc_mint];
+        if !pool_mints.contains(&trade.input_token) || !pool_mints.contains(&trade.output_token) {
+            bail!("AMM {} does not trade {} for {}", trade.pool_id, trade.input_token, trade.output_token);
+        }
+        let quote = self.reserves.quote(&trade.pool_id, &trade.input_token, amount_in)?;
+        let min_amount_out = self.min_amount_out(&quote)?;
+
+        Ok((raydium_amm_v4::swap_base_in_instruction(
+            &self.wallet.public_key,
+            &trade.pool_id,
+            &pool.amm,
+            &pool.market,
+            user_source,
+            user_destination,
+            amount_in,
+            min_amount_out,
+        ), quote))
+    }
+
+    fn whirlpool_swap(
+        &self,
+        trade: &TradeDetails,
+        user_source: &Pubkey,
+        user_destination: &Pubkey,
+        amount_in: u64,
+    ) -> Result<(Instruction, Quote)> {
+        let whirlpool = Whirlpool::from_account_data(&self.rpc_client.get_account_data(&trade.pool_id)?)?;
+        let a_to_b = if trade.input_token == whirlpool.token_mint_a {
+            true
+        } else if trade.input_token == whirlpool.token_mint_b {
+            false
+        } else {
+            bail!("Whirlpool {} does not trade {}", trade.pool_id, trade.input_token);
+        };
+        let quote = quote::quote_concentrated(whirlpool.liquidity, whirlpool.sqrt_price, amount_in, a_to_b, whirlpool.fee())?;
+        let min_amount_out = self.min_amount_out(&quote)?;
+
+        // Every tick array slot must be filled; repeat the current array when
+        // the swap's direction runs past the initialized ones.
+        let existing: Vec<Pubkey> = self.existing_accounts(&whirlpool.tick_array_addresses(&trade.pool_id, a_to_b))?
+            .into_iter()
+            .map(|(address, _)| address)
+            .collect();
+        let first = *existing.first()
+            .ok_or_else(|| anyhow!("No initialized tick arrays for whirlpool {}", trade.pool_id))?;
+        let tick_arrays = [0, 1, 2].map(|index| existing.get(index).copied().unwrap_or(first));
+
+        Ok((orca_whirlpool::swap_instruction(
+            &self.wallet.public_key,
+            &trade.pool_id,
+            &whirlpool,
+            tick_arrays,
+            user_source,
+            user_destination,
+            a_to_b,
+            amount_in,
+            min_amount_out,
+        ), quote))
+    }
+
+    fn dlmm_swap(
+        &self,
+        trade: &TradeDetails,
+        user_source: &Pubkey,
+        user_destination: &Pubkey,
+        amount_in: u64,
+    ) -> Result<(Instruction, Quote)> {
+        let lb_pair = LbPair::from_account_data(&self.rpc_client.get_account_data(&trade.pool_id)?)?;
+        let x_to_y = if trade.input_token == lb_pair.token_x_mint {
+            true
+        } else if trade.input_token == lb_pair.token_y_mint {
+            false
+        } else {
+            bail!("DLMM pair {} does not trade {}", trade.pool_id, trade.input_token);
+        };
+        let (bin_arrays, bin_array_data): (Vec<Pubkey>, Vec<Vec<u8>>) = self
+            .existing_accounts(&lb_pair.bin_array_addresses(&trade.pool_id, x_to_y))?
+            .into_iter()
+            .unzip();
+        if bin_arrays.is_empty() {
+            bail!("No initialized bin arrays for DLMM pair {}", trade.pool_id);
+        }
+        let bins = lb_pair.swap_bins(&bin_array_data, x_to_y)?;
+        let quote = quote::quote_bins(&bins, amount_in, x_to_y, lb_pair.fee())?;
+        let min_amount_out = self.min_amount_out(&quote)?;
+
+        Ok((meteora_dlmm::swap_instruction(
+            &self.wallet.public_key,
+            &trade.pool_id,
+            &lb_pair,
+            &bin_arrays,
+            user_source,
+            user_destination,
+            amount_in,
+            min_amount_out,
+        ), quote))
+    }
+
+    /// The addresses that have an account on chain and their data, in order,
+    /// stopping at the first gap since a swap cannot cross it.
+    fn existing_accounts(&self, addresses: &[Pubkey]) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let accounts = self.rpc_client.get_multiple_accounts(addresses)?;
+        Ok(addresses.iter()
+            .zip(accounts)
+            .map_while(|(address, account)| account.map(|account| (*address, account.data)))
+            .collect())
+    }
+
+    /// Copies a bonding-curve buy, quoting against the curve's current
+    /// reserves and guarding the SOL side with the slippage tolerance.
+    fn pump_fun_buy(&self, trade: &TradeDetails, sol_in: u64) -> Result<()> {
+        let mint = trade.output_token;
+        let (curve, fee_recipient) = self.bonding_curve(&mint)?;
+        let user = self.wallet.public_key;
+        let tokens_out = curve.quote_buy(sol_in);
+        let max_sol_cost = self.slippage_up(sol_in);
+        info!("Buying {} of {} for at most {} lamports", tokens_out, mint, max_sol_cost);
+        let mut instructions: Vec<Instruction> = self.create_token_account_instruction(&mint)
+            .into_iter()
+            .collect();
+        instructions.push(pump_fun::buy_instruction(&user, &mint, &fee_recipient, &curve, tokens_out, max_sol_cost));
+
+        match self.send_receiving(&instructions, &mint)? {
+            Some(signature) => info!("pump.fun buy executed with signature: {}", signature),
+            None => info!("Paper trade, pump.fun buy not sent"),
+        }
+        self.record_fill(trade, sol_in, tokens_out);
+        Ok(())
+    }
+
+    fn pump_fun_sell(&self, trade: &TradeDetails, tokens_in: u64) -> Result<()> {
+        let mint = trade.input_token;
+        let (curve, fee_recipient) = self.bonding_curve(&mint)?;
+        let min_sol_output = self.slippage_down(curve.quote_sell(tokens_in));
+        info!("Selling {} of {} for at least {} lamports", tokens_in, mint, min_sol_output);
+        let instructions = vec![pump_fun::sell_instruction(&self.wallet.public_key, &mint, &fee_recipient, &curve, tokens_in, min_sol_output)];
+
+        // The curve may move before the sell lands, so record what it paid
+        // rather than the floor we set.
+        let sol_out = match self.send_transaction(&instructions)? {
+            Some(signature) => {
+                info!("pump.fun sell executed with signature: {}", signature);
+                self.lamports_received(&signature).unwrap_or_else(|e| {
+                    warn!("Cannot read the proceeds of {}, recording the minimum: {:?}", signature, e);
+                    min_sol_output
+                })
+            }
+            None => {
+                info!("Paper trade, pump.fun sell not sent");
+                curve.quote_sell(tokens_in)
+            }
+        };
+        self.record_fill(trade, tokens_in, sol_out);
+        Ok(())
+    }
+
+    /// Lamports one of our confirmed transactions paid into the wallet, with
+    /// its fee added back.
+    fn lamports_received(&self, signature: &Signature) -> Result<u64> {
+        let confirmed = self.rpc_client.get_transaction_with_config(signature, RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(transaction::read_commitment(self.commitment)),
+            max_supported_transaction_version: Some(0),
+        })?;
+        let meta = confirmed.transaction.meta
+            .ok_or_else(|| anyhow!("No status for transaction {}", signature))?;
+        // We pay the fee, so our wallet is the first account.
+        let (Some(pre), Some(post)) = (meta.pre_balances.first(), meta.post_balances.first()) else {
+            bail!("No balances for transaction {}", signature);
+        };
+        let received = *post as i128 - *pre as i128 + meta.fee as i128;
+        u64::try_from(received).map_err(|_| anyhow!("Transaction {} spent {} lamports", signature, -received))
+    }
+
+    /// The curve `mint` trades on while it has not migrated, and the fee
+    /// recipient its trades pay.
+    fn bonding_curve(&self, mint: &Pubkey) -> Result<(BondingCurve, Pubkey)> {
+        let curve_data = self.rpc_client.get_account_data(&pump_fun::bonding_curve_address(mint))?;
+        let curve = BondingCurve::from_account_data(&curve_data)?;
+        if curve.complete {
+            bail!("Bonding curve for {} is complete, the token has migrated", mint);
+        }
+        let global_data = self.rpc_client.get_account_data(&pump_fun::global_address())?;
+        Ok((curve, pump_fun::fee_recipient_from_global(&global_data)?))
+    }
+
+    /// How much of `mint` we can sell. Paper positions never reach the
+    /// chain, so the book is the balance.
+    fn held_amount(&self, mint: &Pubkey) -> u64 {
+        if self.paper_trading {
+            self.positions.get(mint).map(|position| position.amount).unwrap_or(0)
+        } else {
+            self.token_balance(mint)
+        }
+    }
+
+    /// Sells positions whose whole amount, quoted where the position trades
+    /// now, has reached the take profit or stop loss of the copy that
+    /// opened it. Positions that cannot be quoted are left for the next
+    /// check.
+    pub fn check_exits(&self) {
+        for position in self.positions.all() {
+            let value = match self.position_value(&position) {
+                Ok(value) => value,
+                Err(e) => {
+                    debug!("Cannot value position in {}: {:?}", position.mint, e);
+                    continue;
+                }
+            };
+            let Some(exit) = position.exit(value) else {
+                continue;
+            };
+            info!("{:?} on {}: {} worth {} lamports against a cost of {}",
+                exit, position.mint, position.amount, value, position.cost_lamports);
+            if let Err(e) = self.sell_position(&position) {
+                error!("Failed to exit position in {}: {:?}", position.mint, e);
+            }
+        }
+    }
+
+    /// Lamports the whole position would sell for now. Positions rebound to
+    /// an AMM v4 pool after migrating are quoted from the reserve tracker.
+    fn position_value(&self, position: &Position) -> Result<u64> {
+        let sell = Self::position_sale(position);
+        let user = self.wallet.public_key;
+        let user_source = get_associated_token_address(&user, &sell.input_token);
+        let user_destination = get_associated_token_address(&user, &sell.output_token);
+        let quote = match position.dex {
+            Dex::RaydiumAmmV4 => {
+                self.reserves.watch(position.pool_id);
+                self.reserves.quote(&position.pool_id, &position.mint, position.amount)?
+            }
+            Dex::PumpFun => return Ok(self.bonding_curve(&position.mint)?.0.quote_sell(position.amount)),
+            Dex::OrcaWhirlpool => self.whirlpool_swap(&sell, &user_source, &user_destination, position.amount)?.1,
+            Dex::MeteoraDlmm => self.dlmm_swap(&sell, &user_source, &user_destination, position.amount)?.1,
+            other => bail!("No quote for {:?} positions", other),
+        };
+        Ok(quote.amount_out)
+    }
+
+    fn sell_position(&self, position: &Position) -> Result<()> {
+        let sell = Self::position_sale(position);
+        let amount = position.amount.min(self.held_amount(&position.mint));
+        if amount == 0 {
+            bail!("No {} held", position.mint);
+        }
+        match position.dex {
+            Dex::PumpFun => self.pump_fun_sell(&sell, amount),
+            _ => self.swap(&sell, amount),
+        }
+    }
+
+    /// A sell of the whole position for SOL on its venue.
+    fn position_sale(position: &Position) -> TradeDetails {
+        TradeDetails {
+            signature: String::new(),
+            target: Pubkey::default(),
+            dex: position.dex,
+            pool_id: position.pool_id,
+            input_token: position.mint,
+            output_token: spl_token::native_mint::id(),
+            input_amount: position.amount,
+            output_amount: 0,
+            trade_type: TradeType::Swap,
+            inferred: false,
+        }
+    }
+
+    /// Idempotent creation of our token account for `mint`, or nothing when
+    /// the account is known to exist. Wrapped SOL is closed after every swap,
+    /// so it is always created.
+    fn create_token_account_instruction(&self, mint: &Pubkey) -> Option<Instruction> {
+        let user = self.wallet.public_key;
+        let account = get_associated_token_address(&user, mint);
+        if *mint != spl_token::native_mint::id() && self.token_accounts.lock().unwrap().contains(&account) {
+            return None;
+        }
+        Some(create_associated_token_account_idempotent(&user, &user, mint, &spl_token::id()))
+    }
+
+    /// Sends a transaction paying out `mint`. Our token account for it is
+    /// known to exist once the transaction lands, and is no longer assumed
+    /// to when a transaction fails and the account turns out to be gone,
+    /// e.g. after we closed it elsewhere.
+    fn send_receiving(&self, instructions: &[Instruction], mint: &Pubkey) -> Result<Option<Signature>> {
+        let account = get_associated_token_address(&self.wallet.public_key, mint);
+        match self.send_transaction(instructions) {
+            Ok(Some(signature)) => {
+                if *mint != spl_token::native_mint::id() {
+                    self.token_accounts.lock().unwrap().insert(account);
+                }
+                Ok(Some(signature))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                let cached = self.token_accounts.lock().unwrap().contains(&account);
+                if cached && self.is_missing(&account) {
+                    debug!("Forgetting token account {} for {}", account, mint);
+                    self.token_accounts.lock().unwrap().remove(&account);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether the chain says `account` does not exist. A failed lookup says
+    /// nothing either way.
+    fn is_missing(&self, account: &Pubkey) -> bool {
+        matches!(
+            self.rpc_client.get_account_with_commitment(account, self.commitment),
+            Ok(response) if response.value.is_none()
+        )
+    }
+
+    /// Our balance of `mint` in the associated token account, zero if it
+    /// does not exist.
+    fn token_balance(&self, mint: &Pubkey) -> u64 {
+        let account = get_associated_token_address(&self.wallet.public_key, mint);
+        self.rpc_client.get_token_account_balance(&account)
+            .ok()
+            .and_then(|balance| balance.amount.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn slippage_up(&self, amount: u64) -> u64 {
+        quote::max_amount_in(amount, self.slippage_bps)
+    }
+
+    fn slippage_down(&self, amount: u64) -> u64 {
+        quote::min_amount_out(amount, self.slippage_bps)
+    }
+
+    /// The least output to accept for a quoted swap, refusing quotes past the
+    /// price-impact cap.
+    fn min_amount_out(&self, quote: &Quote) -> Result<u64> {
+        quote.check_price_impact(self.max_price_impact_bps)?;
+        Ok(self.slippage_down(quote.amount_out))
+    }
+
+    /// Signs, simulates and sends a transaction, waiting for it to reach the
+    /// execution commitment. In paper mode nothing is signed or sent and no
+    /// signature is returned.
+    fn send_transaction(&self, instructions: &[Instruction]) -> Result<Option<Signature>> {
+        if self.paper_trading {
+            let programs: Vec<String> = instructions.iter()
+                .map(|instruction| instruction.program_id.to_string())
+                .collect();
+            info!("Paper mode, not sending {} instructions: {:?}", instructions.len(), programs);
+            return Ok(None);
+        }
+
+        let (recent_blockhash, _) = self.rpc_client
+            .get_latest_blockhash_with_commitment(self.commitment)?;
         
         let transaction = Transaction::new_signed_with_payer(
-            &[instruction],
+            instructions,
             Some(&self.wallet.public_key),
             &[&self.wallet.keypair],
             recent_blockhash,
         );
+
+        let simulation = self.rpc_client.simulate_transaction_with_config(
+            &transaction,
+            RpcSimulateTransactionConfig {
+                commitment: Some(self.commitment),
+                ..Default::default()
+            },
+        )?;
+        if let Some(err) = simulation.value.err {
+            return Err(anyhow!(
+                "Simulation failed: {:?}, logs: {:?}",
+                err,
+                simulation.value.logs.unwrap_or_default(),
+            ));
+        }
         
         let signature = self.rpc_client.send_and_confirm_transaction(&transaction)?;
-        info!("Swap executed with signature: {}", signature);
-        
-        Ok(())
+        Ok(Some(signature))
     }
     
     fn add_liquidity(&self, _trade: TradeDetails) -> Result<()> {
@@ -63,3 +626,97 @@ impl Trader {
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::DecoderRegistry;
+    use crate::fixtures;
+    use crate::source::recorder::{FrameKind, Protocol, Recorder};
+    use crate::source::replay::ReplaySource;
+    use crate::source::{IngestionSource, SourceEvent};
+    use tokio::sync::mpsc;
+
+    /// A capture of one logsSubscribe notification for `target` and the
+    /// transaction fetched for it: 1 SOL into an AMM v4 pool for 2,588,000
+    /// tokens.
+    fn capture(path: &str, target: Pubkey) {
+        let (pool, coin_vault, pc_vault) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (user_sol, user_token, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let native_mint = spl_token::native_mint::id();
+        let mut accounts: Vec<Pubkey> = (0..18).map(|_| Pubkey::new_unique()).collect();
+        accounts[0] = spl_token::id();
+        accounts[1] = pool;
+        accounts[5] = coin_vault;
+        accounts[6] = pc_vault;
+        accounts[15] = user_sol;
+        accounts[16] = user_token;
+        accounts[17] = target;
+        let mut data = vec![9];
+        data.extend_from_slice(&1_000_000_000u64.to_le_bytes());
+        data.extend_from_slice(&2_500_000u64.to_le_bytes());
+
+        let mut transaction = fixtures::transaction(target, vec![
+            fixtures::instruction(raydium_amm_v4::PROGRAM_ID, accounts, data),
+        ]);
+        fixtures::set_token_balance(&mut transaction, &user_sol, &native_mint, &target, Some(1_000_000_000), Some(0));
+        fixtures::set_token_balance(&mut transaction, &user_token, &mint, &target, Some(0), Some(2_588_000));
+        fixtures::set_token_balance(&mut transaction, &coin_vault, &mint, &target, Some(90_000_000_000), Some(89_997_412_000));
+        fixtures::set_token_balance(&mut transaction, &pc_vault, &native_mint, &target, Some(35_000_000_000), Some(36_000_000_000));
+
+        let endpoint = "wss://rpc.example.com/";
+        let recorder = Recorder::new(path).unwrap();
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Connect, String::new());
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Sent, serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "logsSubscribe",
+            "params": [{ "mentions": [target.to_string()] }, { "commitment": "confirmed" }],
+        }).to_string());
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Received,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": 11 }).to_string());
+        recorder.record(endpoint, Protocol::WebSocket, FrameKind::Received, serde_json::json!({
+            "jsonrpc": "2.0", "method": "logsNotification",
+            "params": {
+                "subscription": 11,
+                "result": { "context": { "slot": transaction.slot }, "value": { "signature": transaction.signature, "err": null, "logs": [] } },
+            },
+        }).to_string());
+        recorder.record("https://rpc.example.com/", Protocol::Rpc, FrameKind::Transaction,
+            serde_json::to_string(&transaction).unwrap());
+    }
+
+    /// Replays the capture and prices a copy of every trade in it.
+    async fn replayed_copies(path: &str, target: Pubkey, settings: &CopySettings) -> Vec<(u64, u64)> {
+        let (events_tx, mut events_rx) = mpsc::channel(10);
+        ReplaySource::new(path.to_string(), 0.0).run(events_tx).await.unwrap();
+        let decoders = DecoderRegistry::with_defaults();
+        let mut copies = Vec::new();
+        while let Some(event) = events_rx.recv().await {
+            if let SourceEvent::Transaction { transaction, .. } = event {
+                for swap in decoders.decode(&transaction, target) {
+                    let trade = swap.into_trade(&transaction.signature);
+                    let (amount_in, _, min_amount_out) = Trader::replay_terms(&trade, settings, 100).unwrap();
+                    copies.push((amount_in, min_amount_out));
+                }
+            }
+        }
+        copies
+    }
+
+    #[tokio::test]
+    async fn replaying_a_capture_twice_gives_the_same_min_out() {
+        let target = Pubkey::new_unique();
+        let path = std::env::temp_dir().join(format!("replay-copies-{}.jsonl", Pubkey::new_unique()));
+        let path = path.to_str().unwrap().to_string();
+        capture(&path, target);
+        let settings = CopySettings { order_size: 0.25, buyin_percentage: 0.5, take_profit: 0.5, stop_loss: 0.2 };
+
+        let first = replayed_copies(&path, target, &settings).await;
+        let second = replayed_copies(&path, target, &settings).await;
+        std::fs::remove_file(&path).unwrap();
+
+        // Half of 1 SOL capped at 0.25 SOL, at the target's price less 1%.
+        assert_eq!(first, vec![(250_000_000, 640_530)]);
+        assert_eq!(first, second);
+    }
+}
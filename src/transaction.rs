@@ -0,0 +1,314 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta,
+    UiInnerInstructions,
+    UiInstruction,
+    UiLoadedAddresses,
+    UiTransactionEncoding,
+    UiTransactionTokenBalance,
+};
+use solana_sdk::message::v0::LoadedAddresses;
+use anyhow::{Result, anyhow};
+use crate::alt::AltResolver;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+const FETCH_ATTEMPTS: usize = 5;
+const FETCH_RETRY_DELAY: Duration = Duration::from_millis(400);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+    /// Instructions this one invoked through CPI, in execution order.
+    pub inner_instructions: Vec<ParsedInstruction>,
+    /// Invocation depth of an inner instruction, where top-level ones are 1.
+    /// Older transactions do not record it.
+    pub stack_height: Option<u32>,
+}
+
+/// One instruction anywhere in a transaction, with the calls it made and the
+/// top-level instruction it ran under.
+#[derive(Debug, Clone, Copy)]
+pub struct Invocation<'a> {
+    /// Position in execution order; the instructions this one invoked follow
+    /// it directly.
+    pub index: usize,
+    pub outer_index: usize,
+    pub instruction: &'a ParsedInstruction,
+    /// Instructions invoked by this one, directly or further down.
+    pub children: &'a [ParsedInstruction],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub account_index: usize,
+    pub mint: Pubkey,
+    pub owner: Option<Pubkey>,
+    pub amount: u64,
+    #[allow(dead_code)]
+    pub decimals: u8,
+}
+
+/// A confirmed transaction with its message resolved to pubkeys and the
+/// balance information from its status meta.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedTransaction {
+    pub signature: String,
+    pub slot: u64,
+    /// The transaction executed but returned an error.
+    pub failed: bool,
+    pub account_keys: Vec<Pubkey>,
+    pub instructions: Vec<ParsedInstruction>,
+    pub log_messages: Vec<String>,
+    /// Lamport balances of `account_keys` before and after, and the fee
+    /// charged to the first signer.
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+    pub fee: u64,
+    pub pre_token_balances: Vec<TokenBalance>,
+    pub post_token_balances: Vec<TokenBalance>,
+}
+
+impl ParsedTransaction {
+    /// Resolves the message against its full account list: the static keys
+    /// followed by the addresses loaded from lookup tables. Those come from
+    /// the status meta when the RPC provides them, otherwise from `resolver`.
+    pub async fn from_encoded(
+        signature: &str,
+        encoded: EncodedConfirmedTransactionWithStatusMeta,
+        resolver: &AltResolver,
+    ) -> Result<Self> {
+        let versioned = encoded.transaction.transaction.decode()
+            .ok_or_else(|| anyhow!("Could not decode transaction {}", signature))?;
+        let meta = encoded.transaction.meta
+            .ok_or_else(|| anyhow!("Transaction {} has no status meta", signature))?;
+
+        let lookups = versioned.message.address_table_lookups().unwrap_or_default();
+        let meta_loaded: Option<UiLoadedAddresses> = meta.loaded_addresses.into();
+        let loaded = match meta_loaded {
+            Some(loaded) if !loaded.writable.is_empty() || !loaded.readonly.is_empty() => LoadedAddresses {
+                writable: parse_pubkeys(&loaded.writable)?,
+                readonly: parse_pubkeys(&loaded.readonly)?,
+            },
+            _ if !lookups.is_empty() => resolver.resolve(lookups).await?,
+            _ => LoadedAddresses::default(),
+        };
+
+        let mut account_keys = versioned.message.static_account_keys().to_vec();
+        account_keys.extend(loaded.writable);
+        account_keys.extend(loaded.readonly);
+
+        let mut resolved: Vec<Option<ParsedInstruction>> = versioned.message.instructions().iter()
+            .map(|ix| resolve_instruction(&account_keys, ix.program_id_index, &ix.accounts, ix.data.clone()))
+            .collect();
+
+        let inner_instructions: Option<Vec<UiInnerInstructions>> = meta.inner_instructions.into();
+        for set in inner_instructions.unwrap_or_default() {
+            let calls = set.instructions.into_iter()
+                .filter_map(|ix| match ix {
+                    UiInstruction::Compiled(ix) => resolve_instruction(
+                        &account_keys,
+                        ix.program_id_index,
+                        &ix.accounts,
+                        bs58::decode(&ix.data).into_vec().ok()?,
+                    ).map(|call| call.at_height(ix.stack_height)),
+                    UiInstruction::Parsed(_) => None,
+                })
+                .collect();
+            attach_inner_instructions(&mut resolved, set.index as usize, calls);
+        }
+        let instructions = collect_resolved(signature, resolved);
+
+        let log_messages: Option<Vec<String>> = meta.log_messages.into();
+        let pre_token_balances: Option<Vec<UiTransactionTokenBalance>> = meta.pre_token_balances.into();
+        let post_token_balances: Option<Vec<UiTransactionTokenBalance>> = meta.post_token_balances.into();
+
+        Ok(ParsedTransaction {
+            signature: signature.to_string(),
+            slot: encoded.slot,
+            failed: meta.err.is_some(),
+            account_keys,
+            instructions,
+            log_messages: log_messages.unwrap_or_default(),
+            pre_balances: meta.pre_balances,
+            post_balances: meta.post_balances,
+            fee: meta.fee,
+            pre_token_balances: parse_token_balances(pre_token_balances.unwrap_or_default()),
+            post_token_balances: parse_token_balances(post_token_balances.unwrap_or_default()),
+        })
+    }
+
+    /// Every instruction in execution order: each top-level instruction
+    /// followed by the calls it made. Without stack heights the nesting of
+    /// inner instructions is unknown, so only top-level ones get children.
+    pub fn invocations(&self) -> Vec<Invocation<'_>> {
+        let mut invocations = Vec::new();
+        for (outer_index, outer) in self.instructions.iter().enumerate() {
+            let inner = &outer.inner_instructions;
+            invocations.push(Invocation { index: invocations.len(), outer_index, instruction: outer, children: inner });
+            for (position, instruction) in inner.iter().enumerate() {
+                let children = match instruction.stack_height {
+                    Some(height) => {
+                        let rest = &inner[position + 1..];
+                        let end = rest.iter()
+                            .position(|next| next.stack_height.is_none_or(|next_height| next_height <= height))
+                            .unwrap_or(rest.len());
+                        &rest[..end]
+                    }
+                    None => &[],
+                };
+                invocations.push(Invocation { index: invocations.len(), outer_index, instruction, children });
+            }
+        }
+        invocations
+    }
+
+    pub fn account_index(&self, account: &Pubkey) -> Option<usize> {
+        self.account_keys.iter().position(|key| key == account)
+    }
+
+    /// Mint of a token account, taken from whichever balance snapshot lists it.
+    pub fn token_mint(&self, account: &Pubkey) -> Option<Pubkey> {
+        let index = self.account_index(account)?;
+        self.pre_token_balances.iter()
+            .chain(self.post_token_balances.iter())
+            .find(|balance| balance.account_index == index)
+            .map(|balance| balance.mint)
+    }
+
+    /// Signed change of an account's lamports over the transaction.
+    pub fn lamport_change(&self, account: &Pubkey) -> Option<i128> {
+        let index = self.account_index(account)?;
+        let pre = *self.pre_balances.get(index)? as i128;
+        let post = *self.post_balances.get(index)? as i128;
+        Some(post - pre)
+    }
+
+    /// Signed change of a token account's balance over the transaction.
+    /// Accounts created or closed inside the transaction count as zero on
+    /// the missing side.
+    pub fn token_balance_change(&self, account: &Pubkey) -> Option<i128> {
+        let index = self.account_index(account)?;
+        let pre = self.pre_token_balances.iter().find(|balance| balance.account_index == index);
+        let post = self.post_token_balances.iter().find(|balance| balance.account_index == index);
+        if pre.is_none() && post.is_none() {
+            return None;
+        }
+        let pre_amount = pre.map(|balance| balance.amount).unwrap_or(0) as i128;
+        let post_amount = post.map(|balance| balance.amount).unwrap_or(0) as i128;
+        Some(post_amount - pre_amount)
+    }
+}
+
+pub fn resolve_instruction(
+    account_keys: &[Pubkey],
+    program_id_index: u8,
+    accounts: &[u8],
+    data: Vec<u8>,
+) -> Option<ParsedInstruction> {
+    let program_id = *account_keys.get(program_id_index as usize)?;
+    let accounts = accounts.iter()
+        .map(|index| account_keys.get(*index as usize).copied())
+        .collect::<Option<Vec<_>>>()?;
+    Some(ParsedInstruction { program_id, accounts, data, inner_instructions: Vec::new(), stack_height: None })
+}
+
+impl ParsedInstruction {
+    pub fn at_height(mut self, stack_height: Option<u32>) -> Self {
+        self.stack_height = stack_height;
+        self
+    }
+}
+
+pub fn attach_inner_instructions(
+    instructions: &mut [Option<ParsedInstruction>],
+    index: usize,
+    calls: Vec<ParsedInstruction>,
+) {
+    if let Some(Some(outer)) = instructions.get_mut(index) {
+        outer.inner_instructions = calls;
+    }
+}
+
+/// Drops the top-level instructions that could not be resolved, along with
+/// their inner instructions.
+pub fn collect_resolved(signature: &str, instructions: Vec<Option<ParsedInstruction>>) -> Vec<ParsedInstruction> {
+    instructions.into_iter()
+        .enumerate()
+        .filter_map(|(index, instruction)| {
+            if instruction.is_none() {
+                debug!("Skipping instruction {} of {}: account index out of range", index, signature);
+            }
+            instruction
+        })
+        .collect()
+}
+
+fn parse_pubkeys(keys: &[String]) -> Result<Vec<Pubkey>> {
+    keys.iter()
+        .map(|key| Pubkey::from_str(key).map_err(|e| anyhow!("Invalid pubkey {}: {}", key, e)))
+        .collect()
+}
+
+fn parse_token_balances(balances: Vec<UiTransactionTokenBalance>) -> Vec<TokenBalance> {
+    balances.into_iter()
+        .filter_map(|balance| {
+            let owner: Option<String> = balance.owner.into();
+            Some(TokenBalance {
+                account_index: balance.account_index as usize,
+                mint: Pubkey::from_str(&balance.mint).ok()?,
+                owner: owner.and_then(|owner| Pubkey::from_str(&owner).ok()),
+                amount: balance.ui_token_amount.amount.parse().ok()?,
+                decimals: balance.ui_token_amount.decimals,
+            })
+        })
+        .collect()
+}
+
+/// getTransaction and getSignaturesForAddress do not serve `processed`, so
+/// reads fall back to `confirmed` when detection runs at `processed`.
+pub fn read_commitment(commitment: CommitmentConfig) -> CommitmentConfig {
+    if commitment.is_at_least_confirmed() {
+        commitment
+    } else {
+        CommitmentConfig::confirmed()
+    }
+}
+
+/// Fetches a transaction by signature. A signature seen on a subscription can
+/// take a moment to become queryable, so missing transactions are retried.
+pub async fn fetch_transaction(
+    rpc_client: &RpcClient,
+    resolver: &AltResolver,
+    signature: &str,
+    commitment: CommitmentConfig,
+) -> Result<ParsedTransaction> {
+    let sig = Signature::from_str(signature)?;
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(read_commitment(commitment)),
+        max_supported_transaction_version: Some(0),
+    };
+
+    let mut last_error = None;
+    for attempt in 1..=FETCH_ATTEMPTS {
+        match rpc_client.get_transaction_with_config(&sig, config).await {
+            Ok(encoded) => return ParsedTransaction::from_encoded(signature, encoded, resolver).await,
+            Err(e) => {
+                debug!("getTransaction attempt {} for {} failed: {}", attempt, signature, e);
+                last_error = Some(e);
+                tokio::time::sleep(FETCH_RETRY_DELAY).await;
+            }
+        }
+    }
+
+    Err(anyhow!("Failed to fetch transaction {}: {:?}", signature, last_error))
+}
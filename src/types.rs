@@ -9,14 +9,34 @@ pub enum TradeType {
     RemoveLiquidity,
 }
 
+/// Venue a trade was decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dex {
+    RaydiumAmmV4,
+    RaydiumCpmm,
+    RaydiumClmm,
+    /// A Jupiter route, collapsed to its net input and output.
+    Jupiter,
+    /// A pump.fun bonding curve; the pool is the curve account.
+    PumpFun,
+    OrcaWhirlpool,
+    MeteoraDlmm,
+    /// No registered decoder recognised the transaction.
+    Unclassified,
+}
+
 #[derive(Debug, Clone)]
 pub struct TradeDetails {
+    pub signature: String,
+    /// Target wallet whose transaction produced this trade.
+    pub target: Pubkey,
+    pub dex: Dex,
     pub pool_id: Pubkey,
-    #[allow(dead_code)]
     pub input_token: Pubkey,
-    #[allow(dead_code)]
     pub output_token: Pubkey,
     pub input_amount: u64,
     pub output_amount: u64,
     pub trade_type: TradeType,
+    /// Inferred from balance changes; the venue is unknown.
+    pub inferred: bool,
 }
\ No newline at end of file
@@ -4,7 +4,6 @@ use solana_sdk::pubkey::Pubkey;
 use anyhow::{Result, anyhow};
 use solana_client::rpc_client::RpcClient;
 use colored::*;
-use bs58;
 
 pub struct Wallet {
     pub keypair: Keypair,